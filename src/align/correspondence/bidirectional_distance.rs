@@ -2,7 +2,7 @@ use crate::correspondence::{
     CorrespondenceEstimator, Correspondences, get_ordered_correspondences_and_distances_nn,
 };
 use crate::filter_points::PointFilter;
-use crate::{PointCloud, ToPointCloud, kd_tree_of_point_cloud};
+use crate::{IcpError, PointCloud, ToPointCloud, kd_tree_of_point_cloud, validate_point_cloud};
 use kdtree::KdTree;
 use nalgebra::{RealField, Scalar};
 use num_traits::{Float, One, Zero};
@@ -11,18 +11,19 @@ use num_traits::{Float, One, Zero};
 /// Bidirectional Distance algorithm.
 ///
 /// See this [paper from Dong et al.](https://doi.org/10.1049/iet-cvi.2016.0058)
-pub struct BidirectionalDistance<T>
+pub struct BidirectionalDistance<T, const D: usize = 3>
 where
     T: Scalar + RealField + Float + One + Zero,
 {
     target_tree: KdTree<T, usize, Vec<T>>,
-    target_cloud: PointCloud<T, 3>,
+    target_cloud: PointCloud<T, D>,
 }
 
-impl<'a, T, PC> CorrespondenceEstimator<'a, T, PC, 3> for BidirectionalDistance<T>
+impl<'a, T, PC, const D: usize> CorrespondenceEstimator<'a, T, PC, D>
+    for BidirectionalDistance<T, D>
 where
     T: Scalar + RealField + Float + One + Zero,
-    PC: ToPointCloud<T, 3>,
+    PC: ToPointCloud<T, D>,
 {
     fn new(target: &'a PC) -> Self {
         let target_cloud = target.to_point_cloud();
@@ -34,13 +35,13 @@ where
     }
 
     fn find_correspondences<'b, 't, FP>(
-        &'t self,
-        alignee: &'b PointCloud<T, 3>,
+        &'t mut self,
+        alignee: &'b PointCloud<T, D>,
         _target: &'b PC,
         filter_points: &mut FP,
-    ) -> Correspondences<'b, 't, T, 3>
+    ) -> Correspondences<'b, 't, T, D>
     where
-        FP: PointFilter<T, 3>,
+        FP: PointFilter<T, D>,
         'b: 't,
     {
         let (alignee_point_cloud, corresponding_target_point_cloud, alignee_to_target_distances) =
@@ -68,4 +69,8 @@ where
             target_to_alignee_distances,
         }
     }
+
+    fn validate_target(&self) -> Result<(), IcpError> {
+        validate_point_cloud(&self.target_cloud)
+    }
 }
@@ -3,17 +3,20 @@ use crate::filter_points::PointFilter;
 use crate::{MaskedPointCloud, PointCloud, PointCloudPoint};
 use nalgebra::{Point3, RealField, Scalar, point, vector};
 use num_traits::{Float, One, Zero};
-use std::cell::{Cell, RefCell};
 
 /// Computes the correspondences between the alignee point map and a cylinder of a given radius.
 /// The mantle and the top of the cylinder are considered for correspondences between the alignee
 /// points and the cylinder.
+///
+/// The cylinder lies on the z-axis with its top at `z = 0` and its radius is updated in every iteration.
+/// For a cylinder with an arbitrary pose and both caps use [`PrimitiveSurface`](super::PrimitiveSurface)
+/// with [`primitives::Cylinder`](super::primitives::Cylinder).
 pub struct Cylinder<T>
 where
     T: Scalar + RealField + Float + One + Zero + Copy,
 {
-    radius: Cell<T>,
-    point_cloud: RefCell<PointCloud<T, 3>>,
+    radius: T,
+    point_cloud: PointCloud<T, 3>,
 }
 
 type IntersectionAndDistance<T> = (PointCloudPoint<T, 3>, T);
@@ -32,7 +35,7 @@ where
         let pos_2d = pos.coords.fixed_rows::<2>(0);
         let len = pos_2d.norm();
 
-        let radius = self.radius.get();
+        let radius = self.radius;
         let mantle_distance = len - radius;
 
         if mantle_distance < -pos.z {
@@ -63,13 +66,13 @@ where
 {
     fn new(radius: &T) -> Self {
         Cylinder {
-            radius: Cell::new(*radius),
-            point_cloud: RefCell::new(vec![]),
+            radius: *radius,
+            point_cloud: vec![],
         }
     }
 
     fn find_correspondences<'b, 't, FP>(
-        &'t self,
+        &'t mut self,
         alignee: &'b PointCloud<T, 3>,
         _target: &'b T,
         filter_points: &mut FP,
@@ -78,8 +81,7 @@ where
         FP: PointFilter<T, 3>,
         'b: 't,
     {
-        let mut point_cloud = self.point_cloud.borrow_mut();
-        point_cloud.clear();
+        let mut point_cloud = Vec::with_capacity(alignee.len());
 
         let mut distances = Vec::with_capacity(alignee.len());
 
@@ -113,10 +115,10 @@ where
         let mut alignee_cloud = MaskedPointCloud::new(alignee);
         alignee_cloud.add_mask(&mask);
 
-        let target_cloud =
-            unsafe { MaskedPointCloud::new(self.point_cloud.as_ptr().as_ref().unwrap()) };
+        self.radius += sum / count;
+        self.point_cloud = point_cloud;
 
-        self.radius.set(self.radius.get() + sum / count);
+        let target_cloud = MaskedPointCloud::new(&self.point_cloud);
 
         Correspondences::from_simple_one_way_correspondences(
             alignee_cloud,
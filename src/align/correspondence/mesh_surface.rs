@@ -0,0 +1,177 @@
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{MaskedPointCloud, PointCloud, PointCloudPoint, ToTriangles, TriangleBvh};
+use nalgebra::{RealField, Scalar};
+use num_traits::{Float, One, Zero};
+
+/// Computes the correspondences between the alignee and the surface of a triangle mesh.
+///
+/// For every alignee point the closest point on the actual triangle surface is found instead of
+/// the closest vertex. The corresponding target points carry the face normal of the triangle they lie on.
+/// This gives much better correspondences than [`super::NearestNeighbor`] for coarse meshes with big triangles
+/// like CAD models.
+///
+/// The target can be anything that implements [`ToTriangles`], e.g. a `Vec<Triangle<T>>` or,
+/// with the `modelz` feature enabled, a `Model3D`. Degenerate triangles are ignored.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud, Triangle};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, MeshSurface};
+/// # use modern_icp::transform_estimation::point_to_plane_lls;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// # use nalgebra::Point3;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let target_mesh = vec![
+///     Triangle::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)),
+///     Triangle::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0), Point3::new(0.0, 0.0, 1.0)),
+/// ];
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(MeshSurface::new(&target_mesh))
+///     .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_mesh);
+/// ```
+pub struct MeshSurface<T>
+where
+    T: Scalar + RealField + Float + One + Zero + Copy,
+{
+    bvh: TriangleBvh<T>,
+    point_cloud: PointCloud<T, 3>,
+}
+
+impl<'a, T, TG> CorrespondenceEstimator<'a, T, TG, 3> for MeshSurface<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+    TG: ToTriangles<T>,
+{
+    fn new(target: &'a TG) -> Self {
+        let triangles = target
+            .to_triangles()
+            .into_iter()
+            .filter(|triangle| triangle.normal().is_some())
+            .collect();
+
+        MeshSurface {
+            bvh: TriangleBvh::new(triangles),
+            point_cloud: vec![],
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, 3>,
+        _target: &'b TG,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, 3>
+    where
+        FP: PointFilter<T, 3>,
+        'b: 't,
+    {
+        self.point_cloud.clear();
+
+        let mut distances = Vec::with_capacity(alignee.len());
+
+        let mut mask = vec![false; alignee.len()];
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            let Some((closest, triangle, distance)) = self.bvh.closest_point(&p.pos) else {
+                continue;
+            };
+
+            let mut point = PointCloudPoint::from_pos(closest);
+            point.norm = triangle.normal();
+            self.point_cloud.push(point);
+            distances.push(distance);
+
+            mask[i] = true;
+        }
+
+        let mut alignee_cloud = MaskedPointCloud::new(alignee);
+        alignee_cloud.add_mask(&mask);
+
+        let target_cloud = MaskedPointCloud::new(&self.point_cloud);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_cloud,
+            alignee,
+            target_cloud,
+            distances,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Icp;
+    use crate::Triangle;
+    use crate::convergence::same_squared_distance_error;
+    use crate::test_utils::{assert_isometry_eq, room_corner, transformed};
+    use crate::transform_estimation::point_to_plane_lls;
+    use nalgebra::{Isometry3, Point3, Vector3};
+
+    /// The three faces of [`room_corner`] with two big triangles each.
+    fn room_corner_mesh(size: f32) -> Vec<Triangle<f32>> {
+        let face = |corner: fn(f32, f32) -> Point3<f32>| {
+            [
+                Triangle::new(corner(0.0, 0.0), corner(size, 0.0), corner(0.0, size)),
+                Triangle::new(corner(size, 0.0), corner(size, size), corner(0.0, size)),
+            ]
+        };
+
+        [
+            face(|u, v| Point3::new(u, v, 0.0)),
+            face(|u, v| Point3::new(u, 0.0, v)),
+            face(|u, v| Point3::new(0.0, u, v)),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn corresponding_points_lie_on_triangles() {
+        let mesh = room_corner_mesh(2.0);
+        let alignee = vec![PointCloudPoint::from_pos(Point3::new(0.7, 1.3, 0.2))];
+
+        let mut estimator = MeshSurface::new(&mesh);
+        let correspondences =
+            estimator.find_correspondences(&alignee, &mesh, &mut crate::filter_points::AcceptAll);
+
+        let target: Vec<_> = correspondences
+            .corresponding_target_point_cloud
+            .iter()
+            .collect();
+        assert_eq!(target.len(), 1);
+        assert!((target[0].pos - Point3::new(0.7, 1.3, 0.0)).norm() < 1e-6);
+        assert_eq!(target[0].norm, Some(Vector3::z()));
+        assert!((correspondences.alignee_to_target_distances[0] - 0.04).abs() < 1e-6);
+    }
+
+    #[test]
+    fn registers_points_to_coarse_mesh() {
+        let mesh = room_corner_mesh(2.0);
+
+        let transform = Isometry3::new(
+            Vector3::new(0.1, -0.05, 0.08),
+            Vector3::new(0.03, -0.02, 0.04),
+        );
+        let alignee = transformed(&room_corner(2.0, 0.25), &transform);
+
+        let (result, _) = Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(MeshSurface::new(&mesh))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-12))
+            .estimate_transform(alignee, &mesh);
+
+        assert_isometry_eq(&result, &transform.inverse(), 1e-3);
+    }
+}
@@ -1,15 +1,30 @@
 mod bidirectional_distance;
 mod cylinder;
+mod mesh_surface;
+mod ndt;
 mod nearest_neighbor;
-
-use crate::{MaskedPointCloud, PointCloud, filter_points::PointFilter};
+mod normal_shooting;
+mod primitive_surface;
+pub mod primitives;
+mod projective;
+mod scan_segments;
+mod soft_nearest_neighbors;
+
+use crate::{IcpError, MaskedPointCloud, PointCloud, filter_points::PointFilter};
 pub use bidirectional_distance::*;
 pub use cylinder::*;
 use kdtree::KdTree;
 use kdtree::distance::squared_euclidean;
+pub use mesh_surface::*;
 use nalgebra::*;
+pub use ndt::*;
 pub use nearest_neighbor::*;
+pub use normal_shooting::*;
 use num_traits::{Float, One, Zero};
+pub use primitive_surface::*;
+pub use projective::*;
+pub use scan_segments::*;
+pub use soft_nearest_neighbors::*;
 use std::fmt::Debug;
 
 /// Contains all the correspondences found by the correspondence estimator.
@@ -91,8 +106,11 @@ where
     /// It takes a reference to a `PointCloudPoint` and returns a boolean which is `true` if the point should be included.
     ///
     /// See the [`Correspondences`] return type documentation for more information.
+    ///
+    /// Estimators that generate the corresponding target points, like [`MeshSurface`], keep them in `self`.
+    /// The returned correspondences borrow them until the next call.
     fn find_correspondences<'b, 't, FP>(
-        &'t self,
+        &'t mut self,
         alignee: &'b PointCloud<T, D>,
         target: &'b TG,
         filter_points: &mut FP,
@@ -100,6 +118,21 @@ where
     where
         FP: PointFilter<T, D>,
         'b: 't;
+
+    /// Called by the [`crate::Icp`] before the first iteration of every run.
+    ///
+    /// Estimators that change from iteration to iteration, like the annealing of [`SoftNearestNeighbors`],
+    /// start over here. Does nothing by default.
+    fn reset(&mut self) {}
+
+    /// Checks the target this estimator was built from.
+    ///
+    /// Called by [`crate::Icp::try_estimate_transform`] before the first iteration. Estimators that search a KD-Tree
+    /// of the target return the error of [`crate::validate_point_cloud`] because points with NaN or infinite
+    /// coordinates can't be added to the tree. Accepts every target by default.
+    fn validate_target(&self) -> Result<(), IcpError> {
+        Ok(())
+    }
 }
 
 /// For every point in `data_set_x` finds the nearest point in `data_set_y` using the KD-Tree `tree`.
@@ -107,19 +140,19 @@ where
 /// and another masked point_cloud referencing `data_set_y` that correspond to the points in the first
 /// returned masked point_cloud.
 /// Finally it returns a list of the distances between the points of the two returned point_clouds.
-pub fn get_ordered_correspondences_and_distances_nn<'a, 't, T, FP>(
+pub fn get_ordered_correspondences_and_distances_nn<'a, 't, T, FP, const D: usize>(
     tree: &KdTree<T, usize, Vec<T>>,
-    data_set_x: &'a PointCloud<T, 3>,
-    data_set_y: &'t PointCloud<T, 3>,
+    data_set_x: &'a PointCloud<T, D>,
+    data_set_y: &'t PointCloud<T, D>,
     filter_points: &mut FP,
 ) -> (
-    MaskedPointCloud<'a, T, 3>,
-    MaskedPointCloud<'t, T, 3>,
+    MaskedPointCloud<'a, T, D>,
+    MaskedPointCloud<'t, T, D>,
     Vec<T>,
 )
 where
     T: Scalar + RealField + Float + One + Zero,
-    FP: PointFilter<T, 3>,
+    FP: PointFilter<T, D>,
 {
     let mut distances = vec![];
     let mut ordered_indices = vec![];
@@ -131,9 +164,14 @@ where
         .enumerate()
         .filter(|(_, p)| filter_points.filter(*p))
     {
-        let (distance, idx) = tree
+        // points with NaN or infinite coordinates don't have a nearest neighbor
+        let Some((distance, idx)) = tree
             .nearest(p.pos.coords.as_slice(), 1, &squared_euclidean)
-            .unwrap()[0];
+            .ok()
+            .and_then(|nearest| nearest.first().copied())
+        else {
+            continue;
+        };
 
         ordered_indices.push(*idx);
         distances.push(distance);
@@ -0,0 +1,293 @@
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{MaskedPointCloud, PointCloud, PointCloudPoint, ToPointCloud};
+use nalgebra::{Matrix3, Point3, RealField, Scalar, SymmetricEigen, Vector3};
+use num_traits::{Float, One, Zero};
+use std::collections::HashMap;
+
+/// A voxel of an [`NdtGrid`] that holds the normal distribution of the target points inside of it.
+#[derive(Clone, Debug)]
+pub struct NdtCell<T: Scalar + RealField + Copy> {
+    pub mean: Point3<T>,
+    pub covariance: Matrix3<T>,
+    pub inverse_covariance: Matrix3<T>,
+}
+
+/// Voxel grid of normal distributions used as the target for Normal Distributions Transform (NDT) registration.
+///
+/// The target point cloud is divided into cubic voxels of the size `voxel_size`. For every voxel that contains
+/// at least `min_points_per_cell` points the mean and covariance of these points are computed.
+/// Very flat distributions are regularized by clamping the eigenvalues of the covariance to at least `0.01` times
+/// the largest eigenvalue.
+///
+/// Use this with the [`Ndt`] correspondence estimator and [`crate::transform_estimation::ndt::build_isometry_estimator`].
+///
+/// See this [thesis from Magnusson](https://urn.kb.se/resolve?urn=urn:nbn:se:oru:diva-8458)
+#[derive(Clone, Debug)]
+pub struct NdtGrid<T: Scalar + RealField + Copy> {
+    voxel_size: T,
+    cells: Vec<NdtCell<T>>,
+    cell_indices: HashMap<[i64; 3], usize>,
+    means: PointCloud<T, 3>,
+}
+
+impl<T> NdtGrid<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    pub fn new(
+        target: &impl ToPointCloud<T, 3>,
+        voxel_size: T,
+        min_points_per_cell: usize,
+    ) -> Self {
+        let target = target.to_point_cloud();
+
+        let mut voxels: HashMap<[i64; 3], Vec<Point3<T>>> = HashMap::new();
+        for p in &target {
+            if let Some(key) = Self::key(voxel_size, &p.pos) {
+                voxels.entry(key).or_default().push(p.pos);
+            }
+        }
+
+        let mut keys: Vec<_> = voxels.keys().copied().collect();
+        keys.sort_unstable();
+
+        let mut cells = vec![];
+        let mut cell_indices = HashMap::new();
+        let mut means = vec![];
+
+        let min_points = min_points_per_cell.max(4);
+        let min_eigenvalue_ratio: T = nalgebra::convert(0.01);
+
+        for key in keys {
+            let points = &voxels[&key];
+            if points.len() < min_points {
+                continue;
+            }
+
+            let count = T::from_usize(points.len()).unwrap();
+            let mean = points
+                .iter()
+                .fold(Vector3::zeros(), |sum, p| sum + p.coords)
+                / count;
+
+            let covariance = points.iter().fold(Matrix3::zeros(), |sum, p| {
+                let d = p.coords - mean;
+                sum + d * d.transpose()
+            }) / (count - T::one());
+
+            let mut eigen = SymmetricEigen::new(covariance);
+            let max_eigenvalue = eigen.eigenvalues.max();
+            if max_eigenvalue <= T::zero() {
+                continue;
+            }
+            let min_eigenvalue = max_eigenvalue * min_eigenvalue_ratio;
+            for v in eigen.eigenvalues.iter_mut() {
+                *v = Float::max(*v, min_eigenvalue);
+            }
+
+            let normal = eigen
+                .eigenvectors
+                .column(eigen.eigenvalues.imin())
+                .into_owned();
+
+            let covariance = eigen.recompose();
+            let Some(inverse_covariance) = covariance.try_inverse() else {
+                continue;
+            };
+
+            cell_indices.insert(key, cells.len());
+            means.push(PointCloudPoint::from_pos_norm(Point3::from(mean), normal));
+            cells.push(NdtCell {
+                mean: Point3::from(mean),
+                covariance,
+                inverse_covariance,
+            });
+        }
+
+        NdtGrid {
+            voxel_size,
+            cells,
+            cell_indices,
+            means,
+        }
+    }
+
+    pub fn voxel_size(&self) -> T {
+        self.voxel_size
+    }
+
+    /// The normal distributions of all occupied voxels.
+    pub fn cells(&self) -> &[NdtCell<T>] {
+        &self.cells
+    }
+
+    /// Point cloud of the means of all cells, in the same order as [`NdtGrid::cells`].
+    /// The normal of each point is the direction of least variance of its cell.
+    pub fn means(&self) -> &PointCloud<T, 3> {
+        &self.means
+    }
+
+    /// Returns the index of the cell containing `point` or one of its 26 neighbors that explains `point`
+    /// best (lowest Mahalanobis distance) together with that squared Mahalanobis distance.
+    pub fn best_cell(&self, point: &Point3<T>) -> Option<(usize, T)> {
+        let [x, y, z] = Self::key(self.voxel_size, point)?;
+
+        let mut best: Option<(usize, T)> = None;
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(&idx) = self.cell_indices.get(&[x + dx, y + dy, z + dz]) else {
+                        continue;
+                    };
+
+                    let cell = &self.cells[idx];
+                    let d = point - cell.mean;
+                    let mahalanobis = d.dot(&(cell.inverse_covariance * d));
+
+                    if best.is_none_or(|(_, best_dist)| mahalanobis < best_dist) {
+                        best = Some((idx, mahalanobis));
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    fn key(voxel_size: T, point: &Point3<T>) -> Option<[i64; 3]> {
+        let mut key = [0; 3];
+        for (k, c) in key.iter_mut().zip(point.iter()) {
+            let c: f64 = Float::floor(*c / voxel_size).to_subset()?;
+            if !c.is_finite() {
+                return None;
+            }
+            *k = c as i64;
+        }
+        Some(key)
+    }
+}
+
+/// Computes the correspondences between the alignee and the normal distributions of an [`NdtGrid`].
+///
+/// Every alignee point corresponds to the cell in its neighborhood that explains it best. The corresponding
+/// target points are the means of the cells (see [`NdtGrid::means`]) and the distances are the
+/// squared Mahalanobis distances. Alignee points that are not close to any occupied cell get no correspondence.
+///
+/// Use this together with [`crate::transform_estimation::ndt::build_isometry_estimator`].
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, Ndt, NdtGrid};
+/// # use modern_icp::transform_estimation::ndt;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let target_grid = NdtGrid::new(&target_cloud, 1.0, 6);
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(Ndt::new(&target_grid))
+///     .estimate_step_transform(ndt::build_isometry_estimator(&target_grid))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_grid);
+/// ```
+pub struct Ndt<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    grid: NdtGrid<T>,
+}
+
+impl<'a, T> CorrespondenceEstimator<'a, T, NdtGrid<T>, 3> for Ndt<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    fn new(target: &'a NdtGrid<T>) -> Self {
+        Ndt {
+            grid: target.clone(),
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, 3>,
+        _target: &'b NdtGrid<T>,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, 3>
+    where
+        FP: PointFilter<T, 3>,
+        'b: 't,
+    {
+        let mut distances = vec![];
+        let mut ordered_indices = vec![];
+
+        let mut mask = vec![false; alignee.len()];
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            let Some((idx, distance)) = self.grid.best_cell(&p.pos) else {
+                continue;
+            };
+
+            ordered_indices.push(idx);
+            distances.push(distance);
+            mask[i] = true;
+        }
+
+        let mut alignee_point_cloud = MaskedPointCloud::new(alignee);
+        alignee_point_cloud.add_mask(&mask);
+
+        let mut corresponding_target_point_cloud = MaskedPointCloud::new(&self.grid.means);
+        corresponding_target_point_cloud.add_order(&ordered_indices);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_point_cloud,
+            alignee,
+            corresponding_target_point_cloud,
+            distances,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regularizes_collinear_cells() {
+        let line: PointCloud<f32, 3> = (0..40)
+            .map(|i| PointCloudPoint::from_pos(Point3::new(i as f32 * 0.1, 0.5, 0.5)))
+            .collect();
+
+        let grid = NdtGrid::new(&line, 1.0, 6);
+
+        assert_eq!(grid.cells().len(), 4);
+        for cell in grid.cells() {
+            assert!(cell.inverse_covariance.iter().all(|c| c.is_finite()));
+        }
+
+        let (idx, distance) = grid.best_cell(&Point3::new(1.55, 0.5, 0.5)).unwrap();
+        assert!((grid.cells()[idx].mean.x - 1.45).abs() < 1e-4);
+        assert!(distance < 1.0);
+    }
+
+    #[test]
+    fn skips_sparse_cells() {
+        let points: PointCloud<f32, 3> = (0..3)
+            .map(|i| PointCloudPoint::from_pos(Point3::new(i as f32 * 0.1, 0.0, 0.0)))
+            .collect();
+
+        let grid = NdtGrid::new(&points, 1.0, 6);
+
+        assert!(grid.cells().is_empty());
+        assert!(grid.best_cell(&Point3::origin()).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{
+    IcpError, MaskedPointCloud, PointCloud, ToPointCloud, kd_tree_of_point_cloud,
+    validate_point_cloud,
+};
+use kdtree::KdTree;
+use kdtree::distance::squared_euclidean;
+use nalgebra::{RealField, Scalar};
+use num_traits::{Float, One, Zero};
+
+/// Computes the correspondences between the alignee and the target by shooting a ray from every
+/// alignee point along its normal and picking the target point that is closest to that ray.
+///
+/// The candidates for every alignee point are its `k_neighbors` nearest neighbors in the target
+/// (defaults to `10`). The ray is treated as a line, so the orientation of the normals doesn't matter.
+/// Alignee points without a normal fall back to their nearest neighbor.
+///
+/// This works better than [`super::NearestNeighbor`] for thin, curved surfaces where the nearest
+/// neighbor often lies on the wrong side of the surface.
+///
+/// See this [implementation of the algorithm from PointCloudLibrary](https://github.com/PointCloudLibrary/pcl/blob/master/registration/include/pcl/registration/impl/correspondence_estimation_normal_shooting.hpp)
+pub struct NormalShooting<T, const D: usize = 3>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    tree: KdTree<T, usize, Vec<T>>,
+    target_cloud: PointCloud<T, D>,
+    k_neighbors: usize,
+}
+
+impl<T, const D: usize> NormalShooting<T, D>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    /// Sets the number of nearest target points that are considered as candidates for every alignee point.
+    ///
+    /// Defaults to `10`.
+    pub fn k_neighbors(self, k_neighbors: usize) -> Self {
+        Self {
+            k_neighbors: k_neighbors.max(1),
+            ..self
+        }
+    }
+}
+
+impl<'a, T, PC, const D: usize> CorrespondenceEstimator<'a, T, PC, D> for NormalShooting<T, D>
+where
+    T: Scalar + RealField + Float + One + Zero,
+    PC: ToPointCloud<T, D>,
+{
+    fn new(target: &'a PC) -> Self {
+        let target_cloud = target.to_point_cloud();
+
+        NormalShooting {
+            tree: kd_tree_of_point_cloud(&target_cloud),
+            target_cloud,
+            k_neighbors: 10,
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, D>,
+        _target: &'b PC,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, D>
+    where
+        FP: PointFilter<T, D>,
+        'b: 't,
+    {
+        let mut distances = vec![];
+        let mut ordered_indices = vec![];
+
+        let mut mask = vec![false; alignee.len()];
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            let Ok(neighbors) = self.tree.nearest(
+                p.pos.coords.as_slice(),
+                self.k_neighbors,
+                &squared_euclidean,
+            ) else {
+                continue;
+            };
+
+            let closest_to_ray = match p.norm {
+                Some(norm) if norm.norm_squared() > T::zero() => {
+                    let direction = norm.normalize();
+
+                    neighbors.iter().min_by(|(_, a), (_, b)| {
+                        let dist_to_ray = |idx: usize| {
+                            let diff = self.target_cloud[idx].pos - p.pos;
+                            (diff - direction * diff.dot(&direction)).norm_squared()
+                        };
+
+                        nalgebra::partial_cmp(&dist_to_ray(**a), &dist_to_ray(**b))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                }
+                _ => neighbors.first(),
+            };
+
+            let Some((distance, idx)) = closest_to_ray else {
+                continue;
+            };
+
+            ordered_indices.push(**idx);
+            distances.push(*distance);
+            mask[i] = true;
+        }
+
+        let mut alignee_point_cloud = MaskedPointCloud::new(alignee);
+        alignee_point_cloud.add_mask(&mask);
+
+        let mut corresponding_target_point_cloud = MaskedPointCloud::new(&self.target_cloud);
+        corresponding_target_point_cloud.add_order(&ordered_indices);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_point_cloud,
+            alignee,
+            corresponding_target_point_cloud,
+            distances,
+        )
+    }
+
+    fn validate_target(&self) -> Result<(), IcpError> {
+        validate_point_cloud(&self.target_cloud)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointCloudPoint;
+    use crate::filter_points::accept_all;
+    use nalgebra::{Point3, Vector3};
+
+    fn corresponding_target(
+        alignee: &PointCloud<f32, 3>,
+        target: &PointCloud<f32, 3>,
+    ) -> Vec<Point3<f32>> {
+        let mut estimator = NormalShooting::new(target);
+        let correspondences = estimator.find_correspondences(alignee, target, &mut accept_all);
+
+        correspondences
+            .corresponding_target_point_cloud
+            .points_iter()
+            .collect()
+    }
+
+    #[test]
+    fn picks_target_point_along_normal() {
+        let target: PointCloud<f32, 3> = vec![
+            PointCloudPoint::from_pos(Point3::new(0.3, 0.0, 0.0)),
+            PointCloudPoint::from_pos(Point3::new(0.0, 0.05, -0.5)),
+        ];
+        let alignee = vec![PointCloudPoint::from_pos_norm(
+            Point3::origin(),
+            Vector3::z(),
+        )];
+
+        // the ray is a line, so the target point behind the alignee point is picked as well
+        assert_eq!(
+            corresponding_target(&alignee, &target),
+            vec![Point3::new(0.0, 0.05, -0.5)]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_nearest_neighbor_without_normal() {
+        let target: PointCloud<f32, 3> = vec![
+            PointCloudPoint::from_pos(Point3::new(0.3, 0.0, 0.0)),
+            PointCloudPoint::from_pos(Point3::new(0.0, 0.05, -0.5)),
+        ];
+        let alignee = vec![PointCloudPoint::from_pos(Point3::origin())];
+
+        assert_eq!(
+            corresponding_target(&alignee, &target),
+            vec![Point3::new(0.3, 0.0, 0.0)]
+        );
+    }
+}
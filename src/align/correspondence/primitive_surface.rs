@@ -0,0 +1,187 @@
+use crate::correspondence::primitives::Primitive;
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{MaskedPointCloud, PointCloud, PointCloudPoint};
+use nalgebra::{Point3, RealField, Scalar};
+use num_traits::{Float, One, Zero};
+
+/// Computes the correspondences between the alignee and the surface of an analytic primitive shape
+/// like a plane, sphere, cylinder, cone or torus. See the module [`super::primitives`] for all built-in shapes.
+///
+/// For every alignee point the closest point on the surface of the primitive is used as correspondence.
+/// The corresponding target points carry the surface normal.
+///
+/// If enabled with [`PrimitiveSurface::refine_shape`], the shape parameters of the primitive
+/// (not the pose) are refined to the alignee points before the correspondences are computed in every iteration.
+/// The current state of the primitive can be accessed with [`PrimitiveSurface::primitive`]. The [`crate::Icp`] starts
+/// every run from the initial primitive again (see [`CorrespondenceEstimator::reset`]).
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, PrimitiveSurface};
+/// # use modern_icp::correspondence::primitives::Cylinder;
+/// # use modern_icp::transform_estimation::point_to_plane_lls;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// # use nalgebra::{Isometry3, Vector3};
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let nominal_cylinder = Cylinder::new(
+///     Isometry3::new(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.5, 0.0)),
+///     2.0,
+///     10.0,
+/// );
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(PrimitiveSurface::new(&nominal_cylinder))
+///     .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &nominal_cylinder);
+/// ```
+pub struct PrimitiveSurface<T, P>
+where
+    T: Scalar + RealField + Float + One + Zero + Copy,
+    P: Primitive<T>,
+{
+    initial_primitive: P,
+    primitive: P,
+    refine_shape: bool,
+    point_cloud: PointCloud<T, 3>,
+}
+
+impl<T, P> PrimitiveSurface<T, P>
+where
+    T: Scalar + RealField + Float + One + Zero + Copy,
+    P: Primitive<T>,
+{
+    /// Enables or disables refining the shape parameters of the primitive in every iteration.
+    ///
+    /// Defaults to `false`.
+    pub fn refine_shape(self, refine_shape: bool) -> Self {
+        Self {
+            refine_shape,
+            ..self
+        }
+    }
+
+    /// The primitive with the current shape parameters.
+    pub fn primitive(&self) -> &P {
+        &self.primitive
+    }
+}
+
+impl<'a, T, P> CorrespondenceEstimator<'a, T, P, 3> for PrimitiveSurface<T, P>
+where
+    T: Scalar + RealField + Float + One + Zero,
+    P: Primitive<T> + Clone,
+{
+    fn new(target: &'a P) -> Self {
+        PrimitiveSurface {
+            initial_primitive: target.clone(),
+            primitive: target.clone(),
+            refine_shape: false,
+            point_cloud: vec![],
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, 3>,
+        _target: &'b P,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, 3>
+    where
+        FP: PointFilter<T, 3>,
+        'b: 't,
+    {
+        let mut mask = vec![false; alignee.len()];
+        let mut points: Vec<Point3<T>> = Vec::with_capacity(alignee.len());
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            points.push(p.pos);
+            mask[i] = true;
+        }
+
+        if self.refine_shape {
+            self.primitive.refine(&points);
+        }
+
+        self.point_cloud.clear();
+
+        let mut distances = Vec::with_capacity(points.len());
+
+        for pos in &points {
+            let (closest, normal) = self.primitive.closest_point(pos);
+
+            self.point_cloud
+                .push(PointCloudPoint::from_pos_norm(closest, normal));
+            distances.push((closest - pos).norm_squared());
+        }
+
+        let mut alignee_cloud = MaskedPointCloud::new(alignee);
+        alignee_cloud.add_mask(&mask);
+
+        let target_cloud = MaskedPointCloud::new(&self.point_cloud);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_cloud,
+            alignee,
+            target_cloud,
+            distances,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.primitive = self.initial_primitive.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::correspondence::primitives::Sphere;
+    use crate::filter_points::AcceptAll;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn pairs_points_with_surface() {
+        let sphere = Sphere::new(Point3::origin(), 1.0);
+        let alignee = vec![PointCloudPoint::from_pos(Point3::new(0.0, 0.0, 2.0))];
+
+        let mut estimator = PrimitiveSurface::new(&sphere);
+        let correspondences = estimator.find_correspondences(&alignee, &sphere, &mut AcceptAll);
+
+        let target: Vec<_> = correspondences
+            .corresponding_target_point_cloud
+            .iter()
+            .collect();
+        assert_eq!(target[0].pos, Point3::new(0.0, 0.0, 1.0));
+        assert_eq!(target[0].norm, Some(Vector3::z()));
+        assert_eq!(correspondences.alignee_to_target_distances, vec![1.0]);
+    }
+
+    #[test]
+    fn reset_restores_initial_primitive() {
+        let sphere = Sphere::new(Point3::origin(), 1.0);
+        let alignee = vec![
+            PointCloudPoint::from_pos(Point3::new(0.0, 0.0, 2.0)),
+            PointCloudPoint::from_pos(Point3::new(0.0, -2.0, 0.0)),
+        ];
+
+        let mut estimator = PrimitiveSurface::new(&sphere).refine_shape(true);
+        let correspondences = estimator.find_correspondences(&alignee, &sphere, &mut AcceptAll);
+
+        // the correspondences are computed with the refined radius
+        assert_eq!(correspondences.alignee_to_target_distances, vec![0.0, 0.0]);
+        assert_eq!(estimator.primitive().radius, 2.0);
+
+        CorrespondenceEstimator::reset(&mut estimator);
+        assert_eq!(estimator.primitive().radius, 1.0);
+    }
+}
@@ -0,0 +1,93 @@
+use nalgebra::{Isometry3, Point3, RealField, Scalar, Vector2, Vector3};
+
+use super::{Primitive, mean, radial_direction};
+
+/// A cone with a base cap.
+///
+/// In the local frame the apex of the cone lies at the origin and it opens along the z-axis
+/// with the given `half_angle` (in radians) up to the base cap at `z = height`.
+/// The `pose` transforms the local frame into world coordinates.
+///
+/// The refinement step fits the half angle to the points closest to the mantle.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cone<T: Scalar + RealField + Copy> {
+    pub pose: Isometry3<T>,
+    pub half_angle: T,
+    pub height: T,
+}
+
+enum ConePart {
+    Mantle,
+    Base,
+}
+
+impl<T: Scalar + RealField + Copy> Cone<T> {
+    pub fn new(pose: Isometry3<T>, half_angle: T, height: T) -> Self {
+        Cone {
+            pose,
+            half_angle,
+            height,
+        }
+    }
+
+    fn closest_point_local(&self, local: &Point3<T>) -> (Point3<T>, Vector3<T>, ConePart) {
+        let (dir, rho) = radial_direction(local);
+        let (sin, cos) = self.half_angle.sin_cos();
+
+        // work in the half-plane spanned by the radial direction and the axis
+        let along_mantle = Vector2::new(sin, cos);
+        let mantle_length = self.height / cos;
+        let s = (along_mantle.x * rho + along_mantle.y * local.z).clamp(T::zero(), mantle_length);
+        let mantle = along_mantle * s;
+
+        let base_radius = self.height * sin / cos;
+        let base_rho = rho.min(base_radius);
+
+        let mantle_dist = (mantle.x - rho).powi(2) + (mantle.y - local.z).powi(2);
+        let base_dist = (base_rho - rho).powi(2) + (self.height - local.z).powi(2);
+
+        if mantle_dist <= base_dist {
+            (
+                Point3::new(dir.x * mantle.x, dir.y * mantle.x, mantle.y),
+                Vector3::new(dir.x * cos, dir.y * cos, -sin),
+                ConePart::Mantle,
+            )
+        } else {
+            (
+                Point3::new(dir.x * base_rho, dir.y * base_rho, self.height),
+                Vector3::z(),
+                ConePart::Base,
+            )
+        }
+    }
+}
+
+impl<T> Primitive<T> for Cone<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn closest_point(&self, point: &Point3<T>) -> (Point3<T>, Vector3<T>) {
+        let local = self.pose.inverse_transform_point(point);
+        let (closest, normal, _) = self.closest_point_local(&local);
+
+        (self.pose * closest, self.pose * normal)
+    }
+
+    fn refine(&mut self, points: &[Point3<T>]) {
+        let half_angle = mean(points.iter().filter_map(|p| {
+            let local = self.pose.inverse_transform_point(p);
+
+            match self.closest_point_local(&local) {
+                (_, _, ConePart::Mantle) if local.z > T::zero() => {
+                    Some(radial_direction(&local).1.atan2(local.z))
+                }
+                _ => None,
+            }
+        }));
+
+        if let Some(half_angle) = half_angle {
+            self.half_angle = half_angle;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use nalgebra::{Isometry3, Point3, RealField, Scalar, Vector3};
+
+use super::{Primitive, mean, radial_direction};
+
+/// A cylinder with both caps.
+///
+/// In the local frame the axis of the cylinder is the z-axis, the bottom cap lies at `z = 0` and the
+/// top cap at `z = height`. The `pose` transforms the local frame into world coordinates.
+///
+/// The refinement step fits the radius to the points closest to the mantle.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cylinder<T: Scalar + RealField + Copy> {
+    pub pose: Isometry3<T>,
+    pub radius: T,
+    pub height: T,
+}
+
+enum CylinderPart {
+    Mantle,
+    Cap,
+}
+
+impl<T: Scalar + RealField + Copy> Cylinder<T> {
+    pub fn new(pose: Isometry3<T>, radius: T, height: T) -> Self {
+        Cylinder {
+            pose,
+            radius,
+            height,
+        }
+    }
+
+    fn closest_point_local(&self, local: &Point3<T>) -> (Point3<T>, Vector3<T>, CylinderPart) {
+        let (dir, rho) = radial_direction(local);
+        let z = local.z.clamp(T::zero(), self.height);
+
+        let mantle = Point3::new(dir.x * self.radius, dir.y * self.radius, z);
+
+        let cap_rho = rho.min(self.radius);
+        let cap_z = if local.z * (T::one() + T::one()) < self.height {
+            T::zero()
+        } else {
+            self.height
+        };
+        let cap = Point3::new(dir.x * cap_rho, dir.y * cap_rho, cap_z);
+
+        if (mantle - local).norm_squared() <= (cap - local).norm_squared() {
+            (
+                mantle,
+                Vector3::new(dir.x, dir.y, T::zero()),
+                CylinderPart::Mantle,
+            )
+        } else if cap_z == T::zero() {
+            (cap, -Vector3::z(), CylinderPart::Cap)
+        } else {
+            (cap, Vector3::z(), CylinderPart::Cap)
+        }
+    }
+}
+
+impl<T> Primitive<T> for Cylinder<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn closest_point(&self, point: &Point3<T>) -> (Point3<T>, Vector3<T>) {
+        let local = self.pose.inverse_transform_point(point);
+        let (closest, normal, _) = self.closest_point_local(&local);
+
+        (self.pose * closest, self.pose * normal)
+    }
+
+    fn refine(&mut self, points: &[Point3<T>]) {
+        let radius = mean(points.iter().filter_map(|p| {
+            let local = self.pose.inverse_transform_point(p);
+
+            match self.closest_point_local(&local) {
+                (_, _, CylinderPart::Mantle) => Some(radial_direction(&local).1),
+                (_, _, CylinderPart::Cap) => None,
+            }
+        }));
+
+        if let Some(radius) = radius {
+            self.radius = radius;
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Analytic primitive shapes that can be used as targets with the
+//! [`PrimitiveSurface`](super::PrimitiveSurface) correspondence estimator.
+//!
+//! All shapes implement the [`Primitive`] trait. Shapes with an axis are described in a local frame
+//! whose z-axis is the axis of the shape. The `pose` of the shape transforms this local frame into world coordinates.
+
+mod cone;
+mod cylinder;
+mod plane;
+mod sphere;
+mod torus;
+
+pub use cone::*;
+pub use cylinder::*;
+pub use sphere::*;
+pub use torus::*;
+
+use nalgebra::{Point3, RealField, Scalar, Vector2, Vector3};
+
+/// Trait for analytic primitive shapes.
+pub trait Primitive<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    /// Computes the point on the surface of the primitive that is closest to `point` and the
+    /// outward facing unit normal of the surface at that point.
+    fn closest_point(&self, point: &Point3<T>) -> (Point3<T>, Vector3<T>);
+
+    /// Refines the shape parameters (like the radius) of the primitive to best fit the given points.
+    /// The pose of the primitive is not changed as this is the job of the ICP.
+    ///
+    /// The default implementation doesn't change anything.
+    fn refine(&mut self, _points: &[Point3<T>]) {}
+}
+
+/// Returns the unit vector of the xy-components of `local` and their length.
+/// Falls back to the x-axis for points on the z-axis.
+fn radial_direction<T>(local: &Point3<T>) -> (Vector2<T>, T)
+where
+    T: Scalar + RealField + Copy,
+{
+    let xy = Vector2::new(local.x, local.y);
+    let rho = xy.norm();
+
+    if rho > T::default_epsilon() {
+        (xy / rho, rho)
+    } else {
+        (Vector2::x(), rho)
+    }
+}
+
+/// Returns the mean of the values or `None` if there are none.
+fn mean<T>(values: impl Iterator<Item = T>) -> Option<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    let (sum, count) = values.fold((T::zero(), T::zero()), |(sum, count), v| {
+        (sum + v, count + T::one())
+    });
+
+    (count > T::zero()).then(|| sum / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Plane3;
+    use nalgebra::Isometry3;
+
+    fn assert_closest_point(
+        primitive: &impl Primitive<f32>,
+        point: Point3<f32>,
+        expected: Point3<f32>,
+        expected_normal: Vector3<f32>,
+    ) {
+        let (closest, normal) = primitive.closest_point(&point);
+
+        assert!(
+            (closest - expected).norm() < 1e-5 && (normal - expected_normal).norm() < 1e-5,
+            "expected {expected:?} with normal {expected_normal:?} but got {closest:?} with normal {normal:?}"
+        );
+    }
+
+    #[test]
+    fn closest_points_on_surfaces() {
+        let pose = Isometry3::translation(1.0, 0.0, 0.0);
+
+        assert_closest_point(
+            &Plane3::from_normal_and_point(&Vector3::z(), &Point3::new(0.0, 0.0, 1.0)),
+            Point3::new(0.3, -0.2, 3.0),
+            Point3::new(0.3, -0.2, 1.0),
+            Vector3::z(),
+        );
+
+        assert_closest_point(
+            &Sphere::new(Point3::new(1.0, 0.0, 0.0), 2.0),
+            Point3::new(1.0, 0.5, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+            Vector3::y(),
+        );
+
+        let cylinder = Cylinder::new(pose, 1.0, 4.0);
+        assert_closest_point(
+            &cylinder,
+            Point3::new(3.0, 0.0, 2.0),
+            Point3::new(2.0, 0.0, 2.0),
+            Vector3::x(),
+        );
+        assert_closest_point(
+            &cylinder,
+            Point3::new(1.5, 0.0, 4.2),
+            Point3::new(1.5, 0.0, 4.0),
+            Vector3::z(),
+        );
+        assert_closest_point(
+            &cylinder,
+            Point3::new(1.5, 0.0, -0.2),
+            Point3::new(1.5, 0.0, 0.0),
+            -Vector3::z(),
+        );
+
+        // the mantle of a cone with a half angle of 45° is the line z = rho in every half-plane
+        let cone = Cone::new(pose, std::f32::consts::FRAC_PI_4, 2.0);
+        assert_closest_point(
+            &cone,
+            Point3::new(1.0, 2.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Vector3::new(0.0, 1.0, -1.0).normalize(),
+        );
+        assert_closest_point(
+            &cone,
+            Point3::new(1.5, 0.0, 2.5),
+            Point3::new(1.5, 0.0, 2.0),
+            Vector3::z(),
+        );
+
+        let torus = Torus::new(pose, 2.0, 0.5);
+        assert_closest_point(
+            &torus,
+            Point3::new(4.0, 0.0, 0.0),
+            Point3::new(3.5, 0.0, 0.0),
+            Vector3::x(),
+        );
+        assert_closest_point(
+            &torus,
+            Point3::new(1.0, 2.0, 1.0),
+            Point3::new(1.0, 2.0, 0.5),
+            Vector3::z(),
+        );
+    }
+
+    #[test]
+    fn refinement_fits_shape_but_not_pose() {
+        let pose = Isometry3::new(Vector3::new(1.0, -1.0, 0.5), Vector3::new(0.3, 0.0, 0.2));
+
+        let mantle_points = |radius: f32| -> Vec<Point3<f32>> {
+            (0..24)
+                .map(|i| {
+                    let angle = i as f32 / 24.0 * std::f32::consts::TAU;
+                    pose * Point3::new(
+                        radius * angle.cos(),
+                        radius * angle.sin(),
+                        1.0 + (i % 3) as f32,
+                    )
+                })
+                .collect()
+        };
+
+        let mut cylinder = Cylinder::new(pose, 1.0, 4.0);
+        cylinder.refine(&mantle_points(1.3));
+        assert!((cylinder.radius - 1.3).abs() < 1e-5);
+        assert_eq!(cylinder.pose, pose);
+
+        let mut sphere = Sphere::new(Point3::new(1.0, 2.0, 3.0), 1.0_f32);
+        sphere.refine(&[Point3::new(1.0, 2.0, 5.0), Point3::new(-1.0, 2.0, 3.0)]);
+        assert!((sphere.radius - 2.0).abs() < 1e-5);
+
+        let tube_points: Vec<_> = (0..24)
+            .map(|i| {
+                let (angle, tube_angle) = (i as f32 * 0.7, i as f32 / 24.0 * std::f32::consts::TAU);
+                let rho = 3.0 + 0.4 * tube_angle.cos();
+                pose * Point3::new(rho * angle.cos(), rho * angle.sin(), 0.4 * tube_angle.sin())
+            })
+            .collect();
+
+        let mut torus = Torus::new(pose, 2.5, 0.5);
+        // the radii depend on each other, so the refinement converges over several iterations
+        for _ in 0..50 {
+            torus.refine(&tube_points);
+        }
+        assert!((torus.major_radius - 3.0).abs() < 1e-3);
+        assert!((torus.minor_radius - 0.4).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,17 @@
+use nalgebra::{Point3, RealField, Scalar, Vector3};
+
+use super::Primitive;
+use crate::Plane3;
+
+/// An infinite plane. The normal of the plane has to be normalized.
+impl<T> Primitive<T> for Plane3<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn closest_point(&self, point: &Point3<T>) -> (Point3<T>, Vector3<T>) {
+        (
+            point - self.normal * self.distance_to_point(point),
+            self.normal,
+        )
+    }
+}
@@ -0,0 +1,38 @@
+use nalgebra::{Point3, RealField, Scalar, Vector3};
+
+use super::{Primitive, mean};
+
+/// A sphere with the given `center` and `radius`.
+///
+/// The refinement step fits the radius.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sphere<T: Scalar + RealField + Copy> {
+    pub center: Point3<T>,
+    pub radius: T,
+}
+
+impl<T: Scalar + RealField + Copy> Sphere<T> {
+    pub fn new(center: Point3<T>, radius: T) -> Self {
+        Sphere { center, radius }
+    }
+}
+
+impl<T> Primitive<T> for Sphere<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn closest_point(&self, point: &Point3<T>) -> (Point3<T>, Vector3<T>) {
+        let normal = (point - self.center)
+            .try_normalize(T::default_epsilon())
+            .unwrap_or_else(Vector3::z);
+
+        (self.center + normal * self.radius, normal)
+    }
+
+    fn refine(&mut self, points: &[Point3<T>]) {
+        if let Some(radius) = mean(points.iter().map(|p| (p - self.center).norm())) {
+            self.radius = radius;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use nalgebra::{Isometry3, Point3, RealField, Scalar, Vector2, Vector3};
+
+use super::{Primitive, mean, radial_direction};
+
+/// A torus.
+///
+/// In the local frame the center of the torus lies at the origin and the z-axis is its axis of
+/// rotational symmetry. The center line of the tube is a circle with the `major_radius` in the xy-plane,
+/// and the tube has the `minor_radius`. The `pose` transforms the local frame into world coordinates.
+///
+/// The refinement step fits both radii.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Torus<T: Scalar + RealField + Copy> {
+    pub pose: Isometry3<T>,
+    pub major_radius: T,
+    pub minor_radius: T,
+}
+
+impl<T: Scalar + RealField + Copy> Torus<T> {
+    pub fn new(pose: Isometry3<T>, major_radius: T, minor_radius: T) -> Self {
+        Torus {
+            pose,
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    /// Returns the unit direction from the tube center line to the point in the
+    /// half-plane spanned by the radial direction and the axis.
+    fn tube_direction(&self, rho: T, z: T) -> Vector2<T> {
+        Vector2::new(rho - self.major_radius, z)
+            .try_normalize(T::default_epsilon())
+            .unwrap_or_else(Vector2::x)
+    }
+}
+
+impl<T> Primitive<T> for Torus<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn closest_point(&self, point: &Point3<T>) -> (Point3<T>, Vector3<T>) {
+        let local = self.pose.inverse_transform_point(point);
+        let (dir, rho) = radial_direction(&local);
+
+        let tube_dir = self.tube_direction(rho, local.z);
+        let closest_rho = self.major_radius + tube_dir.x * self.minor_radius;
+
+        let closest = Point3::new(
+            dir.x * closest_rho,
+            dir.y * closest_rho,
+            tube_dir.y * self.minor_radius,
+        );
+        let normal = Vector3::new(dir.x * tube_dir.x, dir.y * tube_dir.x, tube_dir.y);
+
+        (self.pose * closest, self.pose * normal)
+    }
+
+    fn refine(&mut self, points: &[Point3<T>]) {
+        let local_points: Vec<_> = points
+            .iter()
+            .map(|p| {
+                let local = self.pose.inverse_transform_point(p);
+                (radial_direction(&local).1, local.z)
+            })
+            .collect();
+
+        let major_radius = mean(
+            local_points
+                .iter()
+                .map(|(rho, z)| *rho - self.tube_direction(*rho, *z).x * self.minor_radius),
+        );
+
+        if let Some(major_radius) = major_radius {
+            self.major_radius = major_radius;
+        }
+
+        let minor_radius = mean(
+            local_points
+                .iter()
+                .map(|(rho, z)| Vector2::new(*rho - self.major_radius, *z).norm()),
+        );
+
+        if let Some(minor_radius) = minor_radius {
+            self.minor_radius = minor_radius;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{MaskedPointCloud, OrganizedPointCloud, PointCloud};
+use nalgebra::{RealField, Scalar};
+use num_traits::{Float, One, Zero};
+
+/// Computes the correspondences between the alignee and an organized target (e.g. a depth camera frame)
+/// by projecting every alignee point into the target image (projective data association).
+///
+/// The alignee points have to be given in the camera coordinates of the target. The target point at
+/// the pixel the alignee point is projected onto is its correspondence. This avoids any nearest neighbor
+/// search and is therefore much faster than [`super::NearestNeighbor`].
+///
+/// Correspondences that are farther apart than `max_distance` are dropped (defaults to infinity).
+///
+/// See this [paper from Newcombe et al. (KinectFusion)](https://doi.org/10.1109/ISMAR.2011.6092378)
+pub struct Projective<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    target: OrganizedPointCloud<T>,
+    max_squared_distance: T,
+}
+
+impl<T> Projective<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    /// Sets the maximum distance between an alignee point and the target point it is projected onto.
+    ///
+    /// Defaults to infinity.
+    pub fn max_distance(self, max_distance: T) -> Self {
+        Self {
+            max_squared_distance: max_distance * max_distance,
+            ..self
+        }
+    }
+}
+
+impl<'a, T> CorrespondenceEstimator<'a, T, OrganizedPointCloud<T>, 3> for Projective<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    fn new(target: &'a OrganizedPointCloud<T>) -> Self {
+        Projective {
+            target: target.clone(),
+            max_squared_distance: <T as Float>::infinity(),
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, 3>,
+        _target: &'b OrganizedPointCloud<T>,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, 3>
+    where
+        FP: PointFilter<T, 3>,
+        'b: 't,
+    {
+        let mut distances = vec![];
+        let mut ordered_indices = vec![];
+
+        let mut mask = vec![false; alignee.len()];
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            let Some(idx) = self.target.project(&p.pos) else {
+                continue;
+            };
+
+            let distance = (self.target.points[idx].pos - p.pos).norm_squared();
+            if distance > self.max_squared_distance {
+                continue;
+            }
+
+            ordered_indices.push(idx);
+            distances.push(distance);
+            mask[i] = true;
+        }
+
+        let mut alignee_point_cloud = MaskedPointCloud::new(alignee);
+        alignee_point_cloud.add_mask(&mask);
+
+        let mut corresponding_target_point_cloud = MaskedPointCloud::new(&self.target.points);
+        corresponding_target_point_cloud.add_order(&ordered_indices);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_point_cloud,
+            alignee,
+            corresponding_target_point_cloud,
+            distances,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_points::AcceptAll;
+    use crate::{PinholeCamera, PointCloudPoint};
+    use nalgebra::Point3;
+
+    #[test]
+    fn pairs_points_with_pixel_they_are_projected_onto() {
+        let target = OrganizedPointCloud::from_depth_image(
+            &[2.0; 16],
+            4,
+            4,
+            PinholeCamera::new(10.0, 10.0, 1.5, 1.5),
+        );
+
+        let alignee = vec![
+            // in front of pixel (3, 0)
+            PointCloudPoint::from_pos(target.camera.back_project(3.0, 0.0, 1.9)),
+            // behind the camera
+            PointCloudPoint::from_pos(Point3::new(0.0, 0.0, -1.0)),
+            // too far from pixel (1, 2)
+            PointCloudPoint::from_pos(target.camera.back_project(1.0, 2.0, 3.0)),
+        ];
+
+        let mut estimator = Projective::new(&target).max_distance(0.5);
+        let correspondences = estimator.find_correspondences(&alignee, &target, &mut AcceptAll);
+
+        assert_eq!(correspondences.alignee_point_cloud.len(), 1);
+        assert_eq!(
+            correspondences
+                .corresponding_target_point_cloud
+                .points_iter()
+                .collect::<Vec<_>>(),
+            vec![target.points[target.index(3, 0).unwrap()].pos]
+        );
+    }
+}
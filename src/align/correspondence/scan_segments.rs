@@ -0,0 +1,193 @@
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{
+    IcpError, MaskedPointCloud, PointCloud, PointCloudPoint, ToPointCloud, kd_tree_of_point_cloud,
+    validate_point_cloud,
+};
+use kdtree::KdTree;
+use kdtree::distance::squared_euclidean;
+use nalgebra::{Point2, RealField, Scalar, Vector2};
+use num_traits::{Float, One, Zero};
+
+/// Computes the correspondences between the alignee and the line segments of a 2D target scan.
+///
+/// Consecutive points of the target are connected into segments, so the points have to be in scan order.
+/// Consecutive points that are further apart than `max_segment_length` are not connected (see
+/// [`ScanSegments::max_segment_length`]). For every alignee point the closest point on the two segments adjacent to
+/// its nearest target point is found. The corresponding target points carry the normal of their segment,
+/// so use them with a point-to-line estimator like [`crate::transform_estimation::pl_icp::estimate_isometry`].
+///
+/// The distances are squared like the ones of [`super::NearestNeighbor`].
+///
+/// See this [paper from Censi](https://doi.org/10.1109/ROBOT.2008.4543181)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, ScanSegments};
+/// # use modern_icp::transform_estimation::pl_icp;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_scan = PointCloud::<f32, 2>::new();
+/// # let target_scan = PointCloud::<f32, 2>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(ScanSegments::new(&target_scan).max_segment_length(0.5))
+///     .estimate_step_transform(pl_icp::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_scan, &target_scan);
+/// ```
+pub struct ScanSegments<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    tree: KdTree<T, usize, Vec<T>>,
+    target_cloud: PointCloud<T, 2>,
+    max_segment_length: T,
+    point_cloud: PointCloud<T, 2>,
+}
+
+impl<T> ScanSegments<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    /// Consecutive target points that are further apart than this are not connected, e.g. at depth discontinuities.
+    ///
+    /// Defaults to infinity, i.e. all consecutive points are connected.
+    pub fn max_segment_length(self, max_segment_length: T) -> Self {
+        Self {
+            max_segment_length,
+            ..self
+        }
+    }
+
+    /// The segment from the target point with the index `start` to the next one if it exists and isn't too long.
+    fn segment(&self, start: usize) -> Option<(Point2<T>, Point2<T>)> {
+        let a = self.target_cloud.get(start)?.pos;
+        let b = self.target_cloud.get(start + 1)?.pos;
+
+        if (b - a).norm() > self.max_segment_length {
+            return None;
+        }
+
+        Some((a, b))
+    }
+
+    /// The closest point on the segments adjacent to the target point with the index `idx` together with the normal
+    /// of the segment and the squared distance.
+    fn closest_point(&self, point: &Point2<T>, idx: usize) -> Option<(Point2<T>, Vector2<T>, T)> {
+        let candidates = [
+            idx.checked_sub(1).and_then(|i| self.segment(i)),
+            self.segment(idx),
+        ];
+
+        candidates
+            .into_iter()
+            .flatten()
+            .filter_map(|(a, b)| {
+                let direction = b - a;
+                let length_squared = direction.norm_squared();
+                if length_squared <= T::zero() {
+                    return None;
+                }
+
+                let s = Float::min(
+                    Float::max((point - a).dot(&direction) / length_squared, T::zero()),
+                    T::one(),
+                );
+                let closest = a + direction * s;
+                let normal = Vector2::new(-direction.y, direction.x).normalize();
+
+                Some((closest, normal, (point - closest).norm_squared()))
+            })
+            .min_by(|(_, _, d1), (_, _, d2)| {
+                d1.partial_cmp(d2).unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+}
+
+impl<'a, T, PC> CorrespondenceEstimator<'a, T, PC, 2> for ScanSegments<T>
+where
+    T: Scalar + RealField + Float + One + Zero,
+    PC: ToPointCloud<T, 2>,
+{
+    fn new(target: &'a PC) -> Self {
+        let target_cloud = target.to_point_cloud();
+
+        ScanSegments {
+            tree: kd_tree_of_point_cloud(&target_cloud),
+            target_cloud,
+            max_segment_length: Float::infinity(),
+            point_cloud: vec![],
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, 2>,
+        _target: &'b PC,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, 2>
+    where
+        FP: PointFilter<T, 2>,
+        'b: 't,
+    {
+        let mut point_cloud = Vec::with_capacity(alignee.len());
+
+        let mut distances = Vec::with_capacity(alignee.len());
+
+        let mut mask = vec![false; alignee.len()];
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            let Some(idx) = self
+                .tree
+                .nearest(p.pos.coords.as_slice(), 1, &squared_euclidean)
+                .ok()
+                .and_then(|nearest| nearest.first().map(|(_, idx)| **idx))
+            else {
+                continue;
+            };
+
+            // isolated target points without segments can only be used if they have a normal
+            let Some((closest, normal, distance)) = self.closest_point(&p.pos, idx).or_else(|| {
+                let target = &self.target_cloud[idx];
+                target
+                    .norm
+                    .map(|norm| (target.pos, norm, (p.pos - target.pos).norm_squared()))
+            }) else {
+                continue;
+            };
+
+            let mut point = PointCloudPoint::from_pos(closest);
+            point.norm = Some(normal);
+            point.weight = self.target_cloud[idx].weight;
+            point_cloud.push(point);
+            distances.push(distance);
+
+            mask[i] = true;
+        }
+
+        let mut alignee_cloud = MaskedPointCloud::new(alignee);
+        alignee_cloud.add_mask(&mask);
+
+        self.point_cloud = point_cloud;
+
+        let target_cloud = MaskedPointCloud::new(&self.point_cloud);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_cloud,
+            alignee,
+            target_cloud,
+            distances,
+        )
+    }
+
+    fn validate_target(&self) -> Result<(), IcpError> {
+        validate_point_cloud(&self.target_cloud)
+    }
+}
@@ -0,0 +1,243 @@
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::PointFilter;
+use crate::{
+    IcpError, MaskedPointCloud, PointCloud, ToPointCloud, kd_tree_of_point_cloud,
+    validate_point_cloud,
+};
+use kdtree::KdTree;
+use kdtree::distance::squared_euclidean;
+use nalgebra::{RealField, Scalar};
+use num_traits::{Float, One, Zero};
+
+/// Computes soft correspondences between the alignee and the target like EM-ICP / softassign.
+///
+/// Every alignee point is paired with its `k_neighbors` nearest target points (defaults to `5`).
+/// Every pair is weighted by a Gaussian of the distance `exp(-d² / (2σ²))`, normalized over the neighbors
+/// of the alignee point and multiplied with the weight of the target point. The resulting weights are stored in
+/// the `weight` field of the corresponding target points so they can be used by weighted transform estimators
+/// like [`crate::transform_estimation::point_to_plane_lls_weighted::estimate_isometry`].
+///
+/// The standard deviation σ anneals with every call of `find_correspondences`, i.e. with every ICP iteration:
+/// `σ = max(initial_sigma * annealing_rate^iteration, min_sigma)`. A large σ in the beginning widens the
+/// convergence basin while a small σ in the end converges to the nearest neighbor solution.
+/// The [`crate::Icp`] resets the annealing at the start of every run (see [`CorrespondenceEstimator::reset`]).
+///
+/// See this [paper from Granger and Pennec](https://doi.org/10.1007/3-540-47979-1_28)
+pub struct SoftNearestNeighbors<T, const D: usize = 3>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    tree: KdTree<T, usize, Vec<T>>,
+    target_cloud: PointCloud<T, D>,
+    k_neighbors: usize,
+    initial_sigma: T,
+    annealing_rate: T,
+    min_sigma: T,
+    iteration: i32,
+    weighted_target_cloud: PointCloud<T, D>,
+}
+
+impl<T, const D: usize> SoftNearestNeighbors<T, D>
+where
+    T: Scalar + RealField + Float + One + Zero,
+{
+    /// Sets the number of nearest target points every alignee point is paired with.
+    ///
+    /// Defaults to `5`.
+    pub fn k_neighbors(self, k_neighbors: usize) -> Self {
+        Self {
+            k_neighbors: k_neighbors.max(1),
+            ..self
+        }
+    }
+
+    /// Sets the standard deviation of the Gaussian weights in the first iteration.
+    /// This should be in the order of the initial misalignment.
+    ///
+    /// Defaults to `1.0`.
+    pub fn initial_sigma(self, initial_sigma: T) -> Self {
+        Self {
+            initial_sigma,
+            ..self
+        }
+    }
+
+    /// Sets the factor the standard deviation is multiplied with after every iteration.
+    ///
+    /// Defaults to `0.9`.
+    pub fn annealing_rate(self, annealing_rate: T) -> Self {
+        Self {
+            annealing_rate,
+            ..self
+        }
+    }
+
+    /// Sets the lower bound of the standard deviation.
+    ///
+    /// Defaults to `0.01`.
+    pub fn min_sigma(self, min_sigma: T) -> Self {
+        Self { min_sigma, ..self }
+    }
+
+    /// The standard deviation of the Gaussian weights that is used in the next iteration.
+    pub fn current_sigma(&self) -> T {
+        Float::max(
+            self.initial_sigma * Float::powi(self.annealing_rate, self.iteration),
+            self.min_sigma,
+        )
+    }
+}
+
+impl<'a, T, PC, const D: usize> CorrespondenceEstimator<'a, T, PC, D> for SoftNearestNeighbors<T, D>
+where
+    T: Scalar + RealField + Float + One + Zero,
+    PC: ToPointCloud<T, D>,
+{
+    fn new(target: &'a PC) -> Self {
+        let target_cloud = target.to_point_cloud();
+
+        SoftNearestNeighbors {
+            tree: kd_tree_of_point_cloud(&target_cloud),
+            target_cloud,
+            k_neighbors: 5,
+            initial_sigma: T::one(),
+            annealing_rate: nalgebra::convert(0.9),
+            min_sigma: nalgebra::convert(0.01),
+            iteration: 0,
+            weighted_target_cloud: vec![],
+        }
+    }
+
+    fn find_correspondences<'b, 't, FP>(
+        &'t mut self,
+        alignee: &'b PointCloud<T, D>,
+        _target: &'b PC,
+        filter_points: &mut FP,
+    ) -> Correspondences<'b, 't, T, D>
+    where
+        FP: PointFilter<T, D>,
+        'b: 't,
+    {
+        let sigma = self.current_sigma();
+        let two_sigma_sq = (T::one() + T::one()) * sigma * sigma;
+
+        self.weighted_target_cloud.clear();
+
+        let mut distances = vec![];
+        let mut alignee_indices = vec![];
+
+        for (i, p) in alignee
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| filter_points.filter(*p))
+        {
+            let Ok(neighbors) = self.tree.nearest(
+                p.pos.coords.as_slice(),
+                self.k_neighbors,
+                &squared_euclidean,
+            ) else {
+                continue;
+            };
+
+            let Some((min_distance, _)) = neighbors.first() else {
+                continue;
+            };
+
+            // subtracting the smallest distance doesn't change the normalized weights but avoids underflow
+            let gaussians: Vec<T> = neighbors
+                .iter()
+                .map(|(d, _)| Float::exp(-(*d - *min_distance) / two_sigma_sq))
+                .collect();
+            let sum: T = gaussians.iter().fold(T::zero(), |s, g| s + *g);
+
+            for ((distance, idx), gaussian) in neighbors.iter().zip(gaussians) {
+                let mut target_point = self.target_cloud[**idx];
+                target_point.weight *= gaussian / sum;
+
+                self.weighted_target_cloud.push(target_point);
+                alignee_indices.push(i);
+                distances.push(*distance);
+            }
+        }
+
+        self.iteration = self.iteration.saturating_add(1);
+
+        let alignee_point_cloud = MaskedPointCloud::compose(alignee, alignee_indices);
+
+        let corresponding_target_point_cloud = MaskedPointCloud::new(&self.weighted_target_cloud);
+
+        Correspondences::from_simple_one_way_correspondences(
+            alignee_point_cloud,
+            alignee,
+            corresponding_target_point_cloud,
+            distances,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.iteration = 0;
+    }
+
+    fn validate_target(&self) -> Result<(), IcpError> {
+        validate_point_cloud(&self.target_cloud)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointCloudPoint;
+    use crate::filter_points::AcceptAll;
+    use nalgebra::Point3;
+
+    fn line(count: usize) -> PointCloud<f32, 3> {
+        (0..count)
+            .map(|i| PointCloudPoint::from_pos(Point3::new(i as f32 * 0.1, 0.0, 0.0)))
+            .collect()
+    }
+
+    #[test]
+    fn weights_of_neighbors_sum_to_one() {
+        let target = line(10);
+        let alignee = vec![PointCloudPoint::from_pos(Point3::new(0.42, 0.05, 0.0))];
+
+        let mut estimator = SoftNearestNeighbors::new(&target)
+            .k_neighbors(3)
+            .initial_sigma(0.1);
+        let correspondences = estimator.find_correspondences(&alignee, &target, &mut AcceptAll);
+
+        let neighbors: Vec<_> = correspondences
+            .corresponding_target_point_cloud
+            .iter()
+            .collect();
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(correspondences.alignee_point_cloud.len(), 3);
+
+        let weight_sum: f32 = neighbors.iter().map(|p| p.weight).sum();
+        assert!((weight_sum - 1.0).abs() < 1e-6);
+
+        // the neighbors are sorted by distance, so the weights decrease
+        assert_eq!(neighbors[0].pos, Point3::new(0.4, 0.0, 0.0));
+        assert!(neighbors[0].weight > neighbors[1].weight);
+        assert!(neighbors[1].weight > neighbors[2].weight);
+    }
+
+    #[test]
+    fn sigma_anneals_until_reset() {
+        let target = line(10);
+        let alignee = line(3);
+
+        let mut estimator = SoftNearestNeighbors::new(&target)
+            .initial_sigma(1.0)
+            .annealing_rate(0.5)
+            .min_sigma(0.2);
+
+        for expected in [1.0, 0.5, 0.25, 0.2, 0.2] {
+            assert_eq!(estimator.current_sigma(), expected);
+            estimator.find_correspondences(&alignee, &target, &mut AcceptAll);
+        }
+
+        CorrespondenceEstimator::<f32, PointCloud<f32, 3>, 3>::reset(&mut estimator);
+        assert_eq!(estimator.current_sigma(), 1.0);
+    }
+}
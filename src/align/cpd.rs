@@ -0,0 +1,601 @@
+use nalgebra::{allocator::Allocator, *};
+use num_traits::Float;
+
+use crate::transform_estimation::svd::rotation_from_covariance;
+use crate::{PointCloud, compute_centroid};
+
+/// The transformation model of [`Cpd`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpdMode<T> {
+    /// Rotation and translation, plus a uniform scale if `estimate_scale` is `true`.
+    Rigid { estimate_scale: bool },
+    /// Arbitrary linear transform and translation.
+    Affine,
+    /// Smooth displacement field made of Gaussian kernels with the width `beta` that is regularized with `lambda`.
+    /// Both are relative to the normalized point clouds. The paper uses `2.0` for both.
+    NonRigid { beta: T, lambda: T },
+}
+
+/// Coherent Point Drift (CPD) registration.
+///
+/// The alignee points are the centroids of a Gaussian mixture model that is fitted to the target points with
+/// expectation maximization. The centroids move coherently according to the [`CpdMode`], which can be rigid,
+/// affine or a non-rigid displacement field. Unlike ICP there are no hard correspondences, every alignee point
+/// is softly assigned to every target point. `outlier_weight` (`0.0..1.0`) accounts for noise and outliers with an
+/// additional uniform distribution.
+///
+/// Both point clouds are normalized to zero mean and unit variance before the registration. Memory and run time
+/// grow with the product of the sizes of both point clouds (and the cube of the size of the alignee for the
+/// non-rigid mode), so downsample large point clouds first.
+///
+/// See this [paper from Myronenko and Song](https://doi.org/10.1109/TPAMI.2010.46)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::PointCloud;
+/// # use modern_icp::cpd::Cpd;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// if let Some(field) = Cpd::non_rigid(2.0, 2.0)
+///     .outlier_weight(0.1)
+///     .register(&alignee_cloud, &target_cloud)
+/// {
+///     let mut aligned_cloud = alignee_cloud.clone();
+///     field.transform_point_cloud(&mut aligned_cloud);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cpd<T> {
+    mode: CpdMode<T>,
+    outlier_weight: T,
+    max_iterations: usize,
+    tolerance: T,
+}
+
+impl<T> Cpd<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    pub fn new(mode: CpdMode<T>) -> Self {
+        Self {
+            mode,
+            outlier_weight: nalgebra::convert(0.1),
+            max_iterations: 100,
+            tolerance: nalgebra::convert(1e-6),
+        }
+    }
+
+    /// Rigid registration with scale estimation.
+    pub fn rigid() -> Self {
+        Self::new(CpdMode::Rigid {
+            estimate_scale: true,
+        })
+    }
+
+    pub fn affine() -> Self {
+        Self::new(CpdMode::Affine)
+    }
+
+    pub fn non_rigid(beta: T, lambda: T) -> Self {
+        Self::new(CpdMode::NonRigid { beta, lambda })
+    }
+
+    /// Weight of the uniform distribution that models noise and outliers. Defaults to `0.1`.
+    pub fn outlier_weight(mut self, outlier_weight: T) -> Self {
+        self.outlier_weight = outlier_weight;
+        self
+    }
+
+    /// Defaults to `100`.
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// The registration stops when the variance of the mixture model (of the normalized point clouds)
+    /// changes less than this. Defaults to `1e-6`.
+    pub fn tolerance(mut self, tolerance: T) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Registers the `alignee` onto the `target` and returns the resulting displacement field.
+    ///
+    /// Returns `None` if one of the point clouds is empty or a linear system couldn't be solved.
+    pub fn register<const D: usize>(
+        &self,
+        alignee: &PointCloud<T, D>,
+        target: &PointCloud<T, D>,
+    ) -> Option<DisplacementField<T, D>>
+    where
+        Const<D>: DimMin<Const<D>, Output = Const<D>> + DimSub<U1>,
+        DefaultAllocator: Allocator<DimDiff<Const<D>, U1>>,
+    {
+        self.register_with_rotation_solver(alignee, target, rotation_from_covariance::<T, D>)
+    }
+
+    /// Implementation of [`Cpd::register`]. It is separate so that the allocator bounds of the SVD
+    /// don't shadow the ones needed for the other matrix operations.
+    #[allow(non_snake_case)]
+    fn register_with_rotation_solver<const D: usize>(
+        &self,
+        alignee: &PointCloud<T, D>,
+        target: &PointCloud<T, D>,
+        rotation_from_covariance: impl Fn(SMatrix<T, D, D>) -> Option<SMatrix<T, D, D>>,
+    ) -> Option<DisplacementField<T, D>> {
+        let m = alignee.len();
+        let n = target.len();
+        if m == 0 || n == 0 {
+            return None;
+        }
+
+        let (alignee_mean, mut alignee_scale) = normalization(alignee);
+        let (target_mean, mut target_scale) = normalization(target);
+
+        // the rotation of the rigid modes stays a rotation in the original coordinates only if both point clouds
+        // are scaled alike, e.g. if the target only overlaps with a part of the alignee
+        if let CpdMode::Rigid { .. } = self.mode {
+            let scale = Float::max(alignee_scale, target_scale);
+            alignee_scale = scale;
+            target_scale = scale;
+        }
+
+        let X = DMatrix::from_fn(n, D, |i, j| {
+            (target[i].pos[j] - target_mean[j]) / target_scale
+        });
+        let Y = DMatrix::from_fn(m, D, |i, j| {
+            (alignee[i].pos[j] - alignee_mean[j]) / alignee_scale
+        });
+
+        let dim = T::from_usize(D).unwrap();
+        let two: T = nalgebra::convert(2.0);
+        let min_sigma_squared: T = nalgebra::convert(1e-10);
+
+        let mut sigma_squared = squared_distance_sum(&X, &Y)
+            / (dim * T::from_usize(m).unwrap() * T::from_usize(n).unwrap());
+
+        let mut linear = SMatrix::<T, D, D>::identity();
+        let mut translation = SVector::<T, D>::zeros();
+
+        let G = match self.mode {
+            CpdMode::NonRigid { beta, .. } => Some(gaussian_kernel(&Y, &Y, beta)),
+            _ => None,
+        };
+        let mut W = DMatrix::<T>::zeros(m, D);
+
+        let mut TY = Y.clone();
+
+        let outlier_constant = Float::powf(two * T::pi(), dim / two) * self.outlier_weight
+            / (T::one() - self.outlier_weight)
+            * T::from_usize(m).unwrap()
+            / T::from_usize(n).unwrap();
+
+        for _ in 0..self.max_iterations {
+            // E-step: posterior probabilities of the alignee points (rows) for every target point (columns)
+            let mut P = DMatrix::<T>::zeros(m, n);
+            let uniform = outlier_constant * Float::powf(sigma_squared, dim / two);
+
+            for (j, x) in X.row_iter().enumerate() {
+                let mut denominator = uniform;
+                for (i, y) in TY.row_iter().enumerate() {
+                    let p = Float::exp(-(x - y).norm_squared() / (two * sigma_squared));
+                    P[(i, j)] = p;
+                    denominator += p;
+                }
+                if denominator > T::zero() {
+                    P.column_mut(j).unscale_mut(denominator);
+                }
+            }
+
+            let P1 = P.column_sum();
+            let Pt1 = P.row_sum().transpose();
+            let PX = &P * &X;
+            let Np = P1.sum();
+
+            if Np <= T::default_epsilon() {
+                break;
+            }
+
+            let xPx = X
+                .row_iter()
+                .zip(Pt1.iter())
+                .fold(T::zero(), |sum, (x, p)| sum + x.norm_squared() * *p);
+
+            let new_sigma_squared = match self.mode {
+                CpdMode::Rigid { .. } | CpdMode::Affine => {
+                    let mu_x: SVector<T, D> = to_svector(&(X.transpose() * &Pt1)) / Np;
+                    let mu_y: SVector<T, D> = to_svector(&(Y.transpose() * &P1)) / Np;
+
+                    // demeaned weighted cross-covariance X^T P^T Y and alignee covariance Y^T diag(P1) Y
+                    let A: SMatrix<T, D, D> =
+                        to_smatrix(&(PX.transpose() * &Y)) - mu_x * mu_y.transpose() * Np;
+                    let yPy: SMatrix<T, D, D> =
+                        Y.row_iter()
+                            .zip(P1.iter())
+                            .fold(SMatrix::zeros(), |sum, (y, p)| {
+                                let y: SVector<T, D> = to_svector(&y.transpose());
+                                sum + y * y.transpose() * *p
+                            })
+                            - mu_y * mu_y.transpose() * Np;
+                    let xPx = xPx - mu_x.norm_squared() * Np;
+
+                    let (new_linear, sigma_squared) = match self.mode {
+                        CpdMode::Rigid { estimate_scale } => {
+                            let R = rotation_from_covariance(A.transpose())?;
+                            let trace_AR = (A.transpose() * R).trace();
+                            let scale = if estimate_scale {
+                                trace_AR / yPy.trace()
+                            } else {
+                                T::one()
+                            };
+
+                            (
+                                R * scale,
+                                (xPx - two * scale * trace_AR + scale * scale * yPy.trace())
+                                    / (Np * dim),
+                            )
+                        }
+                        _ => {
+                            // B = A * yPy^-1, solved without an explicit inverse
+                            let B_transposed: DMatrix<T> =
+                                to_dmatrix(&yPy).lu().solve(&to_dmatrix(&A.transpose()))?;
+                            let B = to_smatrix::<T, D>(&B_transposed).transpose();
+                            (B, (xPx - (A * B.transpose()).trace()) / (Np * dim))
+                        }
+                    };
+
+                    linear = new_linear;
+                    translation = mu_x - linear * mu_y;
+
+                    TY = &Y * to_dmatrix(&linear.transpose());
+                    for mut row in TY.row_iter_mut() {
+                        row += translation.transpose();
+                    }
+
+                    sigma_squared
+                }
+                CpdMode::NonRigid { lambda, .. } => {
+                    let G = G.as_ref()?;
+
+                    // (diag(P1) * G + lambda * sigma^2 * I) * W = P * X - diag(P1) * Y
+                    let mut lhs = G.clone();
+                    let mut rhs = -Y.clone();
+                    for (i, p) in P1.iter().enumerate() {
+                        lhs.row_mut(i).scale_mut(*p);
+                        lhs[(i, i)] += lambda * sigma_squared;
+                        rhs.row_mut(i).scale_mut(*p);
+                    }
+                    rhs += &PX;
+
+                    W = lhs.lu().solve(&rhs)?;
+                    TY = &Y + G * &W;
+
+                    let tyPty = TY
+                        .row_iter()
+                        .zip(P1.iter())
+                        .fold(T::zero(), |sum, (y, p)| sum + y.norm_squared() * *p);
+
+                    (xPx - two * PX.dot(&TY) + tyPty) / (Np * dim)
+                }
+            };
+
+            let new_sigma_squared = Float::max(Float::abs(new_sigma_squared), min_sigma_squared);
+            let change = Float::abs(sigma_squared - new_sigma_squared);
+            sigma_squared = new_sigma_squared;
+
+            if change < self.tolerance {
+                break;
+            }
+        }
+
+        let (beta, kernel_centers, coefficients) = match self.mode {
+            CpdMode::NonRigid { beta, .. } => (
+                beta,
+                Y.row_iter().map(|r| to_svector(&r.transpose())).collect(),
+                W.row_iter().map(|r| to_svector(&r.transpose())).collect(),
+            ),
+            _ => (T::one(), vec![], vec![]),
+        };
+
+        Some(DisplacementField {
+            alignee_mean,
+            alignee_scale,
+            target_mean,
+            target_scale,
+            linear,
+            translation,
+            beta,
+            kernel_centers,
+            coefficients,
+            sigma_squared: sigma_squared * target_scale * target_scale,
+        })
+    }
+}
+
+/// The result of a [`Cpd`] registration that maps any point from the alignee space into the target space.
+///
+/// It is made of a rigid or affine part and, for the non-rigid mode, a smooth displacement made of Gaussian kernels
+/// centered at the (normalized) alignee points.
+#[derive(Clone, Debug)]
+pub struct DisplacementField<T, const D: usize>
+where
+    T: Scalar,
+{
+    alignee_mean: SVector<T, D>,
+    alignee_scale: T,
+    target_mean: SVector<T, D>,
+    target_scale: T,
+    linear: SMatrix<T, D, D>,
+    translation: SVector<T, D>,
+    beta: T,
+    kernel_centers: Vec<SVector<T, D>>,
+    coefficients: Vec<SVector<T, D>>,
+    sigma_squared: T,
+}
+
+impl<T, const D: usize> DisplacementField<T, D>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    /// Maps the `point` from the alignee space into the target space.
+    pub fn transform_point(&self, point: &Point<T, D>) -> Point<T, D> {
+        let normalized = (point.coords - self.alignee_mean) / self.alignee_scale;
+
+        let two: T = nalgebra::convert(2.0);
+        let displacement = self
+            .kernel_centers
+            .iter()
+            .zip(self.coefficients.iter())
+            .fold(SVector::<T, D>::zeros(), |sum, (center, coefficient)| {
+                let weight = Float::exp(
+                    -(normalized - center).norm_squared() / (two * self.beta * self.beta),
+                );
+                sum + coefficient * weight
+            });
+
+        let transformed = self.linear * normalized + self.translation + displacement;
+
+        Point::from(transformed * self.target_scale + self.target_mean)
+    }
+
+    /// The vector that moves the `point` from the alignee space to its position in the target space.
+    pub fn displacement(&self, point: &Point<T, D>) -> SVector<T, D> {
+        self.transform_point(point) - point
+    }
+
+    /// Moves all points of the `cloud` with [`DisplacementField::transform_point`]. The normals are not changed.
+    pub fn transform_point_cloud(&self, cloud: &mut PointCloud<T, D>) {
+        for p in cloud.iter_mut() {
+            p.pos = self.transform_point(&p.pos);
+        }
+    }
+
+    /// The linear part of the rigid or affine transform in the original coordinates.
+    /// This is the identity for the non-rigid mode.
+    pub fn linear(&self) -> SMatrix<T, D, D> {
+        self.linear * (self.target_scale / self.alignee_scale)
+    }
+
+    /// The translation of the rigid or affine transform in the original coordinates.
+    pub fn translation(&self) -> SVector<T, D> {
+        (self.translation - self.linear * self.alignee_mean / self.alignee_scale)
+            * self.target_scale
+            + self.target_mean
+    }
+
+    /// The final variance of the Gaussian mixture model in the original coordinates of the target.
+    pub fn sigma_squared(&self) -> T {
+        self.sigma_squared
+    }
+}
+
+/// Centroid and the root mean squared distance to it.
+fn normalization<T, const D: usize>(cloud: &PointCloud<T, D>) -> (SVector<T, D>, T)
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mean = compute_centroid(cloud.iter().map(|p| p.pos));
+
+    let variance = cloud.iter().fold(T::zero(), |sum, p| {
+        sum + (p.pos.coords - mean).norm_squared()
+    }) / T::from_usize(cloud.len()).unwrap();
+
+    let scale = Float::sqrt(variance);
+    if scale > T::zero() {
+        (mean, scale)
+    } else {
+        (mean, T::one())
+    }
+}
+
+fn squared_distance_sum<T>(a: &DMatrix<T>, b: &DMatrix<T>) -> T
+where
+    T: Scalar + RealField + Copy,
+{
+    a.row_iter().fold(T::zero(), |sum, x| {
+        b.row_iter()
+            .fold(sum, |sum, y| sum + (x - y).norm_squared())
+    })
+}
+
+fn gaussian_kernel<T>(a: &DMatrix<T>, b: &DMatrix<T>, beta: T) -> DMatrix<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let two: T = nalgebra::convert(2.0);
+    DMatrix::from_fn(a.nrows(), b.nrows(), |i, j| {
+        Float::exp(-(a.row(i) - b.row(j)).norm_squared() / (two * beta * beta))
+    })
+}
+
+fn to_svector<T, const D: usize>(v: &DVector<T>) -> SVector<T, D>
+where
+    T: Scalar + Copy,
+{
+    SVector::from_iterator(v.iter().copied())
+}
+
+fn to_smatrix<T, const D: usize>(m: &DMatrix<T>) -> SMatrix<T, D, D>
+where
+    T: Scalar + Copy,
+{
+    SMatrix::from_iterator(m.iter().copied())
+}
+
+fn to_dmatrix<T, const D: usize>(m: &SMatrix<T, D, D>) -> DMatrix<T>
+where
+    T: Scalar + Copy,
+{
+    DMatrix::from_iterator(D, D, m.iter().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointCloudPoint;
+    use crate::test_utils::*;
+
+    fn max_distance(a: &PointCloud<f32, 3>, b: &PointCloud<f32, 3>) -> f32 {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a.pos - b.pos).norm())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn recovers_rigid_transform() {
+        let alignee = wavy_surface(4.0, 0.5);
+
+        let transform = Isometry3::new(Vector3::new(0.5, -0.3, 0.2), Vector3::new(0.2, -0.1, 0.3));
+        let target = transformed(&alignee, &transform);
+
+        let field = Cpd::new(CpdMode::Rigid {
+            estimate_scale: false,
+        })
+        .outlier_weight(0.0)
+        .register(&alignee, &target)
+        .unwrap();
+
+        let rotation = transform.rotation.to_rotation_matrix().into_inner();
+        assert!((field.linear() - rotation).norm() < 1e-3);
+        assert!((field.translation() - transform.translation.vector).norm() < 1e-3);
+    }
+
+    #[test]
+    fn recovers_rigid_transform_of_partial_overlap() {
+        let alignee = room_corner(2.0, 0.2);
+
+        // the target only overlaps with the half x <= 1 of the alignee
+        let half: PointCloud<f32, 3> = alignee.iter().filter(|p| p.pos.x <= 1.0).copied().collect();
+        let transform = Isometry3::new(
+            Vector3::new(0.1, -0.05, 0.05),
+            Vector3::new(0.05, -0.05, 0.1),
+        );
+        let target = transformed(&half, &transform);
+
+        let field = Cpd::new(CpdMode::Rigid {
+            estimate_scale: false,
+        })
+        .register(&alignee, &target)
+        .unwrap();
+
+        let rotation = transform.rotation.to_rotation_matrix().into_inner();
+        assert!((field.linear() - rotation).norm() < 1e-3);
+        assert!((field.translation() - transform.translation.vector).norm() < 1e-3);
+    }
+
+    #[test]
+    fn recovers_scale() {
+        let alignee = wavy_surface(4.0, 0.5);
+
+        let rotation = Rotation3::new(Vector3::new(0.1, 0.2, -0.1));
+        let mut target = alignee.clone();
+        for p in &mut target {
+            p.pos = Point3::from(rotation * p.pos.coords * 1.5 + Vector3::new(1.0, 2.0, 3.0));
+        }
+
+        let field = Cpd::rigid()
+            .outlier_weight(0.0)
+            .register(&alignee, &target)
+            .unwrap();
+
+        assert!((field.linear() - rotation.into_inner() * 1.5).norm() < 1e-3);
+
+        let mut aligned = alignee.clone();
+        field.transform_point_cloud(&mut aligned);
+
+        assert!(max_distance(&aligned, &target) < 1e-3);
+    }
+
+    #[test]
+    fn recovers_affine_transform() {
+        let alignee = wavy_surface(4.0, 0.5);
+
+        let linear = Matrix3::new(1.1, 0.2, 0.0, -0.1, 0.9, 0.1, 0.0, 0.3, 1.2);
+        let mut target = alignee.clone();
+        for p in &mut target {
+            p.pos = Point3::from(linear * p.pos.coords + Vector3::new(0.2, -0.1, 0.3));
+        }
+
+        let field = Cpd::affine()
+            .outlier_weight(0.0)
+            .register(&alignee, &target)
+            .unwrap();
+
+        assert!((field.linear() - linear).norm() < 1e-3);
+    }
+
+    #[test]
+    fn recovers_smooth_deformation() {
+        let alignee = wavy_surface(4.0, 0.5);
+
+        // bends the surface along x
+        let mut target = alignee.clone();
+        for p in &mut target {
+            p.pos.z += 0.05 * p.pos.x * p.pos.x;
+        }
+
+        let field = Cpd::non_rigid(2.0, 2.0)
+            .outlier_weight(0.0)
+            .register(&alignee, &target)
+            .unwrap();
+
+        let mut aligned = alignee.clone();
+        field.transform_point_cloud(&mut aligned);
+
+        assert!(max_distance(&aligned, &target) < 0.05);
+    }
+
+    #[test]
+    fn recovers_rotation_of_planar_cloud() {
+        // points on a right triangle in the plane z = 0
+        let alignee: PointCloud<f32, 3> = (0..12)
+            .flat_map(|i| (0..=i / 2).map(move |j| (i as f32 * 0.25, j as f32 * 0.25)))
+            .map(|(x, y)| PointCloudPoint::from_pos(Point3::new(x, y, 0.0)))
+            .collect();
+
+        let transform = Isometry3::new(Vector3::new(0.1, 0.2, 0.3), Vector3::new(0.3, 0.2, -0.4));
+        let target = transformed(&alignee, &transform);
+
+        let field = Cpd::new(CpdMode::Rigid {
+            estimate_scale: false,
+        })
+        .outlier_weight(0.0)
+        .register(&alignee, &target)
+        .unwrap();
+
+        let rotation = transform.rotation.to_rotation_matrix().into_inner();
+        assert!((field.linear() - rotation).norm() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_empty_point_clouds() {
+        let cloud = wavy_surface(1.0, 0.5);
+
+        assert!(Cpd::rigid().register(&PointCloud::new(), &cloud).is_none());
+        assert!(Cpd::rigid().register(&cloud, &PointCloud::new()).is_none());
+    }
+}
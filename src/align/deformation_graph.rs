@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+
+use kdtree::KdTree;
+use kdtree::distance::squared_euclidean;
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::correspondence::{CorrespondenceEstimator, Correspondences};
+use crate::filter_points::AcceptAll;
+use crate::{PointCloud, PointCloudPoint, kd_tree_of_point_cloud};
+
+/// A node of a [`DeformationGraph`]. It deforms the space around its `position` with the affine transform
+/// `p -> affine * (p - position) + position + translation`.
+#[derive(Clone, Debug)]
+pub struct DeformationNode<T: Scalar> {
+    pub position: Point3<T>,
+    pub affine: Matrix3<T>,
+    pub translation: Vector3<T>,
+}
+
+/// Embedded deformation graph for non-rigid registration.
+///
+/// The nodes are sampled from the alignee on a voxel grid with the size `node_spacing`. Every node has an affine
+/// transform and every point is deformed by the blended transforms of its `k_nearest_nodes` nearest nodes.
+/// [`DeformationGraph::fit`] runs a non-rigid ICP: in every iteration the correspondences between the deformed
+/// alignee and the target are found with any [`CorrespondenceEstimator`] and the node transforms are solved with
+/// Gauss-Newton. Besides fitting the correspondences two regularizers keep the deformation plausible:
+/// the `smoothness_weight` makes neighboring nodes agree where they overlap and the `rigidity_weight` keeps the affine
+/// transforms close to rotations (as-rigid-as-possible).
+///
+/// The linear systems are dense in the number of nodes, so keep the node count in the hundreds.
+///
+/// See this [paper from Sumner et al.](https://doi.org/10.1145/1276377.1276478)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::PointCloud;
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::deformation_graph::DeformationGraph;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let mut graph = DeformationGraph::new(&alignee_cloud, 0.5)
+///     .smoothness_weight(1.0)
+///     .rigidity_weight(1.0);
+///
+/// let mean_squared_distance = graph.fit(
+///     &alignee_cloud,
+///     &target_cloud,
+///     &mut NearestNeighbor::new(&target_cloud),
+///     30,
+/// );
+///
+/// let mut deformed_cloud = alignee_cloud.clone();
+/// graph.warp_point_cloud(&mut deformed_cloud);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DeformationGraph<T: Scalar> {
+    nodes: Vec<DeformationNode<T>>,
+    edges: Vec<(usize, usize)>,
+    node_tree: KdTree<T, usize, Vec<T>>,
+    k_nearest_nodes: usize,
+    smoothness_weight: T,
+    rigidity_weight: T,
+}
+
+impl<T> DeformationGraph<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    pub fn new(alignee: &PointCloud<T, 3>, node_spacing: T) -> Self {
+        let mut voxels: HashMap<[i64; 3], Point3<T>> = HashMap::new();
+        for p in alignee {
+            if let Some(key) = voxel_key(node_spacing, &p.pos) {
+                voxels.entry(key).or_insert(p.pos);
+            }
+        }
+
+        let mut keys: Vec<_> = voxels.keys().copied().collect();
+        keys.sort_unstable();
+
+        let nodes: Vec<_> = keys
+            .iter()
+            .map(|key| DeformationNode {
+                position: voxels[key],
+                affine: Matrix3::identity(),
+                translation: Vector3::zeros(),
+            })
+            .collect();
+
+        let node_cloud: PointCloud<T, 3> = nodes
+            .iter()
+            .map(|node| PointCloudPoint::from_pos(node.position))
+            .collect();
+        let node_tree = kd_tree_of_point_cloud(&node_cloud);
+
+        let mut graph = Self {
+            nodes,
+            edges: vec![],
+            node_tree,
+            k_nearest_nodes: 4,
+            smoothness_weight: T::one(),
+            rigidity_weight: T::one(),
+        };
+        graph.connect_nodes();
+        graph
+    }
+
+    /// Number of nodes that influence every point and that every node is connected to. Defaults to `4`.
+    pub fn k_nearest_nodes(mut self, k_nearest_nodes: usize) -> Self {
+        self.k_nearest_nodes = k_nearest_nodes.max(1);
+        self.connect_nodes();
+        self
+    }
+
+    /// Weight of the regularizer that makes neighboring nodes agree where they overlap. Defaults to `1.0`.
+    pub fn smoothness_weight(mut self, smoothness_weight: T) -> Self {
+        self.smoothness_weight = smoothness_weight;
+        self
+    }
+
+    /// Weight of the regularizer that keeps the node transforms close to rotations. Defaults to `1.0`.
+    pub fn rigidity_weight(mut self, rigidity_weight: T) -> Self {
+        self.rigidity_weight = rigidity_weight;
+        self
+    }
+
+    pub fn nodes(&self) -> &[DeformationNode<T>] {
+        &self.nodes
+    }
+
+    /// Pairs of node indices that are regularized against each other.
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
+    /// Deforms the `point` with the current node transforms.
+    pub fn warp_point(&self, point: &Point3<T>) -> Point3<T> {
+        self.warp_with_influences(point, &self.influences(point))
+    }
+
+    /// Deforms the positions and normals of all points of the `cloud` with the current node transforms.
+    pub fn warp_point_cloud(&self, cloud: &mut PointCloud<T, 3>) {
+        for p in cloud.iter_mut() {
+            let influences = self.influences(&p.pos);
+            self.warp_point_with_influences(p, &influences);
+        }
+    }
+
+    /// Runs a non-rigid ICP that deforms the `alignee` onto the `target` for `max_iterations` iterations.
+    ///
+    /// The `alignee` has to be the undeformed point cloud that the graph was built from (or one in the same space).
+    /// Returns the mean squared distance of the correspondences found in the last iteration.
+    pub fn fit<'a, TG, CE>(
+        &mut self,
+        alignee: &PointCloud<T, 3>,
+        target: &TG,
+        correspondence_estimator: &mut CE,
+        max_iterations: usize,
+    ) -> T
+    where
+        CE: CorrespondenceEstimator<'a, T, TG, 3>,
+    {
+        const GAUSS_NEWTON_ITERATIONS: usize = 3;
+
+        let influences: Vec<_> = alignee.iter().map(|p| self.influences(&p.pos)).collect();
+
+        let mut warped = alignee.clone();
+        let mut mean_squared_distance = T::zero();
+
+        correspondence_estimator.reset();
+
+        for _ in 0..max_iterations {
+            for ((w, p), influences) in warped.iter_mut().zip(alignee).zip(influences.iter()) {
+                *w = *p;
+                self.warp_point_with_influences(w, influences);
+            }
+
+            let Correspondences {
+                alignee_point_cloud,
+                corresponding_target_point_cloud,
+                alignee_to_target_distances,
+                ..
+            } = correspondence_estimator.find_correspondences(&warped, target, &mut AcceptAll);
+
+            let correspondences: Vec<_> = alignee_point_cloud
+                .masked_and_ordered_to_plain_index
+                .iter()
+                .zip(corresponding_target_point_cloud.iter())
+                .map(|(i, t)| (*i, t.pos, alignee[*i].weight * t.weight))
+                .collect();
+
+            if correspondences.is_empty() {
+                break;
+            }
+
+            mean_squared_distance = alignee_to_target_distances
+                .iter()
+                .fold(T::zero(), |sum, d| sum + *d)
+                / T::from_usize(alignee_to_target_distances.len().max(1)).unwrap();
+
+            for _ in 0..GAUSS_NEWTON_ITERATIONS {
+                if !self.gauss_newton_step(alignee, &influences, &correspondences) {
+                    break;
+                }
+            }
+        }
+
+        mean_squared_distance
+    }
+
+    /// Performs one Gauss-Newton step on the parameters of all nodes. Returns `false` if the system couldn't be solved.
+    ///
+    /// The parameters of every node are the columns of its affine matrix followed by its translation.
+    #[allow(non_snake_case)]
+    fn gauss_newton_step(
+        &mut self,
+        alignee: &PointCloud<T, 3>,
+        influences: &[Vec<(usize, T)>],
+        correspondences: &[(usize, Point3<T>, T)],
+    ) -> bool {
+        let dim = self.nodes.len() * 12;
+        if dim == 0 {
+            return false;
+        }
+
+        let mut H = DMatrix::<T>::zeros(dim, dim);
+        let mut g = DVector::<T>::zeros(dim);
+
+        // the terms are normalized by their number of residuals to make the weights independent of the sizes
+        let count = |n: usize| T::from_usize(n.max(1)).unwrap();
+
+        let fit_weight = T::one() / count(correspondences.len());
+        for (i, target_pos, weight) in correspondences {
+            let point = &alignee[*i].pos;
+
+            let mut residual = -target_pos.coords;
+            let mut jacobians = Vec::with_capacity(influences[*i].len());
+
+            for (j, influence) in &influences[*i] {
+                let node = &self.nodes[*j];
+                let d = point - node.position;
+
+                residual +=
+                    (node.affine * d + node.position.coords + node.translation) * *influence;
+                jacobians.push((*j, parameter_jacobian(&d, T::one(), *influence)));
+            }
+
+            add_residual(&mut H, &mut g, &jacobians, &residual, *weight * fit_weight);
+        }
+
+        let smoothness_weight = self.smoothness_weight / count(self.edges.len());
+        for (j, k) in &self.edges {
+            let (node_j, node_k) = (&self.nodes[*j], &self.nodes[*k]);
+            let e = node_k.position - node_j.position;
+
+            let residual = node_j.affine * e + node_j.position.coords + node_j.translation
+                - node_k.position.coords
+                - node_k.translation;
+
+            let jacobians = [
+                (*j, parameter_jacobian(&e, T::one(), T::one())),
+                (
+                    *k,
+                    parameter_jacobian(&Vector3::zeros(), -T::one(), T::one()),
+                ),
+            ];
+
+            add_residual(&mut H, &mut g, &jacobians, &residual, smoothness_weight);
+        }
+
+        let rigidity_weight = self.rigidity_weight / count(self.nodes.len());
+        let two: T = nalgebra::convert(2.0);
+        for (j, node) in self.nodes.iter().enumerate() {
+            let c1 = node.affine.column(0).into_owned();
+            let c2 = node.affine.column(1).into_owned();
+            let c3 = node.affine.column(2).into_owned();
+
+            let residual = Vector6::new(
+                c1.dot(&c2),
+                c1.dot(&c3),
+                c2.dot(&c3),
+                c1.dot(&c1) - T::one(),
+                c2.dot(&c2) - T::one(),
+                c3.dot(&c3) - T::one(),
+            );
+
+            let mut J = SMatrix::<T, 6, 12>::zeros();
+            let mut set = |row: usize, column: usize, v: &Vector3<T>| {
+                J.fixed_view_mut::<1, 3>(row, column * 3)
+                    .copy_from(&v.transpose());
+            };
+            set(0, 0, &c2);
+            set(0, 1, &c1);
+            set(1, 0, &c3);
+            set(1, 2, &c1);
+            set(2, 1, &c3);
+            set(2, 2, &c2);
+            set(3, 0, &(c1 * two));
+            set(4, 1, &(c2 * two));
+            set(5, 2, &(c3 * two));
+
+            add_residual(&mut H, &mut g, &[(j, J)], &residual, rigidity_weight);
+        }
+
+        // small damping keeps nodes without any residual and unconstrained directions, like the rotation around
+        // collinear points, solvable. It's relative to the system so it isn't lost to rounding for f32.
+        let max_diagonal = H
+            .diagonal()
+            .iter()
+            .fold(T::zero(), |max, v| Float::max(max, *v));
+        let damping = max_diagonal * Float::sqrt(T::default_epsilon());
+        for i in 0..dim {
+            H[(i, i)] += damping;
+        }
+
+        let Some(cholesky) = H.cholesky() else {
+            return false;
+        };
+        let delta = -cholesky.solve(&g);
+
+        for (j, node) in self.nodes.iter_mut().enumerate() {
+            let offset = j * 12;
+            for c in 0..3 {
+                for r in 0..3 {
+                    node.affine[(r, c)] += delta[offset + c * 3 + r];
+                }
+            }
+            for r in 0..3 {
+                node.translation[r] += delta[offset + 9 + r];
+            }
+        }
+
+        true
+    }
+
+    /// Connects every node to its `k_nearest_nodes` nearest other nodes.
+    fn connect_nodes(&mut self) {
+        self.edges = self
+            .nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(j, node)| {
+                self.node_tree
+                    .nearest(
+                        node.position.coords.as_slice(),
+                        self.k_nearest_nodes + 1,
+                        &squared_euclidean,
+                    )
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(move |(_, k)| **k != j)
+                    .map(move |(_, k)| (j, *k))
+            })
+            .collect();
+    }
+
+    /// The nodes that influence the `point` together with their normalized weights.
+    fn influences(&self, point: &Point3<T>) -> Vec<(usize, T)> {
+        let Ok(neighbors) = self.node_tree.nearest(
+            point.coords.as_slice(),
+            self.k_nearest_nodes + 1,
+            &squared_euclidean,
+        ) else {
+            return vec![];
+        };
+
+        let Some(((_, nearest), (max_squared_distance, _))) =
+            neighbors.first().zip(neighbors.last())
+        else {
+            return vec![];
+        };
+
+        // the weights fall off to zero at the distance of the (k + 1)-th nearest node
+        let max_distance = Float::sqrt(*max_squared_distance);
+        let count = self.k_nearest_nodes.min(neighbors.len() - 1);
+
+        let mut influences: Vec<_> = neighbors
+            .iter()
+            .take(count)
+            .map(|(squared_distance, idx)| {
+                let w = T::one() - Float::sqrt(*squared_distance) / max_distance;
+                (**idx, w * w)
+            })
+            .collect();
+
+        let sum = influences.iter().fold(T::zero(), |sum, (_, w)| sum + *w);
+        if sum.partial_cmp(&T::zero()) != Some(std::cmp::Ordering::Greater) {
+            return vec![(**nearest, T::one())];
+        }
+
+        for (_, w) in influences.iter_mut() {
+            *w /= sum;
+        }
+
+        influences
+    }
+
+    fn warp_with_influences(&self, point: &Point3<T>, influences: &[(usize, T)]) -> Point3<T> {
+        if influences.is_empty() {
+            return *point;
+        }
+
+        Point3::from(
+            influences
+                .iter()
+                .fold(Vector3::zeros(), |sum, (j, influence)| {
+                    let node = &self.nodes[*j];
+                    sum + (node.affine * (point - node.position)
+                        + node.position.coords
+                        + node.translation)
+                        * *influence
+                }),
+        )
+    }
+
+    fn warp_point_with_influences(
+        &self,
+        point: &mut PointCloudPoint<T, 3>,
+        influences: &[(usize, T)],
+    ) {
+        point.pos = self.warp_with_influences(&point.pos, influences);
+
+        if let Some(norm) = point.norm {
+            // normals transform with the inverse transpose of the affine transforms
+            let warped_norm = influences
+                .iter()
+                .fold(Vector3::zeros(), |sum, (j, influence)| {
+                    let affine = &self.nodes[*j].affine;
+                    let normal_matrix = affine
+                        .try_inverse()
+                        .map(|inv| inv.transpose())
+                        .unwrap_or(*affine);
+                    sum + normal_matrix * norm * *influence
+                });
+
+            point.norm = warped_norm
+                .try_normalize(T::default_epsilon())
+                .or(Some(norm));
+        }
+    }
+}
+
+/// Jacobian of `A * d + t_scale * t` (scaled by `scale`) w.r.t. the columns of `A` and `t`.
+fn parameter_jacobian<T>(d: &Vector3<T>, t_scale: T, scale: T) -> SMatrix<T, 3, 12>
+where
+    T: Scalar + RealField + Copy,
+{
+    let mut jacobian = SMatrix::<T, 3, 12>::zeros();
+    for c in 0..3 {
+        jacobian
+            .fixed_view_mut::<3, 3>(0, c * 3)
+            .fill_diagonal(d[c] * scale);
+    }
+    jacobian
+        .fixed_view_mut::<3, 3>(0, 9)
+        .fill_diagonal(t_scale * scale);
+    jacobian
+}
+
+/// Adds the weighted normal equations of a residual that depends on the parameters of the given nodes.
+#[allow(non_snake_case)]
+fn add_residual<T, const R: usize>(
+    H: &mut DMatrix<T>,
+    g: &mut DVector<T>,
+    jacobians: &[(usize, SMatrix<T, R, 12>)],
+    residual: &SVector<T, R>,
+    weight: T,
+) where
+    T: Scalar + RealField + Copy,
+{
+    for (a, J_a) in jacobians {
+        let JT_a = J_a.transpose() * weight;
+
+        let mut g_a = g.fixed_rows_mut::<12>(a * 12);
+        g_a += JT_a * residual;
+
+        for (b, J_b) in jacobians {
+            let mut H_ab = H.fixed_view_mut::<12, 12>(a * 12, b * 12);
+            H_ab += JT_a * J_b;
+        }
+    }
+}
+
+fn voxel_key<T>(voxel_size: T, point: &Point3<T>) -> Option<[i64; 3]>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mut key = [0; 3];
+    for (k, c) in key.iter_mut().zip(point.iter()) {
+        let c: f64 = Float::floor(*c / voxel_size).to_subset()?;
+        if !c.is_finite() {
+            return None;
+        }
+        *k = c as i64;
+    }
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::correspondence::NearestNeighbor;
+    use crate::test_utils::*;
+
+    fn fit(
+        graph: &mut DeformationGraph<f32>,
+        alignee: &PointCloud<f32, 3>,
+        target: &PointCloud<f32, 3>,
+    ) -> f32 {
+        graph.fit(alignee, target, &mut NearestNeighbor::new(target), 10)
+    }
+
+    fn warped(graph: &DeformationGraph<f32>, alignee: &PointCloud<f32, 3>) -> PointCloud<f32, 3> {
+        let mut cloud = alignee.clone();
+        graph.warp_point_cloud(&mut cloud);
+        cloud
+    }
+
+    #[test]
+    fn starts_as_identity() {
+        let alignee = wavy_surface(4.0, 0.25);
+        let graph = DeformationGraph::new(&alignee, 2.0);
+
+        assert!(!graph.nodes().is_empty());
+        for (w, p) in warped(&graph, &alignee).iter().zip(&alignee) {
+            assert!((w.pos - p.pos).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn recovers_translation() {
+        let alignee = wavy_surface(4.0, 0.5);
+        let target = transformed(&alignee, &Isometry3::translation(0.05, -0.03, 0.1));
+
+        let mut graph = DeformationGraph::new(&alignee, 2.0);
+        let mean_squared_distance = fit(&mut graph, &alignee, &target);
+
+        assert!(mean_squared_distance < 1e-4);
+        for (w, t) in warped(&graph, &alignee).iter().zip(&target) {
+            assert!((w.pos - t.pos).norm() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn recovers_smooth_bend() {
+        let alignee = wavy_surface(4.0, 0.5);
+
+        // bends the surface along x
+        let bend = |p: &Point3<f32>| 0.05 * p.x * p.x;
+        let mut target = alignee.clone();
+        for p in &mut target {
+            p.pos.z += bend(&p.pos);
+        }
+
+        // the points may slide along the surface, so only the vertical distance to it is measured
+        let mean_deviation = |cloud: &PointCloud<f32, 3>| {
+            cloud
+                .iter()
+                .map(|p| (p.pos.z - 0.5 * p.pos.x.sin() * p.pos.y.cos() - bend(&p.pos)).abs())
+                .sum::<f32>()
+                / cloud.len() as f32
+        };
+
+        let mut graph = DeformationGraph::new(&alignee, 2.0);
+        fit(&mut graph, &alignee, &target);
+
+        // the nearest neighbors pull the border points inwards, so the bend isn't recovered exactly
+        assert!(mean_deviation(&warped(&graph, &alignee)) < 0.1 * mean_deviation(&alignee));
+    }
+
+    #[test]
+    fn handles_single_node() {
+        let alignee = wavy_surface(1.0, 0.25);
+        let target = transformed(&alignee, &Isometry3::translation(0.0, 0.0, 0.05));
+
+        let mut graph = DeformationGraph::new(&alignee, 10.0);
+        assert_eq!(graph.nodes().len(), 1);
+
+        fit(&mut graph, &alignee, &target);
+
+        for (w, t) in warped(&graph, &alignee).iter().zip(&target) {
+            assert!((w.pos - t.pos).norm() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn handles_collinear_points() {
+        let alignee: PointCloud<f32, 3> = (0..40)
+            .map(|i| PointCloudPoint::from_pos(Point3::new(i as f32 * 0.1, 0.0, 0.0)))
+            .collect();
+        let target = transformed(&alignee, &Isometry3::translation(0.0, 0.05, 0.02));
+
+        let mut graph = DeformationGraph::new(&alignee, 1.0);
+        fit(&mut graph, &alignee, &target);
+
+        for w in warped(&graph, &alignee) {
+            assert!(
+                (w.pos.y - 0.05).abs() < 1e-2 && (w.pos.z - 0.02).abs() < 1e-2,
+                "{w:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn handles_empty_alignee() {
+        let target = wavy_surface(1.0, 0.25);
+
+        let mut graph = DeformationGraph::new(&PointCloud::new(), 1.0);
+        assert!(graph.nodes().is_empty());
+
+        assert_eq!(fit(&mut graph, &PointCloud::new(), &target), 0.0);
+    }
+}
@@ -2,8 +2,13 @@ use crate::convergence::ConvergenceCriterion;
 use crate::correspondence::{CorrespondenceEstimator, Correspondences};
 use crate::filter_points::{AcceptAll, PointFilter};
 use crate::reject_outliers::{KeepAll, OutlierRejector};
-use crate::transform_estimation::TransformEstimator;
-use crate::{MaskedPointCloud, PointCloudPoint, ToPointCloud, transform_point_cloud};
+use crate::transform_estimation::{
+    CovarianceEstimator, Fallible, NoCovariance, TransformEstimator,
+};
+use crate::{
+    IcpError, MaskedPointCloud, PointCloud, PointCloudPoint, ToPointCloud, transform_point_cloud,
+    validate_point_cloud,
+};
 use cfg_if::cfg_if;
 use nalgebra::*;
 use num_traits::{Float, One, Zero};
@@ -15,8 +20,19 @@ use tracing::info;
 /// The ICP algorithm for aligning two point clouds.
 ///
 /// This is the main struct for this crate.
-pub struct Icp<'a, T, M, TG, CE, ET, IC, const D: usize, FP = AcceptAll, RO = KeepAll>
-where
+pub struct Icp<
+    'a,
+    T,
+    M,
+    TG,
+    CE,
+    ET,
+    IC,
+    const D: usize,
+    FP = AcceptAll,
+    RO = KeepAll,
+    CV = NoCovariance,
+> where
     T: Scalar + RealField + Float + One + Zero + Debug,
     f32: From<T>,
     M: One
@@ -31,8 +47,11 @@ where
     estimate_step_transform: ET,
     is_converged: IC,
     max_iterations: usize,
+    initial_transform: M,
+    record_history: bool,
     filter_points: FP,
     reject_outliers: RO,
+    estimate_covariance: CV,
 
     _marker: PhantomData<(T, M, TG)>,
     _lt: &'a (),
@@ -79,11 +98,14 @@ where
     pub fn new() -> Self {
         Self {
             max_iterations: 50,
+            initial_transform: M::one(),
+            record_history: false,
             correspondence_estimator: (),
             estimate_step_transform: (),
             is_converged: (),
             filter_points: AcceptAll,
             reject_outliers: KeepAll,
+            estimate_covariance: NoCovariance,
 
             _marker: PhantomData,
             _lt: &(),
@@ -91,7 +113,8 @@ where
     }
 }
 
-impl<'a, T, M, TG, CE, ET, IC, const D: usize, FP, RO> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO>
+impl<'a, T, M, TG, CE, ET, IC, const D: usize, FP, RO, CV>
+    Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO, CV>
 where
     T: Scalar + RealField + Float + One + Zero + Debug,
     f32: From<T>,
@@ -105,6 +128,7 @@ where
     IC: ConvergenceCriterion<T, M>,
     FP: PointFilter<T, D>,
     RO: OutlierRejector<T, D>,
+    CV: CovarianceEstimator<T, M, D>,
 {
     /// Estimates the transform that the `alignee` point cloud has to be transformed by to match the
     /// `target` using the iterative closest point algorithm.
@@ -141,9 +165,154 @@ where
         alignee: impl ToPointCloud<T, D>,
         target: &'a TG,
     ) -> (M, T) {
-        let mut transform = M::one();
+        let result = self.register(alignee, target);
+
+        (result.transform, result.error)
+    }
+
+    /// Same as [`Icp::estimate_transform`] but also returns the covariance of the estimated transform.
+    ///
+    /// The covariance is computed once after the last iteration by the covariance estimator set with
+    /// [`Icp::estimate_covariance`]. It uses the correspondences of the last iteration with the residuals after its
+    /// step, so it describes the uncertainty of the returned transform. It is `None` if no covariance estimator was
+    /// set, the registration terminated with an error or the covariance estimator failed.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use modern_icp::{Icp, PointCloud};
+    /// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    /// # use modern_icp::transform_estimation::point_to_plane_lls;
+    /// # use modern_icp::convergence::same_squared_distance_error;
+    /// #
+    /// # let alignee_cloud = PointCloud::<f32, 3>::new();
+    /// # let target_cloud = PointCloud::<f32, 3>::new();
+    /// #
+    /// let (alignee_transform, error_sum, covariance) = Icp::new()
+    ///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+    ///     .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+    ///     .is_converged(same_squared_distance_error(0.1))
+    ///     .estimate_covariance(point_to_plane_lls::estimate_covariance)
+    ///     .estimate_transform_with_covariance(alignee_cloud, &target_cloud);
+    /// ```
+    pub fn estimate_transform_with_covariance(
+        &mut self,
+        alignee: impl ToPointCloud<T, D>,
+        target: &'a TG,
+    ) -> (M, T, Option<CV::Covariance>) {
+        let result = self.register(alignee, target);
+
+        (result.transform, result.error, result.covariance)
+    }
+
+    /// Same as [`Icp::estimate_transform`] but returns an [`IcpResult`] that tells why the ICP terminated and
+    /// how good the alignment is.
+    ///
+    /// Use the [`IcpResult::termination_reason`] together with the [`IcpResult::rmse`] and the
+    /// [`IcpResult::fitness`] to tell good alignments from bad ones. Enable [`Icp::record_history`] to get the
+    /// metrics of every iteration.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use modern_icp::{Icp, PointCloud};
+    /// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    /// # use modern_icp::transform_estimation::point_to_plane_lls;
+    /// # use modern_icp::convergence::same_squared_distance_error;
+    /// # use modern_icp::TerminationReason;
+    /// #
+    /// # let alignee_cloud = PointCloud::<f32, 3>::new();
+    /// # let target_cloud = PointCloud::<f32, 3>::new();
+    /// #
+    /// let result = Icp::new()
+    ///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+    ///     .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+    ///     .is_converged(same_squared_distance_error(0.1))
+    ///     .register(alignee_cloud, &target_cloud);
+    ///
+    /// let is_good_alignment =
+    ///     result.termination_reason == TerminationReason::Converged && result.fitness > 0.8;
+    /// ```
+    pub fn register(
+        &mut self,
+        alignee: impl ToPointCloud<T, D>,
+        target: &'a TG,
+    ) -> IcpResult<T, M, CV::Covariance> {
+        let (result, _) = self.run(alignee.to_point_cloud(), target);
 
-        let mut aligned = alignee.to_point_cloud();
+        result
+    }
+
+    /// Same as [`Icp::estimate_transform`] but returns an error instead of a possibly bad transform.
+    ///
+    /// It fails with
+    /// - [`IcpError::EmptyPointCloud`] if the alignee has no points,
+    /// - [`IcpError::NonFiniteCoordinates`] if an alignee point has NaN or infinite coordinates or normals,
+    /// - [`IcpError::NoCorrespondences`] if no correspondences were left in an iteration and
+    /// - the error of the step transform estimator if it failed. Estimators that return an `Option` fail with
+    ///   [`IcpError::EstimationFailed`]. Set estimators that return a `Result` with
+    ///   [`Icp::try_estimate_step_transform`] to get their errors.
+    ///
+    /// The target is checked with [`CorrespondenceEstimator::validate_target`], so the KD-Tree based estimators
+    /// reject targets with NaN or infinite coordinates instead of silently leaving those points out.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use modern_icp::{Icp, IcpError, PointCloud};
+    /// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    /// # use modern_icp::transform_estimation::point_to_plane_lls;
+    /// # use modern_icp::convergence::same_squared_distance_error;
+    /// #
+    /// # let alignee_cloud = PointCloud::<f32, 3>::new();
+    /// # let target_cloud = PointCloud::<f32, 3>::new();
+    /// #
+    /// let result = Icp::new()
+    ///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+    ///     .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+    ///     .is_converged(same_squared_distance_error(0.1))
+    ///     .try_estimate_transform(alignee_cloud, &target_cloud);
+    ///
+    /// assert_eq!(result.unwrap_err(), IcpError::EmptyPointCloud);
+    /// ```
+    pub fn try_estimate_transform(
+        &mut self,
+        alignee: impl ToPointCloud<T, D>,
+        target: &'a TG,
+    ) -> Result<(M, T), IcpError> {
+        let result = self.try_register(alignee, target)?;
+
+        Ok((result.transform, result.error))
+    }
+
+    /// Same as [`Icp::register`] but fails like [`Icp::try_estimate_transform`].
+    pub fn try_register(
+        &mut self,
+        alignee: impl ToPointCloud<T, D>,
+        target: &'a TG,
+    ) -> Result<IcpResult<T, M, CV::Covariance>, IcpError> {
+        let alignee = alignee.to_point_cloud();
+        validate_point_cloud(&alignee)?;
+        self.correspondence_estimator.validate_target()?;
+
+        match self.run(alignee, target) {
+            (_, Some(error)) => Err(error),
+            (result, None) => Ok(result),
+        }
+    }
+
+    /// The ICP loop. Returns the error that terminated it early together with the result.
+    fn run(
+        &mut self,
+        mut aligned: PointCloud<T, D>,
+        target: &'a TG,
+    ) -> (IcpResult<T, M, CV::Covariance>, Option<IcpError>) {
+        let mut transform = self.initial_transform.clone();
+
+        self.correspondence_estimator.reset();
+        self.estimate_step_transform.reset();
+
+        transform_point_cloud(&mut aligned, transform.clone());
 
         cfg_if! {
             if #[cfg(feature = "rerun")]{
@@ -166,6 +335,20 @@ where
 
         let mut distance_error = T::zero();
 
+        let mut termination_reason = TerminationReason::MaxIterations;
+        let mut failure = None;
+        let mut iterations = 0;
+        let mut metrics = IterationMetrics {
+            rmse: T::zero(),
+            fitness: T::zero(),
+            correspondence_count: 0,
+            error: T::zero(),
+        };
+        let mut history = vec![];
+
+        // the correspondences of the last iteration and its step, kept for the covariance of the final transform
+        let mut last_correspondences = None;
+
         for i in 0..self.max_iterations {
             #[cfg(feature = "rerun")]
             {
@@ -325,6 +508,14 @@ where
                 }
             }
 
+            // soft correspondence estimators pair an alignee point with several target points
+            let mut has_correspondence = vec![false; aligned.len()];
+            for i in &alignee_point_cloud.masked_and_ordered_to_plain_index {
+                has_correspondence[*i] = true;
+            }
+            let alignee_inlier_count = has_correspondence.iter().filter(|c| **c).count();
+            let alignee_to_target_count = alignee_point_cloud.len();
+
             let masked_alignee = &mut alignee_point_cloud;
             masked_alignee.extend(&corresponding_alignee_point_cloud);
 
@@ -333,21 +524,53 @@ where
 
             if masked_alignee.is_empty() || masked_target.is_empty() {
                 info!("Correspondence estimation gave empty result. Terminating.");
+                termination_reason = TerminationReason::EmptyCorrespondences;
+                failure = Some(IcpError::NoCorrespondences);
                 break;
             }
 
             let step_transform =
                 match self
                     .estimate_step_transform
-                    .estimate(masked_alignee, masked_target, i)
+                    .try_estimate(masked_alignee, masked_target, i)
                 {
-                    Some(step_transform) => step_transform,
-                    None => {
-                        info!("Step transform estimation failed. Terminating.");
+                    Ok(step_transform) => step_transform,
+                    Err(error) => {
+                        info!("Step transform estimation failed: {error}. Terminating.");
+                        termination_reason = TerminationReason::EstimationFailed;
+                        failure = Some(error);
                         break;
                     }
                 };
 
+            if CV::ESTIMATES_COVARIANCE {
+                let alignee: PointCloud<T, D> = masked_alignee.iter().copied().collect();
+                let target: PointCloud<T, D> = masked_target.iter().copied().collect();
+
+                last_correspondences = Some((alignee, target, step_transform.clone()));
+            }
+
+            let correspondence_count = masked_alignee.len();
+
+            // like the fitness, the RMSE counts every alignee point once with its closest target point of the
+            // alignee-to-target correspondences
+            let mut closest_squared_distances: Vec<Option<T>> = vec![None; aligned.len()];
+            for ((a, a_idx), t) in masked_alignee
+                .iter()
+                .zip(masked_alignee.masked_and_ordered_to_plain_index.iter())
+                .zip(masked_target.iter())
+                .take(alignee_to_target_count)
+            {
+                let squared_distance = (step_transform.clone() * a.pos - t.pos).norm_squared();
+                let closest = &mut closest_squared_distances[*a_idx];
+                *closest =
+                    Some(closest.map_or(squared_distance, |c| Float::min(c, squared_distance)));
+            }
+            let squared_distance_sum = closest_squared_distances
+                .iter()
+                .flatten()
+                .fold(T::zero(), |sum, d| sum + *d);
+
             transform_point_cloud(&mut aligned, step_transform.clone());
 
             transform = step_transform.clone() * transform;
@@ -358,22 +581,68 @@ where
                 crate::rr_log_cloud("alignee", &aligned);
             }
 
-            if self.is_converged.is_converged(
+            let is_converged = self.is_converged.is_converged(
                 &alignee_to_target_distances,
                 &target_to_alignee_distances,
                 &step_transform,
                 &mut distance_error,
                 i,
-            ) {
+            );
+
+            iterations = i + 1;
+            metrics = IterationMetrics {
+                rmse: if alignee_inlier_count == 0 {
+                    T::zero()
+                } else {
+                    Float::sqrt(
+                        squared_distance_sum / nalgebra::convert(alignee_inlier_count as f64),
+                    )
+                },
+                fitness: nalgebra::convert::<_, T>(alignee_inlier_count as f64)
+                    / nalgebra::convert(aligned.len() as f64),
+                correspondence_count,
+                error: distance_error,
+            };
+
+            if self.record_history {
+                history.push(metrics);
+            }
+
+            if is_converged {
+                termination_reason = TerminationReason::Converged;
                 break;
             }
         }
 
-        (transform, distance_error)
+        let covariance = match last_correspondences {
+            Some((alignee, target, step_transform)) if failure.is_none() => {
+                self.estimate_covariance.estimate_covariance(
+                    &mut MaskedPointCloud::new(&alignee),
+                    &mut MaskedPointCloud::new(&target),
+                    &step_transform,
+                )
+            }
+            _ => None,
+        };
+
+        let result = IcpResult {
+            transform,
+            error: distance_error,
+            termination_reason,
+            iterations,
+            rmse: metrics.rmse,
+            fitness: metrics.fitness,
+            correspondence_count: metrics.correspondence_count,
+            covariance,
+            history,
+        };
+
+        (result, failure)
     }
 }
 
-impl<'a, T, M, TG, CE, ET, IC, const D: usize, FP, RO> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO>
+impl<'a, T, M, TG, CE, ET, IC, const D: usize, FP, RO, CV>
+    Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO, CV>
 where
     T: Scalar + RealField + Float + One + Zero + Debug,
     f32: From<T>,
@@ -396,6 +665,51 @@ where
         }
     }
 
+    /// Sets the initial guess of the transform, e.g. a pose predicted by odometry.
+    ///
+    /// The alignee is transformed by it before the first iteration and the returned transform includes it,
+    /// i.e. the result is the full transform from the original alignee to the target.
+    ///
+    /// Defaults to the identity.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use modern_icp::{Icp, PointCloud};
+    /// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    /// # use modern_icp::transform_estimation::point_to_plane_lls;
+    /// # use modern_icp::convergence::same_squared_distance_error;
+    /// # use nalgebra::Isometry3;
+    /// #
+    /// # let alignee_cloud = PointCloud::<f32, 3>::new();
+    /// # let target_cloud = PointCloud::<f32, 3>::new();
+    /// #
+    /// let predicted_pose = Isometry3::translation(0.5, 0.0, 0.0);
+    ///
+    /// let (alignee_transform, error_sum) = Icp::new()
+    ///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+    ///     .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+    ///     .is_converged(same_squared_distance_error(0.1))
+    ///     .initial_transform(predicted_pose)
+    ///     .estimate_transform(alignee_cloud, &target_cloud);
+    /// ```
+    pub fn initial_transform(self, initial_transform: M) -> Self {
+        Self {
+            initial_transform,
+            ..self
+        }
+    }
+
+    /// If enabled, the [`IcpResult`] returned by [`Icp::register`] contains the metrics of every iteration.
+    ///
+    /// Defaults to `false`.
+    pub fn record_history(self, record_history: bool) -> Self {
+        Self {
+            record_history,
+            ..self
+        }
+    }
+
     /// The `filter_points` function is used to filter out points that are not considered for correspondence.
     /// It takes a reference to a `PointCloudPoint` and returns a boolean which is `true` if the point should be included.
     /// Use this to exclude points outside a bounding box, for example, or to filter out points that are too close to the sensor, etc.
@@ -425,15 +739,19 @@ where
     pub fn filter_points<NewFP: PointFilter<T, D>>(
         self,
         filter_points: NewFP,
-    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, NewFP, RO> {
+    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, NewFP, RO, CV> {
         Icp {
             filter_points,
             max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
             correspondence_estimator: self.correspondence_estimator,
             reject_outliers: self.reject_outliers,
             estimate_step_transform: self.estimate_step_transform,
             is_converged: self.is_converged,
 
+            estimate_covariance: self.estimate_covariance,
+
             _marker: PhantomData,
             _lt: self._lt,
         }
@@ -472,25 +790,61 @@ where
     pub fn reject_outliers<NewRO>(
         self,
         reject_outliers: NewRO,
-    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, NewRO>
+    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, NewRO, CV>
     where
         NewRO: FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, &[T]) -> Vec<bool>,
     {
         Icp {
             filter_points: self.filter_points,
             max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
             correspondence_estimator: self.correspondence_estimator,
             reject_outliers,
             estimate_step_transform: self.estimate_step_transform,
             is_converged: self.is_converged,
 
+            estimate_covariance: self.estimate_covariance,
+
+            _marker: PhantomData,
+            _lt: self._lt,
+        }
+    }
+
+    /// `estimate_covariance` is used to estimate the covariance of the final transform. It is called once after the
+    /// last iteration with the correspondences of that iteration (after filtering and outlier rejection) and its
+    /// step transform. No additional correspondence search is run.
+    ///
+    /// Use [`Icp::estimate_transform_with_covariance`] to get the covariance. By default no covariance is estimated.
+    ///
+    /// Check [`crate::transform_estimation::point_to_plane_lls::estimate_covariance`] and
+    /// [`crate::transform_estimation::svd::estimate_covariance`] for built-in covariance estimators that match the
+    /// residuals of the corresponding transform estimators.
+    pub fn estimate_covariance<NewCV>(
+        self,
+        estimate_covariance: NewCV,
+    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO, NewCV>
+    where
+        NewCV: CovarianceEstimator<T, M, D>,
+    {
+        Icp {
+            filter_points: self.filter_points,
+            max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
+            correspondence_estimator: self.correspondence_estimator,
+            reject_outliers: self.reject_outliers,
+            estimate_step_transform: self.estimate_step_transform,
+            is_converged: self.is_converged,
+            estimate_covariance,
+
             _marker: PhantomData,
             _lt: self._lt,
         }
     }
 }
 
-impl<'a, T, M, ET, IC, const D: usize, FP, RO> Icp<'a, T, M, (), (), ET, IC, D, FP, RO>
+impl<'a, T, M, ET, IC, const D: usize, FP, RO, CV> Icp<'a, T, M, (), (), ET, IC, D, FP, RO, CV>
 where
     T: Scalar + RealField + Float + One + Zero + Debug,
     f32: From<T>,
@@ -528,17 +882,20 @@ where
     pub fn correspondence_estimator<'b, CE, TG>(
         self,
         correspondence_estimator: CE,
-    ) -> Icp<'b, T, M, TG, CE, ET, IC, D, FP, RO>
+    ) -> Icp<'b, T, M, TG, CE, ET, IC, D, FP, RO, CV>
     where
         CE: CorrespondenceEstimator<'b, T, TG, D>,
     {
         Icp {
             max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
             correspondence_estimator,
             estimate_step_transform: self.estimate_step_transform,
             is_converged: self.is_converged,
             filter_points: self.filter_points,
             reject_outliers: self.reject_outliers,
+            estimate_covariance: self.estimate_covariance,
 
             _marker: PhantomData,
             _lt: &(),
@@ -546,7 +903,7 @@ where
     }
 }
 
-impl<'a, T, M, TG, CE, IC, const D: usize, FP, RO> Icp<'a, T, M, TG, CE, (), IC, D, FP, RO>
+impl<'a, T, M, TG, CE, IC, const D: usize, FP, RO, CV> Icp<'a, T, M, TG, CE, (), IC, D, FP, RO, CV>
 where
     T: Scalar + RealField + Float + One + Zero + Debug,
     f32: From<T>,
@@ -583,26 +940,109 @@ where
     pub fn estimate_step_transform<ET>(
         self,
         estimate_step_transform: ET,
-    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO>
+    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO, CV>
     where
         // ET: TransformEstimator<T, M, D>,
-        ET: FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<M>,
+        ET: FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, usize) -> Option<M>,
     {
         Icp {
             max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
             correspondence_estimator: self.correspondence_estimator,
             estimate_step_transform,
             is_converged: self.is_converged,
             filter_points: self.filter_points,
             reject_outliers: self.reject_outliers,
 
+            estimate_covariance: self.estimate_covariance,
+
+            _marker: PhantomData,
+            _lt: self._lt,
+        }
+    }
+
+    /// Same as [`Icp::estimate_step_transform`] but for transform estimators that return a `Result` like
+    /// [`crate::transform_estimation::point_to_plane_lls::try_estimate_isometry`].
+    ///
+    /// Their errors are returned by [`Icp::try_estimate_transform`].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use modern_icp::{Icp, PointCloud};
+    /// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    /// # use modern_icp::transform_estimation::point_to_plane_lls;
+    /// # use modern_icp::convergence::same_squared_distance_error;
+    /// #
+    /// # let alignee_cloud = PointCloud::<f32, 3>::new();
+    /// # let target_cloud = PointCloud::<f32, 3>::new();
+    /// #
+    /// let result = Icp::new()
+    ///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+    ///     .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+    ///     .is_converged(same_squared_distance_error(0.1))
+    ///     .try_estimate_transform(alignee_cloud, &target_cloud);
+    /// ```
+    pub fn try_estimate_step_transform<ET>(
+        self,
+        estimate_step_transform: ET,
+    ) -> Icp<'a, T, M, TG, CE, Fallible<ET>, IC, D, FP, RO, CV>
+    where
+        ET: FnMut(
+            &mut MaskedPointCloud<T, D>,
+            &mut MaskedPointCloud<T, D>,
+            usize,
+        ) -> Result<M, IcpError>,
+    {
+        Icp {
+            max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
+            correspondence_estimator: self.correspondence_estimator,
+            estimate_step_transform: Fallible(estimate_step_transform),
+            is_converged: self.is_converged,
+            filter_points: self.filter_points,
+            reject_outliers: self.reject_outliers,
+
+            estimate_covariance: self.estimate_covariance,
+
+            _marker: PhantomData,
+            _lt: self._lt,
+        }
+    }
+
+    /// Same as [`Icp::estimate_step_transform`] but for transform estimators that implement [`TransformEstimator`]
+    /// themselves, like the [`crate::transform_estimation::schedule::ScheduledEstimator`].
+    ///
+    /// Their [`TransformEstimator::reset`] is called before the first iteration of every run, so the same [`Icp`] can
+    /// register several alignees.
+    pub fn step_transform_estimator<ET>(
+        self,
+        estimate_step_transform: ET,
+    ) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO, CV>
+    where
+        ET: TransformEstimator<T, M, D>,
+    {
+        Icp {
+            max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
+            correspondence_estimator: self.correspondence_estimator,
+            estimate_step_transform,
+            is_converged: self.is_converged,
+            filter_points: self.filter_points,
+            reject_outliers: self.reject_outliers,
+
+            estimate_covariance: self.estimate_covariance,
+
             _marker: PhantomData,
             _lt: self._lt,
         }
     }
 }
 
-impl<'a, T, M, TG, CE, ET, const D: usize, FP, RO> Icp<'a, T, M, TG, CE, ET, (), D, FP, RO>
+impl<'a, T, M, TG, CE, ET, const D: usize, FP, RO, CV> Icp<'a, T, M, TG, CE, ET, (), D, FP, RO, CV>
 where
     T: Scalar + RealField + Float + One + Zero + Debug,
     f32: From<T>,
@@ -636,24 +1076,89 @@ where
     ///     .is_converged(same_squared_distance_error(0.1))
     ///     .estimate_transform(alignee_cloud, &target_cloud);
     /// ```
-    pub fn is_converged<IC>(self, is_converged: IC) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO>
+    pub fn is_converged<IC>(self, is_converged: IC) -> Icp<'a, T, M, TG, CE, ET, IC, D, FP, RO, CV>
     where
         IC: FnMut(&[T], &[T], &M, &mut T, usize) -> bool,
     {
         Icp {
             max_iterations: self.max_iterations,
+            initial_transform: self.initial_transform,
+            record_history: self.record_history,
             correspondence_estimator: self.correspondence_estimator,
             estimate_step_transform: self.estimate_step_transform,
             is_converged,
             filter_points: self.filter_points,
             reject_outliers: self.reject_outliers,
 
+            estimate_covariance: self.estimate_covariance,
+
             _marker: PhantomData,
             _lt: self._lt,
         }
     }
 }
 
+/// Why [`Icp::register`] stopped iterating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// The convergence criterion was met.
+    Converged,
+    /// The maximum number of iterations was reached before the convergence criterion was met.
+    MaxIterations,
+    /// No correspondences were left after filtering and outlier rejection.
+    EmptyCorrespondences,
+    /// The step transform estimator failed.
+    EstimationFailed,
+}
+
+/// Metrics of a single ICP iteration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IterationMetrics<T> {
+    /// Root mean squared distance between the alignee points and their closest corresponding target points after
+    /// applying the step transform. Every alignee point that has a correspondence counts once.
+    pub rmse: T,
+    /// Ratio of alignee points that have a correspondence after filtering and outlier rejection.
+    pub fitness: T,
+    /// Number of correspondences that were passed to the step transform estimator.
+    pub correspondence_count: usize,
+    /// The distance error computed by the convergence criterion.
+    pub error: T,
+}
+
+/// The result of [`Icp::register`].
+///
+/// The metrics are the ones of the last iteration in which a step transform was estimated.
+/// They are zero if there was no such iteration.
+#[derive(Clone, Debug)]
+pub struct IcpResult<T, M, C = ()> {
+    /// The transform from the alignee to the target including the initial transform.
+    pub transform: M,
+    /// The distance error computed by the convergence criterion.
+    pub error: T,
+    pub termination_reason: TerminationReason,
+    /// Number of iterations in which a step transform was estimated and applied.
+    pub iterations: usize,
+    /// Root mean squared distance between the alignee points and their closest corresponding target points after
+    /// the last iteration. Every alignee point that has a correspondence counts once.
+    pub rmse: T,
+    /// Ratio of alignee points that had a correspondence after filtering and outlier rejection.
+    pub fitness: T,
+    /// Number of correspondences in the last iteration.
+    pub correspondence_count: usize,
+    /// The covariance of the transform at the final alignment if a covariance estimator was set with
+    /// [`Icp::estimate_covariance`]. See [`Icp::estimate_transform_with_covariance`].
+    pub covariance: Option<C>,
+    /// Metrics of every iteration if enabled with [`Icp::record_history`]. Otherwise empty.
+    pub history: Vec<IterationMetrics<T>>,
+}
+
+impl<T, M, C> IcpResult<T, M, C> {
+    /// Returns `true` if the ICP terminated because the convergence criterion was met.
+    pub fn is_converged(&self) -> bool {
+        self.termination_reason == TerminationReason::Converged
+    }
+}
+
 /// Estimates the transform that the alignee point cloud has to be transformed by to match the
 /// target using the iterative closest point algorithm.
 ///
@@ -714,3 +1219,262 @@ where
         .is_converged(is_converged)
         .estimate_transform(alignee.to_point_cloud(), target)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::{never, same_squared_distance_error};
+    use crate::correspondence::{NearestNeighbor, SoftNearestNeighbors};
+    use crate::test_utils::{assert_isometry_eq, isometry_difference, room_corner, transformed};
+    use crate::transform_estimation::point_to_plane_lls;
+
+    /// A room corner and a copy of it that has to be transformed by the returned isometry to match it again.
+    fn moved_room_corner(transform: &Isometry3<f32>) -> (PointCloud<f32, 3>, PointCloud<f32, 3>) {
+        let target = room_corner(1.0, 0.1);
+        let alignee = transformed(&target, &transform.inverse());
+
+        (alignee, target)
+    }
+
+    #[test]
+    fn result_includes_initial_transform() {
+        let transform = Isometry3::new(Vector3::new(0.1, 0.05, -0.1), Vector3::new(0.0, 0.0, 0.1));
+        let (alignee, target) = moved_room_corner(&transform);
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-6))
+            .initial_transform(transform)
+            .register(alignee, &target);
+
+        assert_isometry_eq(&result.transform, &transform, 1e-4);
+        assert!(result.rmse < 1e-4);
+    }
+
+    #[test]
+    fn initial_transform_helps_with_large_offset() {
+        let transform = Isometry3::new(Vector3::new(1.5, -0.6, 0.3), Vector3::new(0.0, 0.0, 2.8));
+        let (alignee, target) = moved_room_corner(&transform);
+
+        let mut icp = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-6));
+
+        let (without_guess, _) = icp.estimate_transform(alignee.clone(), &target);
+        let (translation, _) = isometry_difference(&without_guess, &transform);
+        assert!(translation > 0.1);
+
+        let guess = Isometry3::new(Vector3::new(1.4, -0.55, 0.3), Vector3::new(0.0, 0.0, 2.7));
+        let (with_guess, _) = icp
+            .initial_transform(guess)
+            .estimate_transform(alignee, &target);
+        assert_isometry_eq(&with_guess, &transform, 1e-3);
+    }
+
+    #[test]
+    fn terminates_when_converged() {
+        let transform = Isometry3::new(
+            Vector3::new(0.05, -0.03, 0.02),
+            Vector3::new(0.02, 0.0, 0.05),
+        );
+        let (alignee, target) = moved_room_corner(&transform);
+        let alignee_len = alignee.len();
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-6))
+            .record_history(true)
+            .register(alignee, &target);
+
+        assert_eq!(result.termination_reason, TerminationReason::Converged);
+        assert!(result.is_converged());
+        assert!(result.iterations < 50);
+        assert_eq!(result.history.len(), result.iterations);
+        assert_eq!(result.history.last().unwrap().rmse, result.rmse);
+        assert!(result.history[0].rmse > result.rmse);
+        assert!(result.rmse < 1e-4);
+        assert_eq!(result.fitness, 1.0);
+        assert_eq!(result.correspondence_count, alignee_len);
+        assert_isometry_eq(&result.transform, &transform, 1e-4);
+    }
+
+    #[test]
+    fn terminates_after_max_iterations() {
+        let (alignee, target) = moved_room_corner(&Isometry3::translation(0.05, 0.0, 0.0));
+
+        let result = Icp::new()
+            .max_iterations(3)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(never)
+            .register(alignee, &target);
+
+        assert_eq!(result.termination_reason, TerminationReason::MaxIterations);
+        assert!(!result.is_converged());
+        assert_eq!(result.iterations, 3);
+        assert!(result.history.is_empty());
+    }
+
+    #[test]
+    fn terminates_without_correspondences() {
+        let (alignee, target) = moved_room_corner(&Isometry3::translation(0.05, 0.0, 0.0));
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(never)
+            .filter_points(|_: &PointCloudPoint<f32, 3>| false)
+            .register(alignee, &target);
+
+        assert_eq!(
+            result.termination_reason,
+            TerminationReason::EmptyCorrespondences
+        );
+        assert_eq!(result.iterations, 0);
+        assert_eq!(result.rmse, 0.0);
+        assert_eq!(result.fitness, 0.0);
+        assert_eq!(result.transform, Isometry3::identity());
+    }
+
+    #[test]
+    fn terminates_when_estimation_fails() {
+        let (alignee, target) = moved_room_corner(&Isometry3::translation(0.05, 0.0, 0.0));
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(
+                |_: &mut MaskedPointCloud<f32, 3>,
+                 _: &mut MaskedPointCloud<f32, 3>,
+                 step: usize| { (step < 2).then(Isometry3::identity) },
+            )
+            .is_converged(never)
+            .register(alignee, &target);
+
+        assert_eq!(
+            result.termination_reason,
+            TerminationReason::EstimationFailed
+        );
+        assert_eq!(result.iterations, 2);
+    }
+
+    #[test]
+    fn fitness_is_ratio_of_points_with_correspondence() {
+        let target = room_corner(1.0, 0.1);
+
+        let result = Icp::new()
+            .max_iterations(1)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(
+                |_: &mut MaskedPointCloud<f32, 3>, _: &mut MaskedPointCloud<f32, 3>, _: usize| {
+                    Some(Isometry3::identity())
+                },
+            )
+            .is_converged(never)
+            .filter_points(|p: &PointCloudPoint<f32, 3>| p.pos.x > 0.5)
+            .register(target.clone(), &target);
+
+        let expected = target.iter().filter(|p| p.pos.x > 0.5).count() as f32 / target.len() as f32;
+        assert!(f32::abs(result.fitness - expected) < 1e-6);
+        assert!(result.fitness < 1.0);
+    }
+
+    #[test]
+    fn rmse_counts_every_alignee_point_once() {
+        // Every alignee point lies on a target point, so the RMSE has to be zero although soft correspondences
+        // pair it with further away neighbors as well.
+        let target = room_corner(1.0, 0.1);
+
+        let result = Icp::new()
+            .max_iterations(1)
+            .correspondence_estimator(SoftNearestNeighbors::new(&target).k_neighbors(4))
+            .estimate_step_transform(
+                |_: &mut MaskedPointCloud<f32, 3>, _: &mut MaskedPointCloud<f32, 3>, _: usize| {
+                    Some(Isometry3::identity())
+                },
+            )
+            .is_converged(never)
+            .register(target.clone(), &target);
+
+        assert_eq!(result.correspondence_count, 4 * target.len());
+        assert_eq!(result.fitness, 1.0);
+        assert!(result.rmse < 1e-6);
+    }
+
+    #[test]
+    fn try_estimate_transform_reports_invalid_point_clouds() {
+        let (alignee, target) = moved_room_corner(&Isometry3::translation(0.05, 0.0, 0.0));
+        let mut with_nan = alignee.clone();
+        with_nan[0].pos.y = f32::NAN;
+        let mut target_with_infinity = target.clone();
+        target_with_infinity[0].norm = Some(Vector3::new(0.0, 0.0, f32::INFINITY));
+
+        let mut icp = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-6));
+
+        assert!(icp.try_estimate_transform(alignee.clone(), &target).is_ok());
+        assert_eq!(
+            icp.try_estimate_transform(PointCloud::new(), &target),
+            Err(IcpError::EmptyPointCloud)
+        );
+        assert_eq!(
+            icp.try_estimate_transform(with_nan, &target),
+            Err(IcpError::NonFiniteCoordinates)
+        );
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target_with_infinity))
+            .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-6))
+            .try_estimate_transform(alignee, &target_with_infinity);
+        assert_eq!(result, Err(IcpError::NonFiniteCoordinates));
+    }
+
+    #[test]
+    fn try_estimate_transform_reports_why_iteration_failed() {
+        let (alignee, target) = moved_room_corner(&Isometry3::translation(0.05, 0.0, 0.0));
+        let floor: PointCloud<f32, 3> = target
+            .iter()
+            .filter(|p| p.norm == Some(Vector3::z()))
+            .copied()
+            .collect();
+        let mut without_normals = target.clone();
+        for p in &mut without_normals {
+            p.norm = None;
+        }
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+            .is_converged(never)
+            .filter_points(|_: &PointCloudPoint<f32, 3>| false)
+            .try_estimate_transform(alignee.clone(), &target);
+        assert_eq!(result, Err(IcpError::NoCorrespondences));
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&without_normals))
+            .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+            .is_converged(never)
+            .try_estimate_transform(alignee.clone(), &without_normals);
+        assert_eq!(result, Err(IcpError::MissingNormals));
+
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&floor))
+            .try_estimate_step_transform(point_to_plane_lls::try_estimate_isometry)
+            .is_converged(never)
+            .try_estimate_transform(floor.clone(), &floor);
+        assert_eq!(result, Err(IcpError::SingularSystem));
+
+        // estimators that return an `Option` don't tell why they failed
+        let result = Icp::new()
+            .correspondence_estimator(NearestNeighbor::new(&floor))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(never)
+            .try_estimate_transform(floor.clone(), &floor);
+        assert_eq!(result, Err(IcpError::EstimationFailed));
+    }
+}
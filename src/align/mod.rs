@@ -1,9 +1,11 @@
 pub mod convergence;
 pub mod correspondence;
+pub mod cpd;
+pub mod deformation_graph;
 pub mod filter_points;
 pub mod icp;
 pub mod pca;
 pub mod reject_outliers;
 pub mod transform_estimation;
 
-pub use icp::Icp;
+pub use icp::{Icp, IcpResult, TerminationReason};
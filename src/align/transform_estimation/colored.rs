@@ -0,0 +1,223 @@
+use kdtree::distance::squared_euclidean;
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::{MaskedPointCloud, PointCloud, kd_tree_of_point_cloud};
+
+/// Estimates the intensity gradient of every point in the tangent plane of the point.
+///
+/// The gradient of a point is fitted to the intensity differences to its `k_neighbors` nearest neighbors
+/// projected onto its tangent plane. Points without a normal or without intensity/color
+/// (see [`crate::PointCloudPoint::intensity_or_luminance`]) get a zero gradient.
+///
+/// See section 4 of this [paper from Park et al.](https://doi.org/10.1109/ICCV.2017.25)
+pub fn compute_intensity_gradients<T>(
+    cloud: &PointCloud<T, 3>,
+    k_neighbors: usize,
+) -> Vec<Vector3<T>>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let tree = kd_tree_of_point_cloud(cloud);
+
+    cloud
+        .iter()
+        .map(|p| {
+            let (Some(norm), Some(intensity)) = (p.norm, p.intensity_or_luminance()) else {
+                return Vector3::zeros();
+            };
+
+            let Ok(neighbors) =
+                tree.nearest(p.pos.coords.as_slice(), k_neighbors + 1, &squared_euclidean)
+            else {
+                return Vector3::zeros();
+            };
+
+            let mut ata = Matrix3::<T>::zeros();
+            let mut atb = Vector3::<T>::zeros();
+            let mut count = T::zero();
+
+            for (_, idx) in neighbors {
+                let neighbor = &cloud[*idx];
+                let Some(neighbor_intensity) = neighbor.intensity_or_luminance() else {
+                    continue;
+                };
+
+                let diff = neighbor.pos - p.pos;
+                let projected = diff - norm * norm.dot(&diff);
+
+                ata += projected * projected.transpose();
+                atb += projected * (neighbor_intensity - intensity);
+                count += T::one();
+            }
+
+            // enforce that the gradient lies in the tangent plane
+            let constraint = norm * count;
+            ata += constraint * constraint.transpose();
+
+            ata.cholesky()
+                .map(|chol| chol.solve(&atb))
+                .unwrap_or_else(Vector3::zeros)
+        })
+        .collect()
+}
+
+/// Builds a transform estimator for colored ICP that jointly minimizes the point-to-plane distance and
+/// the photometric error between the intensities of the alignee and the target.
+///
+/// The estimator is built for the given `target` point cloud because it has to precompute the
+/// intensity gradients of the target points (see [`compute_intensity_gradients`]). It has to be used with
+/// a correspondence estimator that returns correspondences into a copy of this exact point cloud, like
+/// [`crate::correspondence::NearestNeighbor`].
+///
+/// The target needs normals. Both point clouds need intensities or colors, otherwise only the
+/// geometric term is used for that correspondence. `lambda_geometric` weights the geometric against the
+/// photometric term (the paper uses `0.968`) and `k_neighbors` is used for the gradient computation.
+///
+/// See this [paper from Park et al.](https://doi.org/10.1109/ICCV.2017.25)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::colored;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(colored::build_isometry_estimator(&target_cloud, 0.968, 10))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+#[allow(non_snake_case)]
+pub fn build_isometry_estimator<T>(
+    target: &PointCloud<T, 3>,
+    lambda_geometric: T,
+    k_neighbors: usize,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let gradients = compute_intensity_gradients(target, k_neighbors);
+
+    let sqrt_lambda_geometric = Float::sqrt(lambda_geometric);
+    let sqrt_lambda_color = Float::sqrt(T::one() - lambda_geometric);
+
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, _: usize| {
+        let mut ATA = Matrix6::<T>::zeros();
+        let mut ATb = Vector6::<T>::zeros();
+
+        for (a, (t, t_idx)) in alignee.iter().zip(
+            target
+                .iter()
+                .zip(target.masked_and_ordered_to_plain_index.iter()),
+        ) {
+            let norm = t.norm?;
+            let weight = a.weight * t.weight;
+
+            let diff = a.pos - t.pos;
+
+            let mut add_row = |direction: Vector3<T>, residual: T, scale: T| {
+                let row = Vector6::from_iterator(
+                    a.pos
+                        .coords
+                        .cross(&direction)
+                        .iter()
+                        .chain(direction.iter())
+                        .copied(),
+                ) * scale;
+
+                ATA += row * row.transpose() * weight;
+                ATb -= row * (residual * scale) * weight;
+            };
+
+            add_row(norm, diff.dot(&norm), sqrt_lambda_geometric);
+
+            if let (Some(a_intensity), Some(t_intensity), Some(gradient)) = (
+                a.intensity_or_luminance(),
+                t.intensity_or_luminance(),
+                gradients.get(*t_idx),
+            ) {
+                add_row(
+                    *gradient,
+                    t_intensity + gradient.dot(&diff) - a_intensity,
+                    sqrt_lambda_color,
+                );
+            }
+        }
+
+        let x = ATA.cholesky()?.solve(&ATb);
+
+        Some(Isometry3::new(
+            Vector3::new(x[3], x[4], x[5]),
+            Vector3::new(x[0], x[1], x[2]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::test_utils::{assert_isometry_eq, floor, transformed};
+    use crate::{Icp, PointCloudPoint};
+
+    /// A floor with a smooth intensity pattern that constrains the in-plane motion.
+    fn textured_floor() -> PointCloud<f32, 3> {
+        floor(4.0, 0.1)
+            .into_iter()
+            .map(|p| p.with_intensity(0.5 + 0.25 * (2.0 * p.pos.x).sin() * (1.5 * p.pos.y).cos()))
+            .collect()
+    }
+
+    #[test]
+    fn gradient_of_intensity_ramp() {
+        let cloud: PointCloud<f32, 3> = floor(1.0, 0.1)
+            .into_iter()
+            .map(|p| p.with_intensity(2.0 * p.pos.x - p.pos.y))
+            .collect();
+
+        for gradient in compute_intensity_gradients(&cloud, 8) {
+            assert!((gradient - Vector3::new(2.0, -1.0, 0.0)).norm() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn points_without_intensity_have_zero_gradient() {
+        let cloud = vec![
+            PointCloudPoint::from_pos_norm(Point3::origin(), Vector3::z()),
+            PointCloudPoint::from_pos_norm(Point3::new(1.0, 0.0, 0.0), Vector3::z()),
+        ];
+
+        assert_eq!(
+            compute_intensity_gradients(&cloud, 1),
+            vec![Vector3::zeros(); 2]
+        );
+    }
+
+    #[test]
+    fn recovers_in_plane_motion_from_intensities() {
+        let target = textured_floor();
+
+        let transform = Isometry3::new(
+            Vector3::new(0.06, -0.04, 0.02),
+            Vector3::new(0.0, 0.0, 0.03),
+        );
+        let alignee = transformed(&target, &transform);
+
+        let (result, _) = Icp::new()
+            .max_iterations(100)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(build_isometry_estimator(&target, 0.5, 8))
+            .is_converged(same_squared_distance_error(1e-12))
+            .estimate_transform(alignee, &target);
+
+        assert_isometry_eq(&result, &transform.inverse(), 1e-2);
+    }
+}
@@ -0,0 +1,458 @@
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::MaskedPointCloud;
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::levenberg_marquardt::se3_exp;
+use crate::transform_estimation::motion_prior::{MotionPrior, PriorEstimator};
+
+/// Builds a transform estimator that only moves the alignee within the subspace of rigid motions spanned by `basis`.
+///
+/// Every basis vector is a twist `[ω, v]` (rotation vector followed by the translational part, see
+/// [`se3_exp`]) expressed in the coordinate system of the point clouds. Every Gauss-Newton iteration applies the
+/// increment `exp(y_1 * basis_1 + ... + y_k * basis_k)`, so the transform stays inside the motions generated by the
+/// basis, e.g. rotations about one axis. The coefficients `y` are found directly in this reduced parameter space, so unlike
+/// [`crate::transform_estimation::transform_modifier::build_modified_transform_estimator`] the result is the
+/// constrained optimum and not a projection of the unconstrained one.
+///
+/// Correspondences whose target point has a normal contribute their point-to-plane distance,
+/// all others their point-to-point distance. The weights of the points are respected.
+///
+/// Usually you want one of the more specific builders [`build_planar_estimator`],
+/// [`build_axis_rotation_estimator`], [`build_direction_translation_estimator`] or [`build_masked_estimator`].
+pub fn build_constrained_estimator<T>(
+    basis: Vec<Vector6<T>>,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mut estimate = build_reduced_estimator(basis, false, None);
+
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, _: usize| {
+        estimate(alignee, target, &Isometry3::identity())
+    }
+}
+
+/// Builds a transform estimator like [`build_constrained_estimator`] that is regularized toward the pose of the
+/// `prior` within the subspace spanned by `basis`.
+///
+/// The cost of the prior (see [`MotionPrior::normal_equations`]) is added in every Gauss-Newton iteration. Set the
+/// returned [`PriorEstimator`] with [`crate::Icp::step_transform_estimator`] which starts it over in every run. If the
+/// ICP starts from an initial transform (see [`crate::Icp::initial_transform`]) use [`MotionPrior::relative_to`].
+pub fn build_constrained_estimator_with_prior<T>(
+    basis: Vec<Vector6<T>>,
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    PriorEstimator::new(build_reduced_estimator(basis, false, Some(prior)))
+}
+
+/// Gauss-Newton in the space spanned by `basis`.
+///
+/// If `decoupled` is `false` the increments are applied with the exponential map which keeps the transform in the
+/// subgroup generated by a single twist. Otherwise rotation and translation are updated separately
+/// (`R <- exp(δω) * R`, `t <- t + δv`) which keeps the translation in the span of the translational basis vectors.
+///
+/// The estimator gets the transform that has been applied to the alignee so far, which is only needed for the prior.
+#[allow(non_snake_case)]
+fn build_reduced_estimator<T>(
+    basis: Vec<Vector6<T>>,
+    decoupled: bool,
+    prior: Option<MotionPrior<T>>,
+) -> impl FnMut(
+    &mut MaskedPointCloud<T, 3>,
+    &mut MaskedPointCloud<T, 3>,
+    &Isometry3<T>,
+) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    const MAX_GAUSS_NEWTON_ITERATIONS: usize = 10;
+
+    // `from_columns` panics without columns
+    let B = OMatrix::<T, U6, Dyn>::from_iterator(
+        basis.len(),
+        basis.iter().flat_map(|twist| twist.iter().copied()),
+    );
+
+    move |alignee: &mut MaskedPointCloud<T, 3>,
+          target: &mut MaskedPointCloud<T, 3>,
+          current_transform: &Isometry3<T>| {
+        if B.ncols() == 0 {
+            return Some(Isometry3::identity());
+        }
+
+        let mut transform = Isometry3::identity();
+        let min_step: T = nalgebra::convert(1e-8);
+
+        for _ in 0..MAX_GAUSS_NEWTON_ITERATIONS {
+            let mut H = Matrix6::<T>::zeros();
+            let mut g = Vector6::<T>::zeros();
+
+            for (a, t) in alignee.iter().zip(target.iter()) {
+                let weight = a.weight * t.weight;
+
+                let pos = transform * a.pos;
+                let diff = pos - t.pos;
+
+                // the rotation increment acts around the origin either before or after translating
+                let rotated = if decoupled {
+                    transform.rotation * a.pos
+                } else {
+                    pos
+                };
+
+                // derivative of the transformed point w.r.t. the increment
+                let mut J = Matrix3x6::<T>::zeros();
+                J.fixed_view_mut::<3, 3>(0, 0)
+                    .copy_from(&-rotated.coords.cross_matrix());
+                J.fixed_view_mut::<3, 3>(0, 3)
+                    .copy_from(&Matrix3::identity());
+
+                match t.norm {
+                    Some(norm) => {
+                        let J_plane = J.transpose() * norm;
+                        H += J_plane * J_plane.transpose() * weight;
+                        g += J_plane * (diff.dot(&norm) * weight);
+                    }
+                    None => {
+                        H += J.transpose() * J * weight;
+                        g += J.transpose() * diff * weight;
+                    }
+                }
+            }
+
+            if let Some(prior) = &prior {
+                // the decoupled increment [δω, δv] is the left twist [δω, δv + t × δω]
+                let mut A = Matrix6::<T>::identity();
+                if decoupled {
+                    A.fixed_view_mut::<3, 3>(3, 0)
+                        .copy_from(&transform.translation.vector.cross_matrix());
+                }
+
+                let (prior_H, prior_b) = prior.normal_equations(&(transform * current_transform));
+                H += A.transpose() * prior_H * A;
+                g -= A.transpose() * prior_b;
+            }
+
+            let reduced_H = B.transpose() * H * &B;
+            let reduced_g = B.transpose() * g;
+
+            let y = -reduced_H.cholesky()?.solve(&reduced_g);
+            let delta: Vector6<T> = &B * y.clone();
+
+            transform = if decoupled {
+                Isometry3::from_parts(
+                    Translation3::new(delta[3], delta[4], delta[5]) * transform.translation,
+                    UnitQuaternion::from_scaled_axis(Vector3::new(delta[0], delta[1], delta[2]))
+                        * transform.rotation,
+                )
+            } else {
+                se3_exp(&delta) * transform
+            };
+
+            if y.norm() < min_step {
+                break;
+            }
+        }
+
+        Some(transform)
+    }
+}
+
+/// Builds a transform estimator for planar motion: translation along x and y and rotation about the z axis.
+///
+/// This is useful for ground vehicles. Use [`build_masked_estimator`] for other planes.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::constrained;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(constrained::build_planar_estimator())
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn build_planar_estimator<T>()
+-> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    build_masked_estimator([true, true, false, false, false, true])
+}
+
+/// Builds a transform estimator that only rotates about the axis with the direction `axis` through `point_on_axis`.
+///
+/// This is useful for turntable scanners.
+pub fn build_axis_rotation_estimator<T>(
+    axis: Unit<Vector3<T>>,
+    point_on_axis: Point3<T>,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    // the twist of a rotation about a line through c is [u, c × u]
+    let v = point_on_axis.coords.cross(&axis);
+
+    build_constrained_estimator(vec![Vector6::new(axis.x, axis.y, axis.z, v.x, v.y, v.z)])
+}
+
+/// Builds a transform estimator that only translates along `direction`.
+pub fn build_direction_translation_estimator<T>(
+    direction: Unit<Vector3<T>>,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    build_constrained_estimator(vec![Vector6::new(
+        T::zero(),
+        T::zero(),
+        T::zero(),
+        direction.x,
+        direction.y,
+        direction.z,
+    )])
+}
+
+/// Builds a transform estimator that only uses the degrees of freedom enabled in `mask`.
+///
+/// The mask is ordered `[x, y, z, roll, pitch, yaw]`, i.e. the translations along and the rotations about
+/// the x, y and z axes of the coordinate system of the point clouds. The estimated transform rotates about
+/// the origin first and then translates (like [`Isometry3::new`]), so its translation only has the enabled components.
+pub fn build_masked_estimator<T>(
+    mask: [bool; 6],
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mut estimate = build_reduced_estimator(mask_basis(mask), true, None);
+
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, _: usize| {
+        estimate(alignee, target, &Isometry3::identity())
+    }
+}
+
+/// Builds a transform estimator like [`build_masked_estimator`] that is regularized toward the pose of the `prior`
+/// in the enabled degrees of freedom.
+///
+/// See [`build_constrained_estimator_with_prior`] for how the prior is applied.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::constrained;
+/// # use modern_icp::transform_estimation::motion_prior::MotionPrior;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// # use nalgebra::{Isometry3, Matrix6};
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let wheel_odometry = Isometry3::translation(0.5, 0.0, 0.0);
+/// let prior = MotionPrior::new(wheel_odometry, Matrix6::identity() * 10.0);
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .step_transform_estimator(constrained::build_masked_estimator_with_prior(
+///         [true, true, false, false, false, true],
+///         prior,
+///     ))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn build_masked_estimator_with_prior<T>(
+    mask: [bool; 6],
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    PriorEstimator::new(build_reduced_estimator(mask_basis(mask), true, Some(prior)))
+}
+
+/// The twists of the degrees of freedom enabled in `mask` which is ordered `[x, y, z, roll, pitch, yaw]`.
+fn mask_basis<T>(mask: [bool; 6]) -> Vec<Vector6<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    // twists are ordered [rotation, translation]
+    const TWIST_INDICES: [usize; 6] = [3, 4, 5, 0, 1, 2];
+
+    mask.iter()
+        .zip(TWIST_INDICES)
+        .filter(|(enabled, _)| **enabled)
+        .map(|(_, i)| Vector6::ith(i, T::one()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::test_utils::{assert_isometry_eq, room_corner, transformed};
+    use crate::{Icp, PointCloud};
+
+    fn register(
+        alignee: PointCloud<f32, 3>,
+        target: &PointCloud<f32, 3>,
+        estimator: impl FnMut(
+            &mut MaskedPointCloud<f32, 3>,
+            &mut MaskedPointCloud<f32, 3>,
+            usize,
+        ) -> Option<Isometry3<f32>>,
+    ) -> Isometry3<f32> {
+        Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(NearestNeighbor::new(target))
+            .estimate_step_transform(estimator)
+            .is_converged(same_squared_distance_error(1e-12))
+            .estimate_transform(alignee, target)
+            .0
+    }
+
+    /// Sum of the squared point-to-point distances of the exact correspondences after applying `transform`.
+    fn cost(
+        alignee: &PointCloud<f32, 3>,
+        target: &PointCloud<f32, 3>,
+        transform: &Isometry3<f32>,
+    ) -> f64 {
+        alignee
+            .iter()
+            .zip(target)
+            .map(|(a, t)| ((transform * a.pos) - t.pos).cast::<f64>().norm_squared())
+            .sum()
+    }
+
+    #[test]
+    fn planar_estimator_recovers_planar_motion() {
+        let target = room_corner(2.0, 0.2);
+
+        let transform = Isometry3::new(Vector3::new(0.1, -0.08, 0.0), Vector3::new(0.0, 0.0, 0.06));
+        let alignee = transformed(&target, &transform);
+
+        assert_isometry_eq(
+            &register(alignee, &target, build_planar_estimator()),
+            &transform.inverse(),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn planar_estimator_finds_constrained_optimum() {
+        // point-to-point correspondences without normals
+        let target: PointCloud<f32, 3> = room_corner(2.0, 0.2)
+            .into_iter()
+            .map(|p| crate::PointCloudPoint::from_pos(p.pos))
+            .collect();
+
+        // the motion has components outside of the plane which the estimator can't follow
+        let transform = Isometry3::new(
+            Vector3::new(0.1, -0.08, 0.05),
+            Vector3::new(0.04, -0.03, 0.06),
+        );
+        let alignee = transformed(&target, &transform);
+
+        let result = build_planar_estimator()(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+
+        let (scaled_axis, translation) = (result.rotation.scaled_axis(), result.translation.vector);
+        assert!(
+            scaled_axis.x.abs() < 1e-6 && scaled_axis.y.abs() < 1e-6 && translation.z.abs() < 1e-6
+        );
+
+        // no planar increment decreases the cost
+        let optimum = cost(&alignee, &target, &result);
+        for increment in [
+            Isometry3::translation(1e-3, 0.0, 0.0),
+            Isometry3::translation(0.0, 1e-3, 0.0),
+            Isometry3::rotation(Vector3::new(0.0, 0.0, 1e-3)),
+        ] {
+            assert!(cost(&alignee, &target, &(increment * result)) >= optimum);
+            assert!(cost(&alignee, &target, &(increment.inverse() * result)) >= optimum);
+        }
+    }
+
+    #[test]
+    fn axis_rotation_estimator_rotates_about_axis() {
+        let target = room_corner(2.0, 0.2);
+
+        let point_on_axis = Point3::new(1.0, 1.0, 0.0);
+        let rotation = Isometry3::rotation_wrt_point(
+            UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.08),
+            point_on_axis,
+        );
+
+        // the translation can't be followed
+        let alignee = transformed(
+            &target,
+            &(Isometry3::translation(0.0, 0.0, 0.05) * rotation),
+        );
+
+        let result = register(
+            alignee,
+            &target,
+            build_axis_rotation_estimator(Vector3::z_axis(), point_on_axis),
+        );
+
+        let axis_point = Point3::new(1.0, 1.0, 3.0);
+        assert!((result * axis_point - axis_point).norm() < 1e-5);
+        assert!((result.rotation.angle() - 0.08).abs() < 1e-3);
+    }
+
+    #[test]
+    fn direction_translation_estimator_translates_along_direction() {
+        let target = room_corner(2.0, 0.2);
+        let alignee = transformed(
+            &target,
+            &Isometry3::new(Vector3::new(0.1, 0.05, -0.05), Vector3::new(0.0, 0.02, 0.0)),
+        );
+
+        let direction = Unit::new_normalize(Vector3::new(1.0, 1.0, 0.0));
+        let result = register(
+            alignee,
+            &target,
+            build_direction_translation_estimator(direction),
+        );
+
+        assert_eq!(result.rotation, UnitQuaternion::identity());
+        assert!(result.translation.vector.cross(&direction).norm() < 1e-6);
+        assert!(result.translation.vector.norm() > 0.05);
+    }
+
+    #[test]
+    fn empty_mask_returns_identity() {
+        let target = room_corner(1.0, 0.5);
+
+        assert_eq!(
+            build_masked_estimator([false; 6])(
+                &mut MaskedPointCloud::new(&target),
+                &mut MaskedPointCloud::new(&target),
+                0
+            ),
+            Some(Isometry3::identity())
+        );
+    }
+}
@@ -1,46 +1,266 @@
 use nalgebra::*;
+use num_traits::Float;
 
-// pub fn estimate_affine<'a, T>(alignee: &PointCloud<T, 3>, target: &PointCloud<T, 3>) -> Affine3<T>
-//     where T: Scalar + RealField + From<f32> + Copy,
-// {
-//     // see paper https://doi.org/10.1109/JAS.2019.1911579 end of chapter III. B.
-//     // possibly this has some implementation bugs but it doesn't seem to be numerically stable
-//
-//     let sigma = T::from(1.0);
-//     let quotient = T::one() / (T::from(2.0) * sigma * sigma);
-//
-//     let to_homogeneous_vec4 =
-//         |p: &&PointCloudPoint<T, 3>| p.pos.to_homogeneous();
-//
-//     // alignee
-//     let PT: Matrix4xX<T> = Matrix4xX::from_columns(
-//         alignee.into_iter()
-//             .map(to_homogeneous_vec4)
-//             .collect::<Vec<_>>()
-//             .as_slice()
-//     );
-//     let P = PT.transpose();
-//
-//     let MT: Matrix4xX<T> = Matrix4xX::from_columns(
-//         target.into_iter()
-//             .map(to_homogeneous_vec4)
-//             .collect::<Vec<_>>()
-//             .as_slice(),
-//     );
-//     let M = MT.transpose();
-//
-//     let D = DMatrix::from_diagonal(
-//         &DVector::from_iterator(
-//             PT.column_iter().len(),
-//             (M - &P)
-//                 .row_iter()
-//                 .map(
-//                     |row| (-row.norm_squared() * quotient).exp()
-//                 ),
-//         )
-//     );
-//
-//     let DP = D * P;
-//
-//     Affine3::from_matrix_unchecked(MT * &DP * (PT * DP).try_inverse().unwrap())
-// }
+use crate::MaskedPointCloud;
+use crate::transform_estimation::svd::rotation_from_covariance;
+
+/// Computes the correntropy weights of the correspondences together with the weighted centroids
+/// of the alignee and the target.
+///
+/// The Gaussian kernel is evaluated relative to the closest correspondence so that the weights don't
+/// all underflow to zero for small bandwidths. This doesn't change the result because the weighted
+/// least squares problems below are invariant to a common scale of the weights.
+fn correntropy_weights<T>(
+    alignee: &MaskedPointCloud<T, 3>,
+    target: &MaskedPointCloud<T, 3>,
+    sigma: T,
+) -> Option<(Vec<T>, Vector3<T>, Vector3<T>)>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let squared_distances: Vec<T> = alignee
+        .iter()
+        .zip(target.iter())
+        .map(|(a, t)| (t.pos - a.pos).norm_squared())
+        .collect();
+
+    let min_squared_distance = squared_distances.iter().copied().reduce(Float::min)?;
+
+    let quotient = T::one() / (nalgebra::convert::<_, T>(2.0) * sigma * sigma);
+
+    let weights: Vec<T> = alignee
+        .iter()
+        .zip(target.iter())
+        .zip(squared_distances)
+        .map(|((a, t), d)| a.weight * t.weight * Float::exp(-(d - min_squared_distance) * quotient))
+        .collect();
+
+    let weight_sum = weights.iter().fold(T::zero(), |sum, w| sum + *w);
+    if weight_sum <= T::zero() {
+        return None;
+    }
+
+    let alignee_centroid = alignee
+        .iter()
+        .zip(weights.iter())
+        .fold(Vector3::zeros(), |sum, (a, w)| sum + a.pos.coords * *w)
+        / weight_sum;
+    let target_centroid = target
+        .iter()
+        .zip(weights.iter())
+        .fold(Vector3::zeros(), |sum, (t, w)| sum + t.pos.coords * *w)
+        / weight_sum;
+
+    Some((weights, alignee_centroid, target_centroid))
+}
+
+/// The kernel bandwidth for the given ICP step: `initial_sigma * annealing_rate^step` but at least `min_sigma`.
+fn annealed_sigma<T>(initial_sigma: T, annealing_rate: T, min_sigma: T, step: usize) -> T
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let step = i32::try_from(step).unwrap_or(i32::MAX);
+    Float::max(initial_sigma * Float::powi(annealing_rate, step), min_sigma)
+}
+
+/// Builds a transform estimator that estimates the affine transformation between the alignee and the target
+/// by maximizing the correntropy of the correspondences.
+///
+/// Every correspondence is weighted with a Gaussian kernel of its distance so that correspondences with
+/// large errors have almost no influence. This makes the estimation robust against heavy non-Gaussian noise and outliers.
+/// The kernel bandwidth starts at `initial_sigma` and is multiplied by `annealing_rate` in every ICP step until it
+/// reaches `min_sigma`. Start with a bandwidth in the order of the initial misalignment.
+///
+/// Like [`crate::transform_estimation::affine_transformation::estimate_affine`] this needs an alignee that
+/// spans all three dimensions. Otherwise `None` is returned.
+///
+/// See this [paper from Du et al.](https://doi.org/10.1109/JAS.2019.1911579)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::correntropy;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(correntropy::build_affine_estimator(1.0, 0.9, 0.05))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+#[allow(non_snake_case)]
+pub fn build_affine_estimator<T>(
+    initial_sigma: T,
+    annealing_rate: T,
+    min_sigma: T,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Affine3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, step: usize| {
+        let sigma = annealed_sigma(initial_sigma, annealing_rate, min_sigma, step);
+        let (weights, alignee_centroid, target_centroid) =
+            correntropy_weights(alignee, target, sigma)?;
+
+        let mut alignee_covariance = Matrix3::<T>::zeros();
+        let mut cross_covariance = Matrix3::<T>::zeros();
+
+        for ((a, t), w) in alignee.iter().zip(target.iter()).zip(weights) {
+            let a = a.pos.coords - alignee_centroid;
+            let t = t.pos.coords - target_centroid;
+
+            alignee_covariance += a * a.transpose() * w;
+            cross_covariance += a * t.transpose() * w;
+        }
+
+        // A^T = (sum w a a^T)^-1 * (sum w a t^T), solved without an explicit inverse
+        let A = alignee_covariance
+            .cholesky()?
+            .solve(&cross_covariance)
+            .transpose();
+
+        let translation = target_centroid - A * alignee_centroid;
+
+        let mut M = A.to_homogeneous();
+        M.fixed_view_mut::<3, 1>(0, 3).copy_from(&translation);
+
+        Some(Affine3::from_matrix_unchecked(M))
+    }
+}
+
+/// Builds a transform estimator that estimates the rigid transformation between the alignee and the target
+/// by maximizing the correntropy of the correspondences.
+///
+/// This is the rigid counterpart of [`build_affine_estimator`]. The weighted problem is solved in closed form
+/// with a weighted SVD like [`crate::transform_estimation::svd::estimate_isometry`].
+///
+/// See this [paper from Du et al.](https://doi.org/10.1109/JAS.2019.1911579)
+pub fn build_isometry_estimator<T>(
+    initial_sigma: T,
+    annealing_rate: T,
+    min_sigma: T,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, step: usize| {
+        let sigma = annealed_sigma(initial_sigma, annealing_rate, min_sigma, step);
+        let (weights, alignee_centroid, target_centroid) =
+            correntropy_weights(alignee, target, sigma)?;
+
+        let covariance = alignee.iter().zip(target.iter()).zip(weights).fold(
+            Matrix3::zeros(),
+            |sum, ((a, t), w)| {
+                sum + (a.pos.coords - alignee_centroid)
+                    * (t.pos.coords - target_centroid).transpose()
+                    * w
+            },
+        );
+
+        let rotation = Rotation3::from_matrix_unchecked(rotation_from_covariance(covariance)?);
+        let translation = Translation3::from(target_centroid - rotation * alignee_centroid);
+
+        Some(Isometry3::from_parts(
+            translation,
+            UnitQuaternion::from_rotation_matrix(&rotation),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_isometry_eq, room_corner};
+    use crate::{PointCloud, PointCloudPoint};
+
+    /// The target is the alignee moved by `transform` with every tenth point moved far away.
+    fn target_with_outliers(
+        alignee: &PointCloud<f32, 3>,
+        transform: impl Fn(&Point3<f32>) -> Point3<f32>,
+    ) -> PointCloud<f32, 3> {
+        alignee
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let outlier_offset = if i % 10 == 0 {
+                    Vector3::new(3.0, -2.0, 4.0)
+                } else {
+                    Vector3::zeros()
+                };
+                PointCloudPoint::from_pos(transform(&p.pos) + outlier_offset)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bandwidth_anneals_to_minimum() {
+        assert_eq!(annealed_sigma(1.0, 0.5, 0.1, 0), 1.0);
+        assert_eq!(annealed_sigma(1.0, 0.5, 0.1, 2), 0.25);
+        assert_eq!(annealed_sigma(1.0, 0.5, 0.1, 10), 0.1);
+        assert_eq!(annealed_sigma(1.0, 0.5, 0.1, usize::MAX), 0.1);
+    }
+
+    #[test]
+    fn affine_estimator_ignores_outliers() {
+        let alignee = room_corner(2.0, 0.2);
+
+        let transform = Affine3::from_matrix_unchecked(Matrix4::new(
+            1.1, 0.05, 0.0, 0.2, //
+            0.0, 0.9, 0.1, -0.1, //
+            0.02, 0.0, 1.05, 0.3, //
+            0.0, 0.0, 0.0, 1.0,
+        ));
+        let target = target_with_outliers(&alignee, |p| transform * p);
+
+        let result = build_affine_estimator(0.1, 0.5, 0.1)(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+
+        assert!((result.matrix() - transform.matrix()).norm() < 1e-3);
+    }
+
+    #[test]
+    fn isometry_estimator_ignores_outliers() {
+        let alignee = room_corner(2.0, 0.2);
+
+        let transform = Isometry3::new(Vector3::new(0.2, -0.1, 0.1), Vector3::new(0.05, 0.1, -0.1));
+        let target = target_with_outliers(&alignee, |p| transform * p);
+
+        let result = build_isometry_estimator(0.5, 0.5, 0.5)(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+
+        assert_isometry_eq(&result, &transform, 1e-3);
+    }
+
+    #[test]
+    fn small_bandwidth_does_not_underflow() {
+        let alignee = room_corner(2.0, 0.2);
+
+        // all inliers have the same distance which is far above the bandwidth
+        let translation = Vector3::new(0.5, -0.3, 0.2);
+        let target = target_with_outliers(&alignee, |p| p + translation);
+
+        let result = build_isometry_estimator(1e-3, 0.5, 1e-3)(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+
+        assert_isometry_eq(&result, &Isometry3::translation(0.5, -0.3, 0.2), 1e-4);
+    }
+}
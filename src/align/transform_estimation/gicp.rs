@@ -0,0 +1,302 @@
+use kdtree::distance::squared_euclidean;
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::motion_prior::{AppliedTransform, MotionPrior, PriorEstimator};
+use crate::{MaskedPointCloud, PointCloud, kd_tree_of_point_cloud};
+
+/// Computes the local covariance of every point from its `k_neighbors` nearest neighbors.
+///
+/// The covariances are regularized to describe a plane: the eigenvalues are replaced by `1` for the two
+/// major directions and `epsilon` for the normal direction (see section III.B of the paper).
+///
+/// See this [paper from Segal et al.](https://doi.org/10.15607/RSS.2009.V.021)
+pub fn compute_covariances<T>(
+    cloud: &PointCloud<T, 3>,
+    k_neighbors: usize,
+    epsilon: T,
+) -> Vec<Matrix3<T>>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let tree = kd_tree_of_point_cloud(cloud);
+
+    cloud
+        .iter()
+        .map(|p| {
+            let Ok(neighbors) = tree.nearest(
+                p.pos.coords.as_slice(),
+                k_neighbors.max(3),
+                &squared_euclidean,
+            ) else {
+                return Matrix3::identity();
+            };
+
+            let count = T::from_usize(neighbors.len()).unwrap();
+            let mean = neighbors.iter().fold(Vector3::zeros(), |sum, (_, idx)| {
+                sum + cloud[**idx].pos.coords
+            }) / count;
+            let covariance = neighbors.iter().fold(Matrix3::zeros(), |sum, (_, idx)| {
+                let d = cloud[**idx].pos.coords - mean;
+                sum + d * d.transpose()
+            });
+
+            let mut eigen = SymmetricEigen::new(covariance);
+            let normal_index = eigen.eigenvalues.imin();
+            for (i, v) in eigen.eigenvalues.iter_mut().enumerate() {
+                *v = if i == normal_index { epsilon } else { T::one() };
+            }
+
+            eigen.recompose()
+        })
+        .collect()
+}
+
+/// Builds a transform estimator for Generalized-ICP (plane-to-plane).
+///
+/// Every step minimizes the Mahalanobis distance between the corresponding points under the combined
+/// local covariances `C_target + R * C_alignee * R^T` with one Gauss-Newton step.
+///
+/// The covariances of the `target` are computed once when building the estimator using `k_neighbors`
+/// (see [`compute_covariances`]). Therefore the estimator has to be used with a correspondence estimator that
+/// returns correspondences into a copy of this exact target point cloud, like
+/// [`crate::correspondence::NearestNeighbor`]. The covariances of the alignee are computed in the first call and
+/// rotated with the transform that has been applied to the alignee since then (see [`AppliedTransform`]). They are
+/// computed again if the alignee isn't a rigidly moved copy of that point cloud anymore, e.g. in the next ICP run.
+///
+/// See this [paper from Segal et al.](https://doi.org/10.15607/RSS.2009.V.021)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::gicp;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(gicp::build_isometry_estimator(&target_cloud, 20))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn build_isometry_estimator<T>(
+    target: &PointCloud<T, 3>,
+    k_neighbors: usize,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mut estimate = build_estimator(target, k_neighbors, None);
+
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, _: usize| {
+        estimate(alignee, target, &Isometry3::identity())
+    }
+}
+
+/// Builds a transform estimator like [`build_isometry_estimator`] that is regularized toward the pose of the `prior`.
+///
+/// The prior is added as an extra block to the Gauss-Newton normal equations (see [`MotionPrior::normal_equations`]).
+/// The residuals are weighted with the inverse of the combined local covariances, so the information of the prior is
+/// relative to them.
+///
+/// Set the returned [`PriorEstimator`] with [`crate::Icp::step_transform_estimator`] which starts it over in every run.
+/// If the ICP starts from an initial transform (see [`crate::Icp::initial_transform`]) use
+/// [`MotionPrior::relative_to`].
+pub fn build_isometry_estimator_with_prior<T>(
+    target: &PointCloud<T, 3>,
+    k_neighbors: usize,
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    PriorEstimator::new(build_estimator(target, k_neighbors, Some(prior)))
+}
+
+#[allow(non_snake_case)]
+fn build_estimator<T>(
+    target: &PointCloud<T, 3>,
+    k_neighbors: usize,
+    prior: Option<MotionPrior<T>>,
+) -> impl FnMut(
+    &mut MaskedPointCloud<T, 3>,
+    &mut MaskedPointCloud<T, 3>,
+    &Isometry3<T>,
+) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let epsilon = nalgebra::convert(0.001);
+
+    let target_covariances = compute_covariances(target, k_neighbors, epsilon);
+
+    // covariances of the alignee at the positions of the call that computed them
+    let mut alignee_covariances = vec![];
+    let mut alignee_positions: Vec<Point3<T>> = vec![];
+
+    // recovers the transform that has been applied to the alignee since then
+    let mut applied_transform = AppliedTransform::new();
+
+    let tolerance = Float::sqrt(T::default_epsilon());
+
+    // the prior needs the transform that has been applied in this ICP run, which is passed in
+    move |alignee: &mut MaskedPointCloud<T, 3>,
+          target: &mut MaskedPointCloud<T, 3>,
+          prior_transform: &Isometry3<T>| {
+        let mut current_transform = applied_transform.update(alignee);
+
+        let is_moved_copy = alignee_positions.len() == alignee.point_cloud.len()
+            && alignee_positions
+                .iter()
+                .zip(alignee.point_cloud.iter())
+                .all(|(reference, p)| {
+                    (current_transform * reference - p.pos).norm()
+                        <= tolerance * (T::one() + reference.coords.norm())
+                });
+
+        if !is_moved_copy {
+            alignee_covariances = compute_covariances(alignee.point_cloud, k_neighbors, epsilon);
+            alignee_positions = alignee.point_cloud.iter().map(|p| p.pos).collect();
+
+            applied_transform = AppliedTransform::new();
+            current_transform = applied_transform.update(alignee);
+        }
+
+        let rotation = current_transform.rotation.to_rotation_matrix().into_inner();
+
+        let mut H = Matrix6::<T>::zeros();
+        let mut g = Vector6::<T>::zeros();
+
+        for ((a, a_idx), (t, t_idx)) in alignee
+            .iter()
+            .zip(alignee.masked_and_ordered_to_plain_index.iter())
+            .zip(
+                target
+                    .iter()
+                    .zip(target.masked_and_ordered_to_plain_index.iter()),
+            )
+        {
+            let combined_covariance: Matrix3<T> = target_covariances.get(*t_idx)?
+                + rotation * alignee_covariances[*a_idx] * rotation.transpose();
+            let Some(information) = combined_covariance.try_inverse() else {
+                continue;
+            };
+
+            let weight = a.weight * t.weight;
+            let residual = a.pos - t.pos;
+
+            // derivative of the residual w.r.t. the small rotation vector and the translation
+            let mut J = Matrix3x6::<T>::zeros();
+            J.fixed_view_mut::<3, 3>(0, 0)
+                .copy_from(&-a.pos.coords.cross_matrix());
+            J.fixed_view_mut::<3, 3>(0, 3)
+                .copy_from(&Matrix3::identity());
+
+            let JT_information = J.transpose() * information;
+
+            H += JT_information * J * weight;
+            g += JT_information * residual * weight;
+        }
+
+        if let Some(prior) = &prior {
+            let (prior_H, prior_b) = prior.normal_equations(prior_transform);
+            H += prior_H;
+            g -= prior_b;
+        }
+
+        let x = -H.cholesky()?.solve(&g);
+
+        Some(Isometry3::new(
+            Vector3::new(x[3], x[4], x[5]),
+            Vector3::new(x[0], x[1], x[2]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Icp;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::test_utils::*;
+    use crate::transform_estimation::robust::{RobustKernel, build_irls_estimator};
+
+    fn register(alignee: PointCloud<f32, 3>, target: &PointCloud<f32, 3>) -> Isometry3<f32> {
+        Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(NearestNeighbor::new(target))
+            .estimate_step_transform(build_isometry_estimator(target, 10))
+            .is_converged(same_squared_distance_error(1e-10))
+            .estimate_transform(alignee, target)
+            .0
+    }
+
+    #[test]
+    fn recovers_isometry() {
+        let target = wavy_surface(4.0, 0.2);
+
+        let transform = Isometry3::new(
+            Vector3::new(0.05, -0.04, 0.03),
+            Vector3::new(0.02, -0.01, 0.03),
+        );
+        let alignee = transformed(&target, &transform);
+
+        assert_isometry_eq(&register(alignee, &target), &transform.inverse(), 1e-3);
+    }
+
+    #[test]
+    fn recovers_isometry_with_irls() {
+        let target = wavy_surface(4.0, 0.2);
+
+        let transform = Isometry3::new(
+            Vector3::new(0.05, -0.04, 0.03),
+            Vector3::new(0.02, -0.01, 0.03),
+        );
+        let alignee = transformed(&target, &transform);
+
+        let (result, _) = Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .step_transform_estimator(build_irls_estimator(
+                build_isometry_estimator(&target, 10),
+                RobustKernel::Cauchy(0.1),
+                3,
+            ))
+            .is_converged(same_squared_distance_error(1e-10))
+            .estimate_transform(alignee, &target);
+
+        assert_isometry_eq(&result, &transform.inverse(), 1e-3);
+    }
+
+    #[test]
+    fn recovers_constrained_dofs_of_single_plane() {
+        let target = floor(4.0, 0.2);
+
+        let transform = Isometry3::new(Vector3::new(0.0, 0.0, 0.1), Vector3::new(0.02, -0.03, 0.0));
+        let alignee = transformed(&target, &transform);
+
+        let aligned = transformed(&alignee, &register(alignee.clone(), &target));
+        for p in &aligned {
+            assert!(p.pos.z.abs() < 1e-3, "{p:?} is not on the plane");
+        }
+    }
+
+    #[test]
+    fn covariances_of_plane_are_flat_along_normal() {
+        let covariances = compute_covariances(&floor(1.0, 0.2), 8, 0.001);
+
+        for covariance in covariances {
+            assert!((covariance * Vector3::z() - Vector3::z() * 0.001).norm() < 1e-5);
+            assert!((covariance * Vector3::x() - Vector3::x()).norm() < 1e-5);
+            assert!((covariance * Vector3::y() - Vector3::y()).norm() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::MaskedPointCloud;
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::motion_prior::{MotionPrior, PriorEstimator};
+
+/// The residuals that are minimized by [`build_isometry_estimator`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Residual<T> {
+    /// Euclidean distance between the corresponding points.
+    PointToPoint,
+    /// Distance of the alignee point to the tangent plane of the target point. The target needs normals.
+    PointToPlane,
+    /// Weighted sum of the squared point-to-plane (weighted with the given value in `0..=1`) and the squared
+    /// point-to-point distances (weighted with one minus the given value). The target needs normals.
+    Mixed(T),
+}
+
+/// Exponential map from the Lie algebra se(3) to the rigid transform.
+///
+/// The first three components of `xi` are the rotation vector and the last three the translational part.
+pub fn se3_exp<T>(xi: &Vector6<T>) -> Isometry3<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let omega = Vector3::new(xi[0], xi[1], xi[2]);
+    let v = Vector3::new(xi[3], xi[4], xi[5]);
+
+    let theta_squared = omega.norm_squared();
+    let theta = Float::sqrt(theta_squared);
+    let omega_hat = omega.cross_matrix();
+
+    let (b, c) = if theta < nalgebra::convert(1e-4) {
+        // Taylor expansion around zero
+        (
+            nalgebra::convert::<_, T>(0.5) - theta_squared / nalgebra::convert(24.0),
+            nalgebra::convert::<_, T>(1.0 / 6.0) - theta_squared / nalgebra::convert(120.0),
+        )
+    } else {
+        (
+            (T::one() - Float::cos(theta)) / theta_squared,
+            (theta - Float::sin(theta)) / (theta_squared * theta),
+        )
+    };
+
+    let left_jacobian = Matrix3::identity() + omega_hat * b + omega_hat * omega_hat * c;
+
+    Isometry3::from_parts(
+        Translation3::from(left_jacobian * v),
+        UnitQuaternion::from_scaled_axis(omega),
+    )
+}
+
+/// Builds a transform estimator that minimizes the given `residual` exactly on SE(3) with Levenberg-Marquardt.
+///
+/// Unlike [`crate::transform_estimation::point_to_plane_lls::estimate_isometry`] the rotation is not linearized
+/// with the small angle approximation. Instead the transform is updated with the exponential map of a
+/// Lie-algebra increment (see [`se3_exp`]) and the residuals are re-evaluated for the transformed alignee after every
+/// update. The correspondences stay the ones of the ICP step. This keeps the estimate accurate for large rotations,
+/// which is useful for the first iterations when the initial guess is rough.
+///
+/// Every ICP step runs at most `max_iterations` Levenberg-Marquardt iterations. The weights of the alignee and
+/// the target points are respected.
+///
+/// See this [tutorial from Blanco](https://arxiv.org/abs/2103.15980) for the Lie group derivatives.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::levenberg_marquardt::{build_isometry_estimator, Residual};
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(build_isometry_estimator(Residual::Mixed(0.9), 10))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn build_isometry_estimator<T>(
+    residual: Residual<T>,
+    max_iterations: usize,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mut estimate = build_estimator(residual, max_iterations, None);
+
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, _: usize| {
+        estimate(alignee, target, &Isometry3::identity())
+    }
+}
+
+/// Builds a transform estimator like [`build_isometry_estimator`] that is regularized toward the pose of the `prior`.
+///
+/// The cost of the prior (see [`MotionPrior::normal_equations`]) is added to the cost of the residuals in every
+/// Levenberg-Marquardt iteration. This keeps the estimate close to the prior in directions that the geometry doesn't
+/// constrain.
+///
+/// Set the returned [`PriorEstimator`] with [`crate::Icp::step_transform_estimator`] which starts it over in every run.
+/// The pose of the prior is relative to the alignee that is given to the ICP. If the ICP starts from an initial
+/// transform (see [`crate::Icp::initial_transform`]) use [`MotionPrior::relative_to`].
+pub fn build_isometry_estimator_with_prior<T>(
+    residual: Residual<T>,
+    max_iterations: usize,
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    PriorEstimator::new(build_estimator(residual, max_iterations, Some(prior)))
+}
+
+#[allow(non_snake_case)]
+fn build_estimator<T>(
+    residual: Residual<T>,
+    max_iterations: usize,
+    prior: Option<MotionPrior<T>>,
+) -> impl FnMut(
+    &mut MaskedPointCloud<T, 3>,
+    &mut MaskedPointCloud<T, 3>,
+    &Isometry3<T>,
+) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let (plane_weight, point_weight) = match residual {
+        Residual::PointToPoint => (T::zero(), T::one()),
+        Residual::PointToPlane => (T::one(), T::zero()),
+        Residual::Mixed(plane_weight) => (plane_weight, T::one() - plane_weight),
+    };
+
+    // the transform that has been applied to the alignee so far is only needed for the prior
+    move |alignee: &mut MaskedPointCloud<T, 3>,
+          target: &mut MaskedPointCloud<T, 3>,
+          current_transform: &Isometry3<T>| {
+        // accumulates the normal equations and the cost for the alignee transformed by `transform`
+        let linearize = |transform: &Isometry3<T>| -> Option<(Matrix6<T>, Vector6<T>, T)> {
+            let mut H = Matrix6::<T>::zeros();
+            let mut g = Vector6::<T>::zeros();
+            let mut cost = T::zero();
+
+            for (a, t) in alignee.iter().zip(target.iter()) {
+                let weight = a.weight * t.weight;
+
+                let pos = transform * a.pos;
+                let diff = pos - t.pos;
+
+                // derivative of the transformed point w.r.t. a left increment
+                let mut J = Matrix3x6::<T>::zeros();
+                J.fixed_view_mut::<3, 3>(0, 0)
+                    .copy_from(&-pos.coords.cross_matrix());
+                J.fixed_view_mut::<3, 3>(0, 3)
+                    .copy_from(&Matrix3::identity());
+
+                if plane_weight > T::zero() {
+                    let norm = t.norm?;
+                    let r = diff.dot(&norm);
+                    let J_plane = J.transpose() * norm;
+
+                    H += J_plane * J_plane.transpose() * (weight * plane_weight);
+                    g += J_plane * (r * weight * plane_weight);
+                    cost += r * r * weight * plane_weight;
+                }
+
+                if point_weight > T::zero() {
+                    H += J.transpose() * J * (weight * point_weight);
+                    g += J.transpose() * diff * (weight * point_weight);
+                    cost += diff.norm_squared() * weight * point_weight;
+                }
+            }
+
+            if let Some(prior) = &prior {
+                let error = prior.error(&(transform * current_transform));
+                let weighted_error = prior.information * error;
+
+                H += prior.information;
+                g += weighted_error;
+                cost += error.dot(&weighted_error);
+            }
+
+            Some((H, g, cost))
+        };
+
+        let mut transform = Isometry3::identity();
+        let (mut H, mut g, mut cost) = linearize(&transform)?;
+
+        let mut lambda: T = nalgebra::convert(1e-4);
+        let min_step: T = nalgebra::convert(1e-8);
+
+        for _ in 0..max_iterations {
+            let mut damped_H = H;
+            for i in 0..6 {
+                damped_H[(i, i)] += lambda * Float::max(H[(i, i)], T::default_epsilon());
+            }
+
+            let Some(chol) = damped_H.cholesky() else {
+                lambda *= nalgebra::convert(10.0);
+                continue;
+            };
+            let delta = -chol.solve(&g);
+
+            let candidate = se3_exp(&delta) * transform;
+            let (candidate_H, candidate_g, candidate_cost) = linearize(&candidate)?;
+
+            if candidate_cost < cost {
+                transform = candidate;
+                (H, g, cost) = (candidate_H, candidate_g, candidate_cost);
+                lambda = Float::max(lambda / nalgebra::convert(10.0), nalgebra::convert(1e-10));
+            } else {
+                lambda *= nalgebra::convert(10.0);
+            }
+
+            if delta.norm() < min_step {
+                break;
+            }
+        }
+
+        Some(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{PointCloud, PointCloudPoint};
+
+    /// Exponential of the 4x4 twist matrix by its power series.
+    fn matrix_exp(xi: &Vector6<f64>) -> Matrix4<f64> {
+        let mut twist = Matrix4::zeros();
+        twist
+            .fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&Vector3::new(xi[0], xi[1], xi[2]).cross_matrix());
+        twist
+            .fixed_view_mut::<3, 1>(0, 3)
+            .copy_from(&Vector3::new(xi[3], xi[4], xi[5]));
+
+        let mut sum = Matrix4::identity();
+        let mut term = Matrix4::identity();
+        for k in 1..30 {
+            term = term * twist / k as f64;
+            sum += term;
+        }
+        sum
+    }
+
+    fn estimate(
+        alignee: &PointCloud<f32, 3>,
+        target: &PointCloud<f32, 3>,
+        residual: Residual<f32>,
+    ) -> Option<Isometry3<f32>> {
+        build_isometry_estimator(residual, 20)(
+            &mut MaskedPointCloud::new(alignee),
+            &mut MaskedPointCloud::new(target),
+            0,
+        )
+    }
+
+    #[test]
+    fn se3_exp_matches_matrix_exponential() {
+        for xi in [
+            Vector6::new(0.0, 0.0, 0.0, 1.0, -2.0, 0.5),
+            Vector6::new(0.3, -0.2, 0.1, 1.0, -2.0, 0.5),
+            Vector6::new(1.5, 0.7, -2.0, -0.3, 0.2, 4.0),
+            Vector6::new(5e-5, 0.0, -5e-5, 0.1, 0.2, 0.3),
+        ] {
+            let difference = se3_exp(&xi).to_homogeneous() - matrix_exp(&xi);
+            assert!(difference.norm() < 1e-9, "{xi:?} is off by {difference}");
+        }
+    }
+
+    #[test]
+    fn se3_exp_is_continuous_at_taylor_threshold() {
+        let axis = Vector3::new(1.0, 2.0, -1.0).normalize();
+        let v = Vector3::new(0.5, -1.0, 2.0);
+
+        let below = Vector6::from_iterator(axis.scale(0.99e-4).iter().chain(v.iter()).copied());
+        let above = Vector6::from_iterator(axis.scale(1.01e-4).iter().chain(v.iter()).copied());
+
+        let difference = se3_exp(&below).to_homogeneous() - se3_exp(&above).to_homogeneous();
+        assert!(difference.norm() < 1e-5);
+    }
+
+    #[test]
+    fn recovers_large_rotation_in_one_step() {
+        let alignee = wavy_surface(4.0, 0.25);
+
+        let transform = Isometry3::new(Vector3::new(0.3, -0.2, 0.5), Vector3::new(0.4, -0.3, 0.6));
+        let target = transformed(&alignee, &transform);
+
+        for residual in [
+            Residual::PointToPoint,
+            Residual::PointToPlane,
+            Residual::Mixed(0.9),
+        ] {
+            let result = estimate(&alignee, &target, residual).unwrap();
+            assert_isometry_eq(&result, &transform, 1e-3);
+        }
+    }
+
+    #[test]
+    fn handles_collinear_points() {
+        let alignee: PointCloud<f32, 3> = (0..20)
+            .map(|i| PointCloudPoint::from_pos(Point3::new(i as f32 * 0.1, 0.0, 0.0)))
+            .collect();
+
+        let transform = Isometry3::new(Vector3::new(0.1, 0.2, -0.1), Vector3::new(0.0, 0.05, 0.1));
+        let target = transformed(&alignee, &transform);
+
+        // the rotation around the line is unobservable, but the points still have to match
+        let result = estimate(&alignee, &target, Residual::PointToPoint).unwrap();
+        for (a, t) in alignee.iter().zip(&target) {
+            assert!((result * a.pos - t.pos).norm() < 1e-3);
+        }
+    }
+}
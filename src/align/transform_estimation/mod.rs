@@ -1,14 +1,26 @@
 use std::fmt::Debug;
 
-use nalgebra::Scalar;
+use nalgebra::{Matrix6, RealField, Scalar};
 
-use crate::MaskedPointCloud;
+use crate::{IcpError, MaskedPointCloud};
 
 pub mod affine_transformation;
+pub mod colored;
+pub mod constrained;
+pub mod correntropy;
+pub mod gicp;
+pub mod levenberg_marquardt;
+pub mod motion_prior;
+pub mod ndt;
+pub mod pl_icp;
+pub mod point_to_line_lls;
 pub mod point_to_plane_lls;
 pub mod point_to_plane_lls_weighted;
+pub mod robust;
 pub mod scale;
+pub mod schedule;
 pub mod svd;
+pub mod symmetric_point_to_plane;
 pub mod transform_modifier;
 pub mod translation_point_to_plane;
 
@@ -22,6 +34,25 @@ where
         target: &mut MaskedPointCloud<T, D>,
         step: usize,
     ) -> Option<M>;
+
+    /// Like [`TransformEstimator::estimate`] but tells why the estimation failed.
+    ///
+    /// Defaults to [`IcpError::EstimationFailed`] if [`TransformEstimator::estimate`] returns `None`.
+    fn try_estimate(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        step: usize,
+    ) -> Result<M, IcpError> {
+        self.estimate(alignee, target, step)
+            .ok_or(IcpError::EstimationFailed)
+    }
+
+    /// Called by the [`crate::Icp`] before the first iteration of every run.
+    ///
+    /// Estimators that change from iteration to iteration, like the stages of a
+    /// [`schedule::ScheduledEstimator`], start over here. Does nothing by default.
+    fn reset(&mut self) {}
 }
 
 impl<F, T, M, const D: usize> TransformEstimator<T, M, D> for F
@@ -38,3 +69,116 @@ where
         self(alignee, target, step)
     }
 }
+
+/// A transform estimator that returns a `Result`. Its errors are reported by [`crate::Icp::try_estimate_transform`].
+///
+/// Created by [`crate::Icp::try_estimate_step_transform`].
+pub struct Fallible<F>(pub F);
+
+impl<F, T, M, const D: usize> TransformEstimator<T, M, D> for Fallible<F>
+where
+    F: FnMut(
+        &mut MaskedPointCloud<T, D>,
+        &mut MaskedPointCloud<T, D>,
+        usize,
+    ) -> Result<M, IcpError>,
+    T: Debug + Scalar + Copy,
+{
+    fn estimate(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        step: usize,
+    ) -> Option<M> {
+        (self.0)(alignee, target, step).ok()
+    }
+
+    fn try_estimate(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        step: usize,
+    ) -> Result<M, IcpError> {
+        (self.0)(alignee, target, step)
+    }
+}
+
+/// Estimates the uncertainty of a transform from the correspondences it was estimated from.
+///
+/// The residuals have to be evaluated for the alignee points transformed by `transform`. The [`crate::Icp`] calls
+/// it once with the correspondences and the step transform of the last iteration.
+///
+/// See for example [`point_to_plane_lls::estimate_covariance`] and [`svd::estimate_covariance`].
+pub trait CovarianceEstimator<T, M, const D: usize>
+where
+    T: Debug + Scalar + Copy,
+{
+    type Covariance;
+
+    /// `false` if the estimator never returns a covariance. The [`crate::Icp`] then doesn't keep the correspondences
+    /// of the last iteration.
+    const ESTIMATES_COVARIANCE: bool = true;
+
+    fn estimate_covariance(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        transform: &M,
+    ) -> Option<Self::Covariance>;
+}
+
+impl<F, T, M, C, const D: usize> CovarianceEstimator<T, M, D> for F
+where
+    F: FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, &M) -> Option<C>,
+    T: Debug + Scalar + Copy,
+{
+    type Covariance = C;
+
+    fn estimate_covariance(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        transform: &M,
+    ) -> Option<C> {
+        self(alignee, target, transform)
+    }
+}
+
+/// Default implementation that doesn't estimate any covariance.
+pub struct NoCovariance;
+
+impl<T, M, const D: usize> CovarianceEstimator<T, M, D> for NoCovariance
+where
+    T: Debug + Scalar + Copy,
+{
+    type Covariance = ();
+
+    const ESTIMATES_COVARIANCE: bool = false;
+
+    fn estimate_covariance(
+        &mut self,
+        _: &mut MaskedPointCloud<T, D>,
+        _: &mut MaskedPointCloud<T, D>,
+        _: &M,
+    ) -> Option<()> {
+        None
+    }
+}
+
+/// Scales the inverse of the Gauss-Newton Hessian `H` (`J^T * W * J` of the increment `[ω, v]`) with the residual
+/// variance estimated from the weighted `squared_residual_sum` of `residual_count` scalar residuals.
+#[allow(non_snake_case)]
+pub(crate) fn pose_covariance<T>(
+    H: Matrix6<T>,
+    squared_residual_sum: T,
+    residual_count: usize,
+) -> Option<Matrix6<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    // six degrees of freedom are used up by the transform
+    let degrees_of_freedom = residual_count.checked_sub(6).filter(|dof| *dof > 0)?;
+    let variance = squared_residual_sum / T::from_usize(degrees_of_freedom)?;
+
+    H.try_inverse().map(|H_inv| H_inv * variance)
+}
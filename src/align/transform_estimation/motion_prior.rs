@@ -0,0 +1,257 @@
+use nalgebra::*;
+
+use crate::MaskedPointCloud;
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::svd::rotation_from_covariance;
+
+/// A prior for the transform of the alignee, e.g. a pose predicted from wheel odometry or an IMU.
+///
+/// The prior pulls the estimated transform toward `pose` with the strength given by the 6x6 `information` matrix.
+/// The information matrix is ordered `[ω, v]` (rotation vector followed by the translation) like the covariances
+/// of [`crate::transform_estimation::point_to_plane_lls::estimate_covariance`], so the inverse of a pose covariance
+/// can be used directly.
+///
+/// The geometric residuals of the estimators are not weighted, so the information is relative to them. If the
+/// information is the inverse of a covariance, multiply it by the variance of the geometric residuals to get a
+/// statistically consistent weighting.
+///
+/// Use it with the `build_isometry_estimator_with_prior` functions of
+/// [`crate::transform_estimation::point_to_plane_lls`], [`crate::transform_estimation::point_to_plane_lls_weighted`],
+/// [`crate::transform_estimation::symmetric_point_to_plane`], [`crate::transform_estimation::gicp`] and
+/// [`crate::transform_estimation::levenberg_marquardt`] or with
+/// [`crate::transform_estimation::constrained::build_constrained_estimator_with_prior`] and
+/// [`crate::transform_estimation::constrained::build_masked_estimator_with_prior`]. They return a [`PriorEstimator`]
+/// that is set with [`crate::Icp::step_transform_estimator`].
+/// Custom least squares estimators can add [`MotionPrior::normal_equations`] to their normal equations and use
+/// [`PriorEstimator::new`] to get the transform that has been applied to the alignee so far.
+#[derive(Clone, Copy, Debug)]
+pub struct MotionPrior<T: Scalar> {
+    pub pose: Isometry3<T>,
+    pub information: Matrix6<T>,
+}
+
+impl<T> MotionPrior<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    pub fn new(pose: Isometry3<T>, information: Matrix6<T>) -> Self {
+        Self { pose, information }
+    }
+
+    /// The prior for an alignee that has already been transformed by `initial_transform`, e.g. with
+    /// [`crate::Icp::initial_transform`]. The information stays the same.
+    pub fn relative_to(&self, initial_transform: &Isometry3<T>) -> Self {
+        Self {
+            pose: self.pose * initial_transform.inverse(),
+            information: self.information,
+        }
+    }
+
+    /// The difference `[ω, v]` between the `transform` and the prior pose as a left perturbation, i.e.
+    /// `transform ≈ exp([ω, v]) * pose` for small differences.
+    pub fn error(&self, transform: &Isometry3<T>) -> Vector6<T> {
+        let difference = transform * self.pose.inverse();
+
+        let rotation = difference.rotation.scaled_axis();
+        let translation = difference.translation.vector;
+
+        Vector6::new(
+            rotation.x,
+            rotation.y,
+            rotation.z,
+            translation.x,
+            translation.y,
+            translation.z,
+        )
+    }
+
+    /// The block `(Ω, -Ω * e)` that has to be added to the normal equations `H * x = b` of a problem that is
+    /// linearized for the left increment `x = [ω, v]` at the current `transform`, where `e` is [`MotionPrior::error`].
+    ///
+    /// This adds the cost `(x + e)^T * Ω * (x + e)`.
+    pub fn normal_equations(&self, transform: &Isometry3<T>) -> (Matrix6<T>, Vector6<T>) {
+        let error = self.error(transform);
+
+        (self.information, -(self.information * error))
+    }
+}
+
+/// A transform estimator that is regularized toward a [`MotionPrior`].
+///
+/// It recovers the transform that has been applied to the alignee since the start of the ICP run with
+/// [`AppliedTransform`] and passes it to `estimate_transform` together with the alignee and the target.
+/// [`TransformEstimator::reset`] starts over, so set it with [`crate::Icp::step_transform_estimator`] which resets it
+/// at the start of every run.
+pub struct PriorEstimator<T: Scalar, F> {
+    applied_transform: AppliedTransform<T>,
+    estimate_transform: F,
+}
+
+impl<T, F> PriorEstimator<T, F>
+where
+    T: Scalar + RealField + Copy,
+    F: FnMut(
+        &mut MaskedPointCloud<T, 3>,
+        &mut MaskedPointCloud<T, 3>,
+        &Isometry3<T>,
+    ) -> Option<Isometry3<T>>,
+{
+    pub fn new(estimate_transform: F) -> Self {
+        Self {
+            applied_transform: AppliedTransform::new(),
+            estimate_transform,
+        }
+    }
+}
+
+impl<T, F> TransformEstimator<T, Isometry3<T>, 3> for PriorEstimator<T, F>
+where
+    T: Scalar + RealField + Copy,
+    F: FnMut(
+        &mut MaskedPointCloud<T, 3>,
+        &mut MaskedPointCloud<T, 3>,
+        &Isometry3<T>,
+    ) -> Option<Isometry3<T>>,
+{
+    fn estimate(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, 3>,
+        target: &mut MaskedPointCloud<T, 3>,
+        _: usize,
+    ) -> Option<Isometry3<T>> {
+        let current_transform = self.applied_transform.update(alignee);
+
+        (self.estimate_transform)(alignee, target, &current_transform)
+    }
+
+    fn reset(&mut self) {
+        self.applied_transform = AppliedTransform::new();
+    }
+}
+
+/// Recovers the transform that has been applied to the alignee since a transform estimator saw it first.
+///
+/// The ICP passes the whole transformed alignee point cloud to the transform estimators, so the applied transform is
+/// the rigid transform between the positions of the first call and the current ones. Unlike composing the returned
+/// step transforms this stays correct if the estimator is called several times per ICP step, e.g. by
+/// [`crate::transform_estimation::robust::build_irls_estimator`], or if its results are modified before they are
+/// applied. [`PriorEstimator`] starts it over whenever the ICP resets its transform estimator.
+///
+/// The rotation is ambiguous if all points lie on a line.
+#[derive(Clone, Debug, Default)]
+pub struct AppliedTransform<T: Scalar> {
+    reference: Vec<Point3<T>>,
+}
+
+impl<T> AppliedTransform<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    pub fn new() -> Self {
+        Self { reference: vec![] }
+    }
+
+    /// The transform from the positions of the first call to the current positions of the `alignee`.
+    ///
+    /// The first call and every call with a point cloud of a different size start over and return the identity.
+    pub fn update(&mut self, alignee: &MaskedPointCloud<T, 3>) -> Isometry3<T> {
+        let points = alignee.point_cloud;
+
+        if points.is_empty() || points.len() != self.reference.len() {
+            self.reference = points.iter().map(|p| p.pos).collect();
+            return Isometry3::identity();
+        }
+
+        let count = T::from_usize(points.len()).unwrap_or_else(T::one);
+
+        let reference_centroid = self
+            .reference
+            .iter()
+            .fold(Vector3::zeros(), |sum, p| sum + p.coords)
+            / count;
+        let current_centroid = points
+            .iter()
+            .fold(Vector3::zeros(), |sum, p| sum + p.pos.coords)
+            / count;
+
+        let covariant_matrix =
+            self.reference
+                .iter()
+                .zip(points)
+                .fold(Matrix3::zeros(), |sum, (r, p)| {
+                    sum + (r.coords - reference_centroid)
+                        * (p.pos.coords - current_centroid).transpose()
+                });
+
+        let Some(rotation) = rotation_from_covariance(covariant_matrix) else {
+            return Isometry3::translation(
+                current_centroid.x - reference_centroid.x,
+                current_centroid.y - reference_centroid.y,
+                current_centroid.z - reference_centroid.z,
+            );
+        };
+        let rotation = UnitQuaternion::from_matrix(&rotation);
+
+        Isometry3::from_parts(
+            Translation3::from(current_centroid - rotation * reference_centroid),
+            rotation,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Icp;
+    use crate::convergence::never;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::test_utils::{assert_isometry_eq, floor, transformed};
+    use crate::transform_estimation::point_to_plane_lls;
+
+    #[test]
+    fn applied_transform_is_relative_to_first_call() {
+        let cloud = floor(1.0, 0.25);
+        let transform = Isometry3::new(Vector3::new(0.1, -0.2, 0.3), Vector3::new(0.0, 0.0, 0.2));
+
+        let mut applied_transform = AppliedTransform::new();
+        let first = applied_transform.update(&MaskedPointCloud::new(&cloud));
+        let moved = transformed(&cloud, &transform);
+        let second = applied_transform.update(&MaskedPointCloud::new(&moved));
+
+        assert_isometry_eq(&first, &Isometry3::identity(), 1e-6);
+        assert_isometry_eq(&second, &transform, 1e-4);
+    }
+
+    #[test]
+    fn prior_pulls_featureless_plane() {
+        // The floor only constrains the height, roll and pitch. The prior is wrong about the height, but its
+        // information is small compared to the points, so the geometry decides it.
+        let target = floor(1.0, 0.1);
+        let alignee = transformed(&target, &Isometry3::translation(0.0, 0.0, 0.3));
+
+        let mut information = Matrix6::identity();
+        information.fixed_view_mut::<3, 3>(3, 3).fill_diagonal(0.01);
+        let prior = MotionPrior::new(
+            Isometry3::new(Vector3::new(0.2, -0.1, 0.0), Vector3::new(0.0, 0.0, 0.1)),
+            information,
+        );
+
+        let mut icp = Icp::new()
+            .max_iterations(20)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .step_transform_estimator(point_to_plane_lls::build_isometry_estimator_with_prior(
+                prior,
+            ))
+            .is_converged(never);
+
+        let (transform, _) = icp.estimate_transform(alignee.clone(), &target);
+
+        let expected = Isometry3::new(Vector3::new(0.2, -0.1, -0.3), Vector3::new(0.0, 0.0, 0.1));
+        assert_isometry_eq(&transform, &expected, 1e-3);
+
+        // The second run has to start over instead of measuring the applied transform from the first alignee.
+        let shifted_alignee = transformed(&alignee, &Isometry3::translation(0.05, 0.0, 0.0));
+        let (second_transform, _) = icp.estimate_transform(shifted_alignee, &target);
+        assert_isometry_eq(&second_transform, &expected, 1e-3);
+    }
+}
@@ -0,0 +1,115 @@
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::MaskedPointCloud;
+use crate::correspondence::NdtGrid;
+
+/// Builds a transform estimator for Normal Distributions Transform (NDT) registration.
+///
+/// Every step minimizes the negative log-likelihood of the alignee points under the normal distributions
+/// of the cells they correspond to, i.e. the sum of the squared Mahalanobis distances, with one Gauss-Newton step.
+/// The weights of the alignee and the target points are respected.
+///
+/// It has to be used with the [`crate::correspondence::Ndt`] correspondence estimator built for the same `grid`
+/// because the corresponding target points are looked up in the cells of the grid.
+///
+/// See this [thesis from Magnusson](https://urn.kb.se/resolve?urn=urn:nbn:se:oru:diva-8458)
+#[allow(non_snake_case)]
+pub fn build_isometry_estimator<T>(
+    grid: &NdtGrid<T>,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let inverse_covariances: Vec<Matrix3<T>> = grid
+        .cells()
+        .iter()
+        .map(|cell| cell.inverse_covariance)
+        .collect();
+
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, _: usize| {
+        let mut H = Matrix6::<T>::zeros();
+        let mut g = Vector6::<T>::zeros();
+
+        for (a, (t, t_idx)) in alignee.iter().zip(
+            target
+                .iter()
+                .zip(target.masked_and_ordered_to_plain_index.iter()),
+        ) {
+            let inverse_covariance = inverse_covariances.get(*t_idx)?;
+            let weight = a.weight * t.weight;
+
+            let residual = a.pos - t.pos;
+
+            // derivative of the residual w.r.t. the small rotation vector and the translation
+            let mut J = Matrix3x6::<T>::zeros();
+            J.fixed_view_mut::<3, 3>(0, 0)
+                .copy_from(&-a.pos.coords.cross_matrix());
+            J.fixed_view_mut::<3, 3>(0, 3)
+                .copy_from(&Matrix3::identity());
+
+            let JT_inv_cov = J.transpose() * inverse_covariance;
+
+            H += JT_inv_cov * J * weight;
+            g += JT_inv_cov * residual * weight;
+        }
+
+        let x = -H.cholesky()?.solve(&g);
+
+        Some(Isometry3::new(
+            Vector3::new(x[3], x[4], x[5]),
+            Vector3::new(x[0], x[1], x[2]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, Ndt};
+    use crate::test_utils::*;
+    use crate::{Icp, PointCloud};
+
+    fn register(alignee: PointCloud<f32, 3>, grid: &NdtGrid<f32>) -> Isometry3<f32> {
+        Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(Ndt::new(grid))
+            .estimate_step_transform(build_isometry_estimator(grid))
+            .is_converged(same_squared_distance_error(1e-10))
+            .estimate_transform(alignee, grid)
+            .0
+    }
+
+    #[test]
+    fn recovers_isometry() {
+        let target = room_corner(4.0, 0.1);
+        let grid = NdtGrid::new(&target, 1.0, 6);
+
+        let transform = Isometry3::new(
+            Vector3::new(0.1, -0.05, 0.08),
+            Vector3::new(0.03, -0.02, 0.04),
+        );
+        let alignee = transformed(&target, &transform);
+
+        assert_isometry_eq(&register(alignee, &grid), &transform.inverse(), 1e-2);
+    }
+
+    #[test]
+    fn recovers_constrained_dofs_of_single_plane() {
+        let target = floor(4.0, 0.1);
+        let grid = NdtGrid::new(&target, 1.0, 6);
+
+        let transform = Isometry3::new(Vector3::new(0.0, 0.0, 0.1), Vector3::new(0.02, -0.03, 0.0));
+        let alignee = transformed(&target, &transform);
+
+        let result = register(alignee.clone(), &grid);
+
+        // the plane itself is matched even if the in-plane sliding isn't observable
+        let aligned = transformed(&alignee, &result);
+        for p in &aligned {
+            assert!(p.pos.z.abs() < 1e-2, "{p:?} is not on the plane");
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use nalgebra::*;
+use num_traits::Float;
+use std::cmp::Ordering;
+
+use crate::MaskedPointCloud;
+
+/// Estimates the 2D isometry between the alignee and the target that minimizes the point-to-line distances exactly.
+///
+/// This is the step of PL-ICP. Unlike [`super::point_to_line_lls::estimate_isometry`] the rotation is not linearized.
+/// The unknowns are the translation and `[cos θ, sin θ]` with the constraint `cos² θ + sin² θ = 1`. The translation
+/// is eliminated in closed form and the remaining problem on the unit circle is solved with a Lagrange multiplier.
+/// This converges in a few iterations even for large rotations.
+///
+/// The target points need normals. Use it together with the [`crate::correspondence::ScanSegments`] correspondence
+/// estimator which computes the closest points on the segments of the target scan together with their normals.
+/// The weights of the points are respected.
+/// Returns `None` if a target point has no normal or the correspondences don't constrain the translation.
+///
+/// See this [paper from Censi](https://doi.org/10.1109/ROBOT.2008.4543181)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, ScanSegments};
+/// # use modern_icp::transform_estimation::pl_icp;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_scan = PointCloud::<f32, 2>::new();
+/// # let target_scan = PointCloud::<f32, 2>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(ScanSegments::new(&target_scan))
+///     .estimate_step_transform(pl_icp::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_scan, &target_scan);
+/// ```
+#[allow(non_snake_case)]
+pub fn estimate_isometry<T>(
+    alignee: &mut MaskedPointCloud<T, 2>,
+    target: &mut MaskedPointCloud<T, 2>,
+    _: usize,
+) -> Option<Isometry2<T>>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    // the residual n · (R * p + t - q) is linear in x = [t_x, t_y, cos θ, sin θ]
+    let mut M = Matrix4::<T>::zeros();
+    let mut b = Vector4::<T>::zeros();
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let n = t.norm?;
+        let p = a.pos;
+        let weight = a.weight * t.weight;
+
+        let row = Vector4::new(n.x, n.y, n.x * p.x + n.y * p.y, n.y * p.x - n.x * p.y);
+        let d = n.dot(&t.pos.coords);
+
+        M += row * row.transpose() * weight;
+        b += row * (d * weight);
+    }
+
+    let A = M.fixed_view::<2, 2>(0, 0).into_owned();
+    let B = M.fixed_view::<2, 2>(0, 2).into_owned();
+    let D = M.fixed_view::<2, 2>(2, 2).into_owned();
+    let b_t = b.fixed_rows::<2>(0).into_owned();
+    let b_u = b.fixed_rows::<2>(2).into_owned();
+
+    // for a fixed rotation u = [cos θ, sin θ] the optimal translation is A^-1 * (b_t - B * u)
+    let A_inv = A.try_inverse()?;
+
+    // which leaves the cost u^T * S * u + 2 * h^T * u on the unit circle
+    let S = D - B.transpose() * A_inv * B;
+    let h = -(b_u - B.transpose() * A_inv * b_t);
+
+    let u = minimize_on_unit_circle(&S, &h)?;
+    let translation = A_inv * (b_t - B * u);
+
+    Some(Isometry2::new(translation, Float::atan2(u.y, u.x)))
+}
+
+/// Minimizes `u^T * S * u + 2 * h^T * u` subject to `|u| = 1` for a symmetric `S`.
+///
+/// The minimum satisfies `(S + λ * I) * u = -h` for the largest `λ` with `|u| = 1`, which is found with bisection on
+/// the secular equation in the eigenbasis of `S`. If no such `λ` exists because `h` is (almost) orthogonal to the
+/// eigenvector of the smallest eigenvalue, the solution is completed along that eigenvector.
+#[allow(non_snake_case)]
+fn minimize_on_unit_circle<T>(S: &Matrix2<T>, h: &Vector2<T>) -> Option<Vector2<T>>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    const BISECTION_ITERATIONS: usize = 100;
+
+    let eigen = S.symmetric_eigen();
+
+    let (min, max) = if eigen.eigenvalues[0] <= eigen.eigenvalues[1] {
+        (0, 1)
+    } else {
+        (1, 0)
+    };
+
+    let sigma = [eigen.eigenvalues[min], eigen.eigenvalues[max]];
+    let v = [
+        eigen.eigenvectors.column(min).into_owned(),
+        eigen.eigenvectors.column(max).into_owned(),
+    ];
+    let h_rotated = [v[0].dot(h), v[1].dot(h)];
+
+    let h_norm = h.norm();
+    if h_norm <= T::default_epsilon() {
+        return Some(v[0]);
+    }
+
+    let u_at = |lambda: T| {
+        v[0] * (-h_rotated[0] / (sigma[0] + lambda)) + v[1] * (-h_rotated[1] / (sigma[1] + lambda))
+    };
+
+    // |u(λ)| decreases monotonically for λ > -σ_min and is at most one at λ = -σ_min + |h|
+    let mut low = -sigma[0];
+    let mut high = -sigma[0] + h_norm;
+
+    for _ in 0..BISECTION_ITERATIONS {
+        let mid = (low + high) / nalgebra::convert(2.0);
+
+        if mid <= low || mid >= high {
+            break;
+        }
+
+        if u_at(mid).norm_squared() > T::one() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    // if h is (almost) orthogonal to the eigenvector of σ_min, |u(λ)| stays below one for all λ > -σ_min and the
+    // minimum lies at λ = -σ_min with the missing length along that eigenvector (the "hard case"). Close to it the
+    // bisection can't resolve λ, so the better one of the normalized and the completed solution is used.
+    let u = u_at(high);
+    let component_max = v[1].dot(&u);
+    let sign = if h_rotated[0] > T::zero() {
+        -T::one()
+    } else {
+        T::one()
+    };
+    let missing = Float::max(T::one() - component_max * component_max, T::zero());
+    let completed = v[1] * component_max + v[0] * (sign * Float::sqrt(missing));
+
+    let cost = |u: &Vector2<T>| u.dot(&(S * u)) + h.dot(u) * nalgebra::convert(2.0);
+
+    [u.try_normalize(T::default_epsilon()), Some(completed)]
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| cost(a).partial_cmp(&cost(b)).unwrap_or(Ordering::Equal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, ScanSegments};
+    use crate::{Icp, PointCloud, PointCloudPoint};
+
+    #[allow(non_snake_case)]
+    fn cost(S: &Matrix2<f64>, h: &Vector2<f64>, u: &Vector2<f64>) -> f64 {
+        u.dot(&(S * u)) + 2.0 * h.dot(u)
+    }
+
+    /// Outline of a room with a corner that breaks its symmetries, sampled in scan order.
+    fn room_scan(spacing: f32) -> PointCloud<f32, 2> {
+        let corners = [
+            Point2::new(0.0, 0.0),
+            Point2::new(4.0, 0.0),
+            Point2::new(4.0, 3.0),
+            Point2::new(2.0, 3.0),
+            Point2::new(2.0, 2.0),
+            Point2::new(0.0, 2.0),
+            Point2::new(0.0, 0.0),
+        ];
+
+        corners
+            .windows(2)
+            .flat_map(|segment| {
+                let (start, end) = (segment[0], segment[1]);
+                let count = ((end - start).norm() / spacing) as usize;
+                (0..count).map(move |i| {
+                    PointCloudPoint::from_pos(start + (end - start) * (i as f32 / count as f32))
+                })
+            })
+            .collect()
+    }
+
+    fn transformed_scan(
+        scan: &PointCloud<f32, 2>,
+        transform: &Isometry2<f32>,
+    ) -> PointCloud<f32, 2> {
+        scan.iter()
+            .map(|p| PointCloudPoint::from_pos(transform * p.pos))
+            .collect()
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn minimizes_on_unit_circle() {
+        let cases = [
+            (Matrix2::new(2.0, 0.5, 0.5, 1.0), Vector2::new(0.3, -0.7)),
+            (Matrix2::new(2.0, 0.5, 0.5, 1.0), Vector2::new(30.0, -70.0)),
+            (Matrix2::new(-1.0, 0.2, 0.2, 3.0), Vector2::new(0.1, 0.1)),
+            (Matrix2::new(1.0, 0.0, 0.0, 1.0), Vector2::new(0.5, 0.5)),
+            // h ≈ 0
+            (Matrix2::new(2.0, 0.5, 0.5, 1.0), Vector2::zeros()),
+            (
+                Matrix2::new(2.0, 0.5, 0.5, 1.0),
+                Vector2::new(1e-12, -1e-12),
+            ),
+            // h has no component along the eigenvector of the smallest eigenvalue
+            (Matrix2::new(0.0, 0.0, 0.0, 1.0), Vector2::new(0.0, 1e-6)),
+            (Matrix2::new(0.0, 0.0, 0.0, 1.0), Vector2::new(0.0, 0.4)),
+        ];
+
+        for (S, h) in cases {
+            let u = minimize_on_unit_circle(&S, &h).unwrap();
+
+            let brute_force_minimum = (0..100_000)
+                .map(|i| {
+                    let angle = i as f64 / 100_000.0 * std::f64::consts::TAU;
+                    cost(&S, &h, &Vector2::new(angle.cos(), angle.sin()))
+                })
+                .fold(f64::INFINITY, f64::min);
+
+            assert!((u.norm() - 1.0).abs() < 1e-9);
+            assert!(
+                cost(&S, &h, &u) < brute_force_minimum + 1e-8,
+                "{u:?} is not the minimum for S = {S:?}, h = {h:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn recovers_large_rotation_in_one_step() {
+        let target = room_scan(0.1);
+        let transform = Isometry2::new(Vector2::new(0.5, -0.3), 1.2);
+        let alignee = transformed_scan(&target, &transform.inverse());
+
+        // exact correspondences with the normals of the walls
+        let target: PointCloud<f32, 2> = target
+            .windows(2)
+            .map(|segment| {
+                let direction = segment[1].pos - segment[0].pos;
+                PointCloudPoint::from_pos_norm(
+                    segment[0].pos,
+                    Vector2::new(-direction.y, direction.x).normalize(),
+                )
+            })
+            .collect();
+        let alignee = alignee[..target.len()].to_vec();
+
+        let result = estimate_isometry(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+
+        assert!((result.translation.vector - transform.translation.vector).norm() < 1e-4);
+        assert!((result.rotation.angle() - transform.rotation.angle()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn registers_scans() {
+        let target = room_scan(0.05);
+        let transform = Isometry2::new(Vector2::new(0.1, -0.15), 0.1);
+        let alignee = transformed_scan(&target, &transform.inverse());
+
+        let (result, _) = Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(ScanSegments::new(&target).max_segment_length(0.5))
+            .estimate_step_transform(estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-12))
+            .estimate_transform(alignee, &target);
+
+        assert!((result.translation.vector - transform.translation.vector).norm() < 1e-3);
+        assert!((result.rotation.angle() - transform.rotation.angle()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_single_wall() {
+        let target: PointCloud<f32, 2> = (0..20)
+            .map(|i| PointCloudPoint::from_pos_norm(Point2::new(i as f32 * 0.1, 0.0), Vector2::y()))
+            .collect();
+        let alignee = transformed_scan(&target, &Isometry2::new(Vector2::new(0.0, 0.1), 0.05));
+
+        // the translation along the wall isn't constrained
+        assert!(
+            estimate_isometry(
+                &mut MaskedPointCloud::new(&alignee),
+                &mut MaskedPointCloud::new(&target),
+                0
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn rejects_missing_normals() {
+        let scan = room_scan(0.5);
+
+        assert!(
+            estimate_isometry(
+                &mut MaskedPointCloud::new(&scan),
+                &mut MaskedPointCloud::new(&scan),
+                0
+            )
+            .is_none()
+        );
+    }
+}
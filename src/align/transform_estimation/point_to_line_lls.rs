@@ -0,0 +1,150 @@
+use crate::MaskedPointCloud;
+use nalgebra::*;
+
+/// Estimates the 2D isometry between the alignee and the target using the linearized
+/// Point-to-Line algorithm. This is the 2D analogue of [`super::point_to_plane_lls::estimate_isometry`].
+///
+/// The target points need normals (see [`crate::estimate_normals`]).
+/// Returns `None` if a target point has no normal or the system of equations is singular.
+///
+/// See this [paper from Low](https://www.comp.nus.edu.sg/~lowkl/publications/lowk_point-to-plane_icp_techrep.pdf)
+/// for the 3D derivation.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud, estimate_normals};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::point_to_line_lls;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_scan = PointCloud::<f32, 2>::new();
+/// # let mut target_scan = PointCloud::<f32, 2>::new();
+/// #
+/// estimate_normals(&mut target_scan, 5);
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_scan))
+///     .estimate_step_transform(point_to_line_lls::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_scan, &target_scan);
+/// ```
+#[allow(non_snake_case)]
+pub fn estimate_isometry<T>(
+    alignee: &mut MaskedPointCloud<T, 2>,
+    target: &mut MaskedPointCloud<T, 2>,
+    _: usize,
+) -> Option<Isometry2<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    let mut ATA = Matrix3::<T>::zeros();
+    let mut ATb = Vector3::<T>::zeros();
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let norm_target = t.norm?;
+
+        let nx = norm_target.x;
+        let ny = norm_target.y;
+
+        // derivative of the rotated alignee point w.r.t. the rotation angle projected onto the normal
+        let c = nx * -a.pos.y + ny * a.pos.x;
+
+        let row = Vector3::new(c, nx, ny);
+
+        let d = norm_target.dot(&(t.pos - a.pos));
+
+        ATA += row * row.transpose();
+        ATb += row * d;
+    }
+
+    let x = ATA.cholesky()?.solve(&ATb);
+
+    Some(Isometry2::new(Vector2::new(x[1], x[2]), x[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::transform_estimation::svd;
+    use crate::{Icp, PointCloud, PointCloudPoint, estimate_normals};
+
+    /// Points on the outline of the rectangle `[0.5, 3.5] x [0.5, 2.5]`.
+    fn rectangle_scan() -> PointCloud<f32, 2> {
+        let mut scan = PointCloud::new();
+        for i in 0..60 {
+            let x = 0.5 + i as f32 * 0.05;
+            scan.push(PointCloudPoint::from_pos(Point2::new(x, 0.5)));
+            scan.push(PointCloudPoint::from_pos(Point2::new(x, 2.5)));
+        }
+        for i in 1..40 {
+            let y = 0.5 + i as f32 * 0.05;
+            scan.push(PointCloudPoint::from_pos(Point2::new(0.5, y)));
+            scan.push(PointCloudPoint::from_pos(Point2::new(3.5, y)));
+        }
+        scan
+    }
+
+    fn transformed_scan(
+        scan: &PointCloud<f32, 2>,
+        transform: &Isometry2<f32>,
+    ) -> PointCloud<f32, 2> {
+        scan.iter()
+            .map(|p| PointCloudPoint::from_pos(transform * p.pos))
+            .collect()
+    }
+
+    #[test]
+    fn registers_2d_scans() {
+        let mut target = rectangle_scan();
+        estimate_normals(&mut target, 5);
+
+        let transform = Isometry2::new(Vector2::new(0.08, -0.05), 0.04);
+        let alignee = transformed_scan(&target, &transform);
+
+        let (result, _) = Icp::new()
+            .max_iterations(50)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-12))
+            .estimate_transform(alignee, &target);
+
+        let difference = result * transform;
+        assert!(difference.translation.vector.norm() < 1e-3);
+        assert!(difference.rotation.angle().abs() < 1e-3);
+    }
+
+    #[test]
+    fn svd_estimates_2d_isometry_of_exact_correspondences() {
+        let target = rectangle_scan();
+
+        let transform = Isometry2::new(Vector2::new(0.5, -0.3), 1.0);
+        let alignee = transformed_scan(&target, &transform.inverse());
+
+        let result = svd::estimate_isometry(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+
+        assert!((result.translation.vector - transform.translation.vector).norm() < 1e-4);
+        assert!((result.rotation.angle() - transform.rotation.angle()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_missing_normals() {
+        let scan = rectangle_scan();
+
+        assert!(
+            estimate_isometry(
+                &mut MaskedPointCloud::new(&scan),
+                &mut MaskedPointCloud::new(&scan),
+                0
+            )
+            .is_none()
+        );
+    }
+}
@@ -1,17 +1,67 @@
+use std::ops::Mul;
+
 use nalgebra::*;
 
-use crate::{MaskedPointCloud, Plane};
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::motion_prior::{MotionPrior, PriorEstimator};
+use crate::transform_estimation::pose_covariance;
+use crate::{IcpError, MaskedPointCloud, Plane};
 
 /// Estimates the isometry between the alignee and the target using the Point-to-Plane-LLS algorithm.
 ///
+/// Returns `None` if a target point has no normal or the correspondences don't constrain all six degrees of freedom.
+/// Use [`try_estimate_isometry`] to find out which.
+///
 /// See this [implementation of the algorithm from PointCloudLibrary](https://github.com/PointCloudLibrary/pcl/blob/d242fcbdbb53efc7de48c9159343432a2194a27c/registration/include/pcl/registration/impl/transformation_estimation_point_to_plane_lls.hpp#L165)
 /// See also this [paper from Low](https://www.comp.nus.edu.sg/~lowkl/publications/lowk_point-to-plane_icp_techrep.pdf)
-#[allow(non_snake_case)]
 pub fn estimate_isometry<T>(
     alignee: &mut MaskedPointCloud<T, 3>,
     target: &mut MaskedPointCloud<T, 3>,
-    _: usize,
+    step: usize,
 ) -> Option<Isometry3<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    try_estimate_isometry(alignee, target, step).ok()
+}
+
+/// Same as [`estimate_isometry`] but returns [`IcpError::MissingNormals`] if a target point has no normal and
+/// [`IcpError::SingularSystem`] if the correspondences don't constrain all six degrees of freedom. Returns
+/// [`IcpError::NonFiniteCoordinates`] if the solution isn't finite, e.g. because of NaN coordinates.
+///
+/// Set it with [`crate::Icp::try_estimate_step_transform`] to get these errors from
+/// [`crate::Icp::try_estimate_transform`].
+#[allow(non_snake_case)]
+pub fn try_estimate_isometry<T>(
+    alignee: &mut MaskedPointCloud<T, 3>,
+    target: &mut MaskedPointCloud<T, 3>,
+    _: usize,
+) -> Result<Isometry3<T>, IcpError>
+where
+    T: Scalar + RealField + Copy,
+{
+    let (ATA, ATb) = normal_equations(alignee, target)?;
+
+    let ATA_inv = ATA.try_inverse().ok_or(IcpError::SingularSystem)?;
+
+    // Solve A*x = b
+    let x = ATA_inv * ATb;
+
+    if !x.iter().all(|v| v.is_finite()) {
+        return Err(IcpError::NonFiniteCoordinates);
+    }
+
+    Ok(isometry_from_solution(x))
+}
+
+/// Accumulates the normal equations `ATA * x = ATb` of the linearized point-to-plane problem.
+///
+/// The unknowns `x` are the small rotation angles about the x, y and z axes followed by the translation.
+#[allow(non_snake_case)]
+fn normal_equations<T>(
+    alignee: &MaskedPointCloud<T, 3>,
+    target: &MaskedPointCloud<T, 3>,
+) -> Result<(Matrix6<T>, Vector6<T>), IcpError>
 where
     T: Scalar + RealField + Copy,
 {
@@ -22,7 +72,7 @@ where
         let pos_align = a.pos;
 
         let pos_target = t.pos;
-        let norm_target = t.norm.unwrap();
+        let norm_target = t.norm.ok_or(IcpError::MissingNormals)?;
 
         let ax = pos_align.x;
         let ay = pos_align.y;
@@ -97,29 +147,321 @@ where
     ATA[(5, 3)] = ATA[(3, 5)];
     ATA[(5, 4)] = ATA[(4, 5)];
 
-    ATA.try_inverse().map(|ATA_inv| {
-        // Solve A*x = b
-        let x = ATA_inv * ATb;
-
-        // based on https://github.com/pglira/simpleICP/blob/236dfe918ab8e2af53e71d9963816e3adf8f0b76/python/simpleicp.py#L78
-        let alpha = x[0];
-        let beta = x[1];
-        let gamma = x[2];
-
-        Isometry3::from_parts(
-            Translation3::new(x[3], x[4], x[5]),
-            UnitQuaternion::from_matrix(&matrix![
-            T::one(), -gamma, beta;
-            gamma, T::one(), -alpha;
-            -beta, alpha, T::one()]),
-        )
-    })
+    Ok((ATA, ATb))
 }
 
+pub(crate) fn isometry_from_solution<T>(x: Vector6<T>) -> Isometry3<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    // based on https://github.com/pglira/simpleICP/blob/236dfe918ab8e2af53e71d9963816e3adf8f0b76/python/simpleicp.py#L78
+    let alpha = x[0];
+    let beta = x[1];
+    let gamma = x[2];
+
+    Isometry3::from_parts(
+        Translation3::new(x[3], x[4], x[5]),
+        UnitQuaternion::from_matrix(&matrix![
+        T::one(), -gamma, beta;
+        gamma, T::one(), -alpha;
+        -beta, alpha, T::one()]),
+    )
+}
+
+/// The degeneracy analysis of the point-to-plane problem computed by [`estimate_isometry_with_degeneracy`].
+#[derive(Clone, Debug)]
+pub struct Degeneracy<T: Scalar> {
+    /// The eigenvalues of the normal equations divided by the number of correspondences in ascending order.
+    pub eigenvalues: Vector6<T>,
+    /// The eigenvectors as columns in the same order as the eigenvalues. Every eigenvector is a direction
+    /// `[roll, pitch, yaw, x, y, z]` in the parameter space, i.e. rotations about the axes followed by the translation.
+    pub eigenvectors: Matrix6<T>,
+    /// Whether the direction of the eigenvector with the same index is degenerate (its eigenvalue is below the threshold).
+    pub degenerate: [bool; 6],
+}
+
+impl<T> Degeneracy<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    /// Returns `true` if at least one direction is degenerate.
+    pub fn is_degenerate(&self) -> bool {
+        self.degenerate.contains(&true)
+    }
+
+    /// The eigenvectors of the degenerate directions in the parameter space `[roll, pitch, yaw, x, y, z]`.
+    pub fn degenerate_directions(&self) -> Vec<Vector6<T>> {
+        self.degenerate
+            .iter()
+            .enumerate()
+            .filter(|(_, degenerate)| **degenerate)
+            .map(|(i, _)| self.eigenvectors.column(i).into_owned())
+            .collect()
+    }
+
+    /// The degrees of freedom that are mostly unconstrained, ordered `[x, y, z, roll, pitch, yaw]` like the mask of
+    /// [`crate::transform_estimation::constrained::build_masked_estimator`].
+    ///
+    /// A degree of freedom counts as degenerate if more than half of its unit direction lies within the degenerate
+    /// subspace. For example, for a corridor along the x axis only `x` is degenerate.
+    pub fn degenerate_dofs(&self) -> [bool; 6] {
+        // parameters are ordered [rotation, translation]
+        const PARAMETER_INDICES: [usize; 6] = [3, 4, 5, 0, 1, 2];
+
+        let half: T = nalgebra::convert(0.5);
+
+        PARAMETER_INDICES.map(|k| {
+            let projected = self
+                .degenerate_directions()
+                .iter()
+                .fold(T::zero(), |sum, v| sum + v[k] * v[k]);
+
+            projected > half
+        })
+    }
+}
+
+/// Estimates the isometry between the alignee and the target like [`estimate_isometry`] but only in the
+/// well-constrained directions.
+///
+/// If the geometry is, for example, a single plane or a corridor the normal equations are rank-deficient and the
+/// solution of [`estimate_isometry`] drifts along the unconstrained directions. This function computes the eigenvalues of
+/// the normal equations divided by the number of correspondences. Directions with an eigenvalue below
+/// `eigenvalue_threshold` are degenerate and are left untouched, i.e. the solution is only computed in the subspace of
+/// the other eigenvectors. Start with a threshold of around `1e-3` for point clouds with a size in the order of meters.
+///
+/// Returns the isometry together with the [`Degeneracy`] analysis.
+///
+/// See this [paper from Zhang et al.](https://doi.org/10.1109/ICRA.2016.7487211)
+#[allow(non_snake_case)]
+pub fn estimate_isometry_with_degeneracy<T>(
+    alignee: &mut MaskedPointCloud<T, 3>,
+    target: &mut MaskedPointCloud<T, 3>,
+    eigenvalue_threshold: T,
+) -> Option<(Isometry3<T>, Degeneracy<T>)>
+where
+    T: Scalar + RealField + Copy,
+{
+    let count = alignee.iter().count();
+    if count == 0 {
+        return None;
+    }
+
+    let (ATA, ATb) = normal_equations(alignee, target).ok()?;
+
+    let eigen = ATA.symmetric_eigen();
+
+    let mut order = [0, 1, 2, 3, 4, 5];
+    order.sort_by(|a, b| {
+        eigen.eigenvalues[*a]
+            .partial_cmp(&eigen.eigenvalues[*b])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let count = T::from_usize(count)?;
+
+    let eigenvalues = Vector6::from_fn(|i, _| eigen.eigenvalues[order[i]] / count);
+    let eigenvectors = Matrix6::from_fn(|r, c| eigen.eigenvectors[(r, order[c])]);
+    let degenerate = order.map(|i| eigen.eigenvalues[i] / count < eigenvalue_threshold);
+
+    // solve only in the subspace of the well-constrained eigenvectors
+    let mut x = Vector6::zeros();
+    for (i, degenerate) in degenerate.iter().enumerate() {
+        if !degenerate {
+            let v = eigenvectors.column(i);
+            x += v * (v.dot(&ATb) / (eigenvalues[i] * count));
+        }
+    }
+
+    Some((
+        isometry_from_solution(x),
+        Degeneracy {
+            eigenvalues,
+            eigenvectors,
+            degenerate,
+        },
+    ))
+}
+
+/// Builds a transform estimator that uses [`estimate_isometry_with_degeneracy`] and calls `on_degeneracy` with the
+/// [`Degeneracy`] analysis and the step for every step in which degenerate directions were detected.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::point_to_plane_lls;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(point_to_plane_lls::build_degeneracy_aware_estimator(
+///         1e-3,
+///         |degeneracy, step| tracing::info!("step {step}: degenerate {:?}", degeneracy.degenerate_dofs()),
+///     ))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn build_degeneracy_aware_estimator<T, F>(
+    eigenvalue_threshold: T,
+    mut on_degeneracy: F,
+) -> impl FnMut(&mut MaskedPointCloud<T, 3>, &mut MaskedPointCloud<T, 3>, usize) -> Option<Isometry3<T>>
++ use<T, F>
+where
+    T: Scalar + RealField + Copy,
+    F: FnMut(&Degeneracy<T>, usize),
+{
+    move |alignee: &mut MaskedPointCloud<T, 3>, target: &mut MaskedPointCloud<T, 3>, step: usize| {
+        let (isometry, degeneracy) =
+            estimate_isometry_with_degeneracy(alignee, target, eigenvalue_threshold)?;
+
+        if degeneracy.is_degenerate() {
+            on_degeneracy(&degeneracy, step);
+        }
+
+        Some(isometry)
+    }
+}
+
+/// Builds a transform estimator like [`estimate_isometry`] that is regularized toward the pose of the `prior`.
+///
+/// The prior is added as an extra block to the normal equations (see [`MotionPrior::normal_equations`]). This keeps
+/// the estimate close to the prior in directions that the geometry doesn't constrain, e.g. along a featureless corridor.
+///
+/// Set the returned [`PriorEstimator`] with [`crate::Icp::step_transform_estimator`] which starts it over in every run.
+/// The pose of the prior is relative to the alignee that is given to the ICP. If the ICP starts from an initial
+/// transform (see [`crate::Icp::initial_transform`]) use [`MotionPrior::relative_to`].
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::point_to_plane_lls;
+/// # use modern_icp::transform_estimation::motion_prior::MotionPrior;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// # use nalgebra::{Isometry3, Matrix6, Vector3};
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let odometry = Isometry3::translation(0.5, 0.0, 0.0);
+/// let prior = MotionPrior::new(odometry, Matrix6::identity() * 10.0);
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .step_transform_estimator(point_to_plane_lls::build_isometry_estimator_with_prior(prior))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+#[allow(non_snake_case)]
+pub fn build_isometry_estimator_with_prior<T>(
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    PriorEstimator::new(
+        move |alignee: &mut MaskedPointCloud<T, 3>,
+              target: &mut MaskedPointCloud<T, 3>,
+              current_transform: &Isometry3<T>| {
+            let (mut ATA, mut ATb) = normal_equations(alignee, target).ok()?;
+
+            let (prior_ATA, prior_ATb) = prior.normal_equations(current_transform);
+            ATA += prior_ATA;
+            ATb += prior_ATb;
+
+            Some(isometry_from_solution(ATA.try_inverse()? * ATb))
+        },
+    )
+}
+
+/// Estimates the 6x6 covariance of the step `transform` that was estimated with [`estimate_isometry`].
+///
+/// This is the inverse of the point-to-plane Hessian scaled by the residual variance (the weighted sum of the
+/// squared point-to-plane distances after applying `transform` divided by the number of correspondences minus six).
+/// The covariance is expressed for a left increment `[ω, v]` (rotation vector followed by the translation) in the
+/// coordinate system of the target, the same parametrization as
+/// [`crate::transform_estimation::levenberg_marquardt::se3_exp`]. The weights of the points are respected.
+///
+/// Returns `None` if the target has no normals or the geometry doesn't constrain all six degrees of freedom.
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::point_to_plane_lls;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum, covariance) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_covariance(point_to_plane_lls::estimate_covariance)
+///     .estimate_transform_with_covariance(alignee_cloud, &target_cloud);
+/// ```
+#[allow(non_snake_case)]
+pub fn estimate_covariance<T, M>(
+    alignee: &mut MaskedPointCloud<T, 3>,
+    target: &mut MaskedPointCloud<T, 3>,
+    transform: &M,
+) -> Option<Matrix6<T>>
+where
+    T: Scalar + RealField + Copy,
+    for<'b> &'b M: Mul<Point3<T>, Output = Point3<T>>,
+{
+    let mut H = Matrix6::<T>::zeros();
+    let mut squared_residual_sum = T::zero();
+    let mut count = 0;
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let weight = a.weight * t.weight;
+        let norm = t.norm?;
+
+        let pos = transform * a.pos;
+        let r = (pos - t.pos).dot(&norm);
+
+        let c = pos.coords.cross(&norm);
+        let J = Vector6::new(c.x, c.y, c.z, norm.x, norm.y, norm.z);
+
+        H += J * J.transpose() * weight;
+        squared_residual_sum += r * r * weight;
+        count += 1;
+    }
+
+    pose_covariance(H, squared_residual_sum, count)
+}
+
+/// Estimates the scale along the x, y and z axes between the alignee and the target from the point-to-plane
+/// constraints.
+///
+/// Panics if a target point has no normal. Use [`try_estimate_scale_point_to_plane`] to get an error instead.
 pub fn estimate_scale_point_to_plane<'a, T>(
     alignee: &mut MaskedPointCloud<'a, T, 3>,
     target: &mut MaskedPointCloud<'a, T, 3>,
 ) -> Matrix3<T>
+where
+    T: Scalar + RealField + Copy + From<f32>,
+    f64: From<T>,
+{
+    try_estimate_scale_point_to_plane(alignee, target)
+        .expect("Target point cloud must have normals")
+}
+
+/// Same as [`estimate_scale_point_to_plane`] but returns [`IcpError::MissingNormals`] if a target point has no
+/// normal.
+pub fn try_estimate_scale_point_to_plane<'a, T>(
+    alignee: &mut MaskedPointCloud<'a, T, 3>,
+    target: &mut MaskedPointCloud<'a, T, 3>,
+) -> Result<Matrix3<T>, IcpError>
 where
     T: Scalar + RealField + Copy + From<f32>,
     f64: From<T>,
@@ -127,16 +469,182 @@ where
     let mut scale = Vector3::zeros();
 
     for (a, t) in alignee.iter().zip(target.iter()) {
-        let a_mul_n = a.pos.coords.component_mul(&t.norm.unwrap());
+        let norm = t.norm.ok_or(IcpError::MissingNormals)?;
+        let a_mul_n = a.pos.coords.component_mul(&norm);
 
         let x = a_mul_n.x;
         let y = a_mul_n.y;
         let z = a_mul_n.z;
 
-        let c = Plane::from_normal_and_point(&t.norm.unwrap(), &t.pos).constant;
+        let c = Plane::from_normal_and_point(&norm, &t.pos).constant;
 
         scale += Vector3::new((c - y - z) / x, (c - x - z) / y, (c - y - x) / z);
     }
 
-    Matrix3::from_diagonal(&scale)
+    Ok(Matrix3::from_diagonal(&scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PointCloud, PointCloudPoint};
+
+    /// Two walls at `y = 0` and `y = 1` and a floor at `z = 0` along the x axis.
+    ///
+    /// The wall normals are tilted slightly towards x, like estimated normals of a real scan, so the translation
+    /// along the corridor is barely constrained instead of not at all.
+    fn corridor() -> PointCloud<f32, 3> {
+        let tilted_normal = Vector3::new(0.002, 1.0, 0.0).normalize();
+
+        let mut cloud = PointCloud::new();
+        for i in 0..40 {
+            for j in 0..10 {
+                let (x, v) = (i as f32 * 0.1, j as f32 * 0.1);
+                cloud.push(PointCloudPoint::from_pos_norm(
+                    Point3::new(x, 0.0, v),
+                    tilted_normal,
+                ));
+                cloud.push(PointCloudPoint::from_pos_norm(
+                    Point3::new(x, 1.0, v),
+                    -tilted_normal,
+                ));
+                cloud.push(PointCloudPoint::from_pos_norm(
+                    Point3::new(x, v, 0.0),
+                    Vector3::z(),
+                ));
+            }
+        }
+        cloud
+    }
+
+    fn shifted_corridor() -> (PointCloud<f32, 3>, PointCloud<f32, 3>) {
+        let target = corridor();
+        let alignee = target
+            .iter()
+            .map(|p| PointCloudPoint::from_pos(p.pos + Vector3::new(0.0, 0.05, 0.02)))
+            .collect();
+
+        (alignee, target)
+    }
+
+    #[test]
+    fn detects_corridor_direction_as_degenerate() {
+        let (alignee, target) = shifted_corridor();
+
+        let (_, degeneracy) = estimate_isometry_with_degeneracy(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            1e-3,
+        )
+        .unwrap();
+
+        assert!(degeneracy.is_degenerate());
+        assert_eq!(degeneracy.degenerate_directions().len(), 1);
+        assert_eq!(
+            degeneracy.degenerate_dofs(),
+            [true, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn does_not_drift_along_degenerate_direction() {
+        let (alignee, target) = shifted_corridor();
+
+        let unconstrained = estimate_isometry(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
+        assert!(unconstrained.translation.x.abs() > 0.1);
+
+        let (isometry, _) = estimate_isometry_with_degeneracy(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            1e-3,
+        )
+        .unwrap();
+
+        assert!(isometry.translation.x.abs() < 1e-3);
+        assert!((isometry.translation.vector - Vector3::new(0.0, -0.05, -0.02)).norm() < 1e-3);
+        assert!(isometry.rotation.angle() < 1e-3);
+    }
+
+    #[test]
+    fn reports_degeneracy_with_step() {
+        let (alignee, target) = shifted_corridor();
+
+        let mut reported = vec![];
+        let mut estimator =
+            build_degeneracy_aware_estimator(1e-3, |degeneracy: &Degeneracy<f32>, step| {
+                reported.push((degeneracy.degenerate_dofs(), step))
+            });
+
+        estimator(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            3,
+        )
+        .unwrap();
+        drop(estimator);
+
+        assert_eq!(
+            reported,
+            vec![([true, false, false, false, false, false], 3)]
+        );
+    }
+
+    #[test]
+    fn reports_why_estimation_failed() {
+        let corridor = corridor();
+        let mut without_normals = corridor.clone();
+        for p in &mut without_normals {
+            p.norm = None;
+        }
+        let floor: PointCloud<f32, 3> = corridor
+            .iter()
+            .filter(|p| p.norm == Some(Vector3::z()))
+            .copied()
+            .collect();
+        let mut with_nan = corridor.clone();
+        with_nan[0].pos.x = f32::NAN;
+
+        let estimate = |alignee: &PointCloud<f32, 3>, target: &PointCloud<f32, 3>| {
+            try_estimate_isometry(
+                &mut MaskedPointCloud::new(alignee),
+                &mut MaskedPointCloud::new(target),
+                0,
+            )
+        };
+
+        assert!(estimate(&corridor, &corridor).is_ok());
+        assert_eq!(
+            estimate(&corridor, &without_normals),
+            Err(IcpError::MissingNormals)
+        );
+        assert_eq!(estimate(&floor, &floor), Err(IcpError::SingularSystem));
+        assert_eq!(
+            estimate(&with_nan, &corridor),
+            Err(IcpError::NonFiniteCoordinates)
+        );
+    }
+
+    #[test]
+    fn scale_needs_normals() {
+        let corridor = corridor();
+        let mut without_normals = corridor.clone();
+        without_normals[0].norm = None;
+
+        let scale = try_estimate_scale_point_to_plane(
+            &mut MaskedPointCloud::new(&corridor),
+            &mut MaskedPointCloud::new(&corridor),
+        );
+        assert!(scale.is_ok());
+
+        let scale = try_estimate_scale_point_to_plane(
+            &mut MaskedPointCloud::new(&corridor),
+            &mut MaskedPointCloud::new(&without_normals),
+        );
+        assert_eq!(scale, Err(IcpError::MissingNormals));
+    }
 }
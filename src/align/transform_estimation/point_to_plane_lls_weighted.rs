@@ -1,9 +1,14 @@
 use nalgebra::*;
 
-use crate::{MaskedPointCloud, Plane};
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::motion_prior::{MotionPrior, PriorEstimator};
+use crate::transform_estimation::point_to_plane_lls::isometry_from_solution;
+use crate::{IcpError, MaskedPointCloud, Plane};
 
 /// Estimates the isometry between the alignee and the target using the Point-to-Plane-LLS algorithm.
 ///
+/// Returns `None` if a target point has no normal or the correspondences don't constrain all six degrees of freedom.
+///
 /// See this [implementation of the algorithm from PointCloudLibrary](https://github.com/PointCloudLibrary/pcl/blob/3f19fc83cfa3850e13d5f833895871d6a92221e2/registration/include/pcl/registration/impl/transformation_estimation_point_to_plane_lls_weighted.hpp#L192)
 /// See also this [paper from Low](https://www.comp.nus.edu.sg/~lowkl/publications/lowk_point-to-plane_icp_techrep.pdf)
 #[allow(non_snake_case)]
@@ -12,6 +17,56 @@ pub fn estimate_isometry<T>(
     target: &mut MaskedPointCloud<T, 3>,
     _: usize,
 ) -> Option<Isometry3<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    let (ATA, ATb) = normal_equations(alignee, target)?;
+
+    ATA.try_inverse().map(|ATA_inv| {
+        // Solve A*x = b
+        isometry_from_solution(ATA_inv * ATb)
+    })
+}
+
+/// Builds a transform estimator like [`estimate_isometry`] that is regularized toward the pose of the `prior`.
+///
+/// The prior is added as an extra block to the normal equations (see [`MotionPrior::normal_equations`]). The point
+/// weights enter the normal equations squared, so the information of the prior is relative to the squared weights.
+///
+/// Set the returned [`PriorEstimator`] with [`crate::Icp::step_transform_estimator`] which starts it over in every run,
+/// or wrap it with [`crate::transform_estimation::robust::build_irls_estimator`]. If the ICP starts from an initial
+/// transform (see [`crate::Icp::initial_transform`]) use [`MotionPrior::relative_to`].
+#[allow(non_snake_case)]
+pub fn build_isometry_estimator_with_prior<T>(
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    PriorEstimator::new(
+        move |alignee: &mut MaskedPointCloud<T, 3>,
+              target: &mut MaskedPointCloud<T, 3>,
+              current_transform: &Isometry3<T>| {
+            let (mut ATA, mut ATb) = normal_equations(alignee, target)?;
+
+            let (prior_ATA, prior_ATb) = prior.normal_equations(current_transform);
+            ATA += prior_ATA;
+            ATb += prior_ATb;
+
+            Some(isometry_from_solution(ATA.try_inverse()? * ATb))
+        },
+    )
+}
+
+/// Accumulates the weighted normal equations `ATA * x = ATb` of the linearized point-to-plane problem.
+///
+/// The unknowns `x` are the small rotation angles about the x, y and z axes followed by the translation.
+/// Returns `None` if a target point has no normal.
+#[allow(non_snake_case)]
+fn normal_equations<T>(
+    alignee: &MaskedPointCloud<T, 3>,
+    target: &MaskedPointCloud<T, 3>,
+) -> Option<(Matrix6<T>, Vector6<T>)>
 where
     T: Scalar + RealField + Copy,
 {
@@ -22,7 +77,7 @@ where
         let pos_align = a.pos;
 
         let pos_target = t.pos;
-        let norm_target = t.norm.unwrap() * (a.weight * t.weight);
+        let norm_target = t.norm? * (a.weight * t.weight);
 
         let ax = pos_align.x;
         let ay = pos_align.y;
@@ -97,29 +152,31 @@ where
     ATA[(5, 3)] = ATA[(3, 5)];
     ATA[(5, 4)] = ATA[(4, 5)];
 
-    ATA.try_inverse().map(|ATA_inv| {
-        // Solve A*x = b
-        let x = ATA_inv * ATb;
-
-        // based on https://github.com/pglira/simpleICP/blob/236dfe918ab8e2af53e71d9963816e3adf8f0b76/python/simpleicp.py#L78
-        let alpha = x[0];
-        let beta = x[1];
-        let gamma = x[2];
-
-        Isometry3::from_parts(
-            Translation3::new(x[3], x[4], x[5]),
-            UnitQuaternion::from_matrix(&matrix![
-            T::one(), -gamma, beta;
-            gamma, T::one(), -alpha;
-            -beta, alpha, T::one()]),
-        )
-    })
+    Some((ATA, ATb))
 }
 
+/// Estimates the scale along the x, y and z axes between the alignee and the target from the point-to-plane
+/// constraints.
+///
+/// Panics if a target point has no normal. Use [`try_estimate_scale_point_to_plane`] to get an error instead.
 pub fn estimate_scale_point_to_plane<'a, T>(
     alignee: &mut MaskedPointCloud<'a, T, 3>,
     target: &mut MaskedPointCloud<'a, T, 3>,
 ) -> Matrix3<T>
+where
+    T: Scalar + RealField + Copy + From<f32>,
+    f64: From<T>,
+{
+    try_estimate_scale_point_to_plane(alignee, target)
+        .expect("Target point cloud must have normals")
+}
+
+/// Same as [`estimate_scale_point_to_plane`] but returns [`IcpError::MissingNormals`] if a target point has no
+/// normal.
+pub fn try_estimate_scale_point_to_plane<'a, T>(
+    alignee: &mut MaskedPointCloud<'a, T, 3>,
+    target: &mut MaskedPointCloud<'a, T, 3>,
+) -> Result<Matrix3<T>, IcpError>
 where
     T: Scalar + RealField + Copy + From<f32>,
     f64: From<T>,
@@ -127,16 +184,17 @@ where
     let mut scale = Vector3::zeros();
 
     for (a, t) in alignee.iter().zip(target.iter()) {
-        let a_mul_n = a.pos.coords.component_mul(&t.norm.unwrap());
+        let norm = t.norm.ok_or(IcpError::MissingNormals)?;
+        let a_mul_n = a.pos.coords.component_mul(&norm);
 
         let x = a_mul_n.x;
         let y = a_mul_n.y;
         let z = a_mul_n.z;
 
-        let c = Plane::from_normal_and_point(&t.norm.unwrap(), &t.pos).constant;
+        let c = Plane::from_normal_and_point(&norm, &t.pos).constant;
 
         scale += Vector3::new((c - y - z) / x, (c - x - z) / y, (c - y - x) / z);
     }
 
-    Matrix3::from_diagonal(&scale)
+    Ok(Matrix3::from_diagonal(&scale))
 }
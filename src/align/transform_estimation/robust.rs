@@ -0,0 +1,305 @@
+use std::ops::Mul;
+
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::MaskedPointCloud;
+use crate::transform_estimation::TransformEstimator;
+
+/// Robust kernel (M-estimator) that turns the scaled residual of a correspondence into a weight.
+///
+/// Every variant holds its tuning constant `c` in units of the residual scale. Common choices that give
+/// 95% efficiency for normally distributed residuals are `1.345` for Huber, `4.685` for Tukey, `2.3849` for Cauchy
+/// and `2.9846` for Welsch. Geman-McClure is usually used with `1.0`.
+///
+/// See this [paper from Babin et al.](https://doi.org/10.1109/ICRA.2019.8793791) for a comparison in the context of ICP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RobustKernel<T> {
+    Huber(T),
+    Tukey(T),
+    Cauchy(T),
+    GemanMcClure(T),
+    Welsch(T),
+}
+
+impl<T> RobustKernel<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    /// Returns the weight for the residual `r` that is already divided by the residual scale.
+    pub fn weight(&self, r: T) -> T {
+        let r = Float::abs(r);
+
+        match *self {
+            RobustKernel::Huber(c) => {
+                if r <= c {
+                    T::one()
+                } else {
+                    c / r
+                }
+            }
+            RobustKernel::Tukey(c) => {
+                if r <= c {
+                    let x = T::one() - (r / c) * (r / c);
+                    x * x
+                } else {
+                    T::zero()
+                }
+            }
+            RobustKernel::Cauchy(c) => T::one() / (T::one() + (r / c) * (r / c)),
+            RobustKernel::GemanMcClure(c) => {
+                let x = T::one() + (r / c) * (r / c);
+                T::one() / (x * x)
+            }
+            RobustKernel::Welsch(c) => Float::exp(-(r / c) * (r / c)),
+        }
+    }
+}
+
+/// Computes the robust scale of the residuals as the median absolute deviation (MAD) from zero
+/// scaled to be consistent with the standard deviation of normally distributed residuals.
+pub fn median_absolute_deviation<T>(residuals: &[T]) -> Option<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    let mut absolute: Vec<T> = residuals.iter().map(|r| Float::abs(*r)).collect();
+    if absolute.is_empty() {
+        return None;
+    }
+
+    let middle = absolute.len() / 2;
+    let (_, median, _) = absolute.select_nth_unstable_by(middle, |a, b| {
+        a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Some(*median * nalgebra::convert(1.4826))
+}
+
+/// Wraps a weighted transform estimator with iteratively reweighted least squares (IRLS).
+///
+/// In every ICP step the residuals of the correspondences under the current estimate are computed, their scale
+/// is taken from the [`median_absolute_deviation`] and the `kernel` turns them into weights. These weights are
+/// multiplied with the original weights of the alignee points (see [`crate::PointCloudPoint::weight`]) and the
+/// wrapped estimator is run again. This is repeated `irls_iterations` times per ICP step.
+///
+/// The residual of a correspondence is the distance along the target normal if the target point has one and the
+/// Euclidean distance otherwise. The wrapped estimator has to respect the point weights, like
+/// [`crate::transform_estimation::point_to_plane_lls_weighted::estimate_isometry`]. Estimators that look up
+/// precomputed data by index keep working because the masks and orders of the point clouds are left untouched.
+///
+/// The returned [`IrlsEstimator`] is set with [`crate::Icp::step_transform_estimator`]. It passes
+/// [`TransformEstimator::reset`] on, so it can wrap estimators with state like the
+/// [`crate::transform_estimation::motion_prior::PriorEstimator`].
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::point_to_plane_lls_weighted;
+/// # use modern_icp::transform_estimation::robust::{build_irls_estimator, RobustKernel};
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .step_transform_estimator(build_irls_estimator(
+///         point_to_plane_lls_weighted::estimate_isometry,
+///         RobustKernel::Cauchy(2.3849),
+///         3,
+///     ))
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn build_irls_estimator<T, ET>(
+    estimate_transform: ET,
+    kernel: RobustKernel<T>,
+    irls_iterations: usize,
+) -> IrlsEstimator<T, ET> {
+    IrlsEstimator {
+        estimate_transform,
+        kernel,
+        irls_iterations,
+    }
+}
+
+/// A weighted transform estimator wrapped with iteratively reweighted least squares.
+///
+/// Created by [`build_irls_estimator`].
+pub struct IrlsEstimator<T, ET> {
+    estimate_transform: ET,
+    kernel: RobustKernel<T>,
+    irls_iterations: usize,
+}
+
+impl<T, M, ET, const D: usize> TransformEstimator<T, M, D> for IrlsEstimator<T, ET>
+where
+    T: Scalar + RealField + Float + Copy,
+    for<'b> &'b M: Mul<Point<T, D>, Output = Point<T, D>>,
+    ET: TransformEstimator<T, M, D>,
+{
+    fn estimate(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        step: usize,
+    ) -> Option<M> {
+        let mut weighted_cloud = alignee.point_cloud.clone();
+
+        let mut transform: Option<M> = None;
+
+        for _ in 0..self.irls_iterations.max(1) {
+            let residuals: Vec<T> = alignee
+                .iter()
+                .zip(target.iter())
+                .map(|(a, t)| {
+                    let pos = match &transform {
+                        Some(transform) => transform * a.pos,
+                        None => a.pos,
+                    };
+                    let diff = pos - t.pos;
+
+                    match t.norm {
+                        Some(norm) => diff.dot(&norm),
+                        None => diff.norm(),
+                    }
+                })
+                .collect();
+
+            let scale = median_absolute_deviation(&residuals)?;
+
+            for (idx, residual) in alignee
+                .masked_and_ordered_to_plain_index
+                .iter()
+                .zip(residuals)
+            {
+                let robust_weight = if scale > T::default_epsilon() {
+                    self.kernel.weight(residual / scale)
+                } else {
+                    T::one()
+                };
+
+                weighted_cloud[*idx].weight = alignee.point_cloud[*idx].weight * robust_weight;
+            }
+
+            let mut weighted_alignee = MaskedPointCloud::compose(
+                &weighted_cloud,
+                alignee.masked_and_ordered_to_plain_index.clone(),
+            );
+
+            transform = Some(self.estimate_transform.estimate(
+                &mut weighted_alignee,
+                target,
+                step,
+            )?);
+        }
+
+        transform
+    }
+
+    fn reset(&mut self) {
+        self.estimate_transform.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{isometry_difference, room_corner, transformed};
+    use crate::transform_estimation::point_to_plane_lls_weighted;
+
+    #[test]
+    fn kernels_down_weight_large_residuals() {
+        let kernels = [
+            RobustKernel::Huber(1.345),
+            RobustKernel::Tukey(4.685),
+            RobustKernel::Cauchy(2.3849),
+            RobustKernel::GemanMcClure(1.0),
+            RobustKernel::Welsch(2.9846),
+        ];
+
+        for kernel in kernels {
+            assert_eq!(kernel.weight(0.0), 1.0, "{kernel:?}");
+            assert_eq!(kernel.weight(-2.0), kernel.weight(2.0), "{kernel:?}");
+            assert!(kernel.weight(2.0) > kernel.weight(4.0), "{kernel:?}");
+        }
+
+        assert_eq!(RobustKernel::Huber(1.0).weight(0.5), 1.0);
+        assert_eq!(RobustKernel::Huber(1.0).weight(4.0), 0.25);
+        assert_eq!(RobustKernel::Tukey(1.0).weight(1.5), 0.0);
+    }
+
+    #[test]
+    fn median_absolute_deviation_ignores_outliers() {
+        let residuals = [1.0, -1.0, 2.0, -2.0, 100.0];
+
+        assert!(f32::abs(median_absolute_deviation(&residuals).unwrap() - 2.0 * 1.4826) < 1e-6);
+        assert_eq!(median_absolute_deviation::<f32>(&[]), None);
+    }
+
+    #[test]
+    fn irls_rejects_outlier_correspondences() {
+        let mut target = room_corner(2.0, 0.2);
+
+        let transform = Isometry3::new(
+            Vector3::new(0.01, -0.01, 0.02),
+            Vector3::new(0.005, 0.0, -0.005),
+        );
+        let alignee = transformed(&target, &transform.inverse());
+
+        // every tenth correspondence is an outlier
+        for p in target.iter_mut().step_by(10) {
+            p.pos += p.norm.unwrap() * 0.5;
+        }
+
+        let estimate = |estimator: &mut dyn TransformEstimator<f32, Isometry3<f32>, 3>| {
+            estimator
+                .estimate(
+                    &mut MaskedPointCloud::new(&alignee),
+                    &mut MaskedPointCloud::new(&target),
+                    0,
+                )
+                .unwrap()
+        };
+
+        let plain = estimate(&mut point_to_plane_lls_weighted::estimate_isometry);
+        let robust = estimate(&mut build_irls_estimator(
+            point_to_plane_lls_weighted::estimate_isometry,
+            RobustKernel::Tukey(4.685),
+            3,
+        ));
+
+        let (plain_translation, _) = isometry_difference(&plain, &transform);
+        let (robust_translation, robust_angle) = isometry_difference(&robust, &transform);
+
+        assert!(plain_translation > 0.02);
+        assert!(robust_translation < 1e-3 && robust_angle < 1e-3);
+    }
+
+    #[test]
+    fn reset_is_passed_on() {
+        struct CountResets(usize);
+
+        impl TransformEstimator<f32, Isometry3<f32>, 3> for CountResets {
+            fn estimate(
+                &mut self,
+                _: &mut MaskedPointCloud<f32, 3>,
+                _: &mut MaskedPointCloud<f32, 3>,
+                _: usize,
+            ) -> Option<Isometry3<f32>> {
+                Some(Isometry3::identity())
+            }
+
+            fn reset(&mut self) {
+                self.0 += 1;
+            }
+        }
+
+        let mut estimator = build_irls_estimator(CountResets(0), RobustKernel::Huber(1.345), 3);
+        TransformEstimator::<f32, Isometry3<f32>, 3>::reset(&mut estimator);
+
+        assert_eq!(estimator.estimate_transform.0, 1);
+    }
+}
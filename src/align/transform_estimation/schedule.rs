@@ -0,0 +1,291 @@
+use nalgebra::*;
+use tracing::info;
+
+use crate::MaskedPointCloud;
+use crate::convergence::ConvergenceCriterion;
+use crate::transform_estimation::TransformEstimator;
+
+type BoxedTransformEstimator<T, M, const D: usize> =
+    Box<dyn FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, usize) -> Option<M>>;
+
+enum StageEnd<T, M> {
+    Iterations(usize),
+    Converged(Box<dyn ConvergenceCriterion<T, M>>),
+    Never,
+}
+
+struct Stage<T: Scalar + Copy, M, const D: usize> {
+    estimate_transform: BoxedTransformEstimator<T, M, D>,
+    end: StageEnd<T, M>,
+}
+
+/// Builder for a transform estimator that runs several transform estimators one after another.
+///
+/// Every stage runs its estimator until its end condition is met and then switches to the next stage:
+/// - [`EstimatorSchedule::for_iterations`] runs for a fixed number of ICP iterations.
+/// - [`EstimatorSchedule::until_converged`] runs until a [`ConvergenceCriterion`] is met. The criterion gets the
+///   squared distances between the correspondences that were passed to the estimator as the first argument
+///   (the second is empty), the step transform, its own error value and the step within the stage.
+/// - [`EstimatorSchedule::finally`] adds the last stage which runs until the ICP terminates and returns the
+///   finished [`ScheduledEstimator`].
+///
+/// Every estimator gets the step within its stage, so annealing estimators start from the beginning in their stage.
+/// All estimators have to return the same transform type. Use
+/// [`crate::transform_estimation::transform_modifier::build_modified_transform_estimator`] or a closure to convert
+/// between transform types.
+///
+/// Keep in mind that the convergence criterion of the [`crate::Icp`] sees the steps of all stages. If a stage
+/// converges early it might terminate the whole ICP before later stages ran.
+///
+/// The [`ScheduledEstimator`] is set with [`crate::Icp::step_transform_estimator`] which starts it from the first
+/// stage in every run.
+///
+/// ## Example
+///
+/// Translation only for the first five iterations, then point-to-plane until the error doesn't change anymore and
+/// finally the Levenberg-Marquardt estimator for the remaining iterations.
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::schedule::EstimatorSchedule;
+/// # use modern_icp::transform_estimation::{point_to_plane_lls, translation_point_to_plane};
+/// # use modern_icp::transform_estimation::levenberg_marquardt::{build_isometry_estimator, Residual};
+/// # use modern_icp::convergence::{same_squared_distance_error, never};
+/// # use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let estimator = EstimatorSchedule::new()
+///     .for_iterations(5, |alignee, target, step| {
+///         translation_point_to_plane::estimate_translation(alignee, target, step)
+///             .map(|t| Isometry3::from_parts(t.translation, UnitQuaternion::identity()))
+///     })
+///     .until_converged(same_squared_distance_error(0.01), point_to_plane_lls::estimate_isometry)
+///     .finally(build_isometry_estimator(Residual::PointToPlane, 10));
+///
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .step_transform_estimator(estimator)
+///     .is_converged(never)
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub struct EstimatorSchedule<T: Scalar + Copy, M, const D: usize> {
+    stages: Vec<Stage<T, M, D>>,
+}
+
+impl<T, M, const D: usize> EstimatorSchedule<T, M, D>
+where
+    T: Scalar + RealField + Copy,
+    M: 'static,
+{
+    #[allow(
+        clippy::new_without_default,
+        reason = "An empty schedule is not a transform estimator. It has to be completed with `finally`"
+    )]
+    pub fn new() -> Self {
+        Self { stages: vec![] }
+    }
+
+    /// Adds a stage that runs `estimate_transform` for the given number of `iterations`.
+    pub fn for_iterations<ET>(mut self, iterations: usize, estimate_transform: ET) -> Self
+    where
+        ET: FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, usize) -> Option<M>
+            + 'static,
+    {
+        if iterations > 0 {
+            self.stages.push(Stage {
+                estimate_transform: Box::new(estimate_transform),
+                end: StageEnd::Iterations(iterations),
+            });
+        }
+        self
+    }
+
+    /// Adds a stage that runs `estimate_transform` until `is_converged` is met.
+    pub fn until_converged<ET, IC>(mut self, is_converged: IC, estimate_transform: ET) -> Self
+    where
+        ET: FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, usize) -> Option<M>
+            + 'static,
+        IC: ConvergenceCriterion<T, M> + 'static,
+    {
+        self.stages.push(Stage {
+            estimate_transform: Box::new(estimate_transform),
+            end: StageEnd::Converged(Box::new(is_converged)),
+        });
+        self
+    }
+
+    /// Adds the last stage that runs `estimate_transform` until the ICP terminates and returns the transform estimator
+    /// that runs the whole schedule.
+    pub fn finally<ET>(mut self, estimate_transform: ET) -> ScheduledEstimator<T, M, D>
+    where
+        ET: FnMut(&mut MaskedPointCloud<T, D>, &mut MaskedPointCloud<T, D>, usize) -> Option<M>
+            + 'static,
+    {
+        self.stages.push(Stage {
+            estimate_transform: Box::new(estimate_transform),
+            end: StageEnd::Never,
+        });
+
+        ScheduledEstimator {
+            stages: self.stages,
+            current: 0,
+            stage_step: 0,
+            error: T::zero(),
+        }
+    }
+}
+
+/// A transform estimator that runs the stages of an [`EstimatorSchedule`] one after another.
+///
+/// Created by [`EstimatorSchedule::finally`]. [`TransformEstimator::reset`] starts over from the first stage.
+pub struct ScheduledEstimator<T: Scalar + Copy, M, const D: usize> {
+    stages: Vec<Stage<T, M, D>>,
+    current: usize,
+    stage_step: usize,
+    error: T,
+}
+
+impl<T, M, const D: usize> TransformEstimator<T, M, D> for ScheduledEstimator<T, M, D>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn estimate(
+        &mut self,
+        alignee: &mut MaskedPointCloud<T, D>,
+        target: &mut MaskedPointCloud<T, D>,
+        _: usize,
+    ) -> Option<M> {
+        let stage = &mut self.stages[self.current];
+
+        let transform = (stage.estimate_transform)(alignee, target, self.stage_step)?;
+
+        let is_finished = match &mut stage.end {
+            StageEnd::Iterations(iterations) => self.stage_step + 1 >= *iterations,
+            StageEnd::Converged(is_converged) => {
+                let distances: Vec<T> = alignee
+                    .iter()
+                    .zip(target.iter())
+                    .map(|(a, t)| (t.pos - a.pos).norm_squared())
+                    .collect();
+
+                is_converged.is_converged(
+                    &distances,
+                    &[],
+                    &transform,
+                    &mut self.error,
+                    self.stage_step,
+                )
+            }
+            StageEnd::Never => false,
+        };
+
+        if is_finished {
+            self.current += 1;
+            self.stage_step = 0;
+            self.error = T::zero();
+
+            info!("Switching to transform estimator stage {}.", self.current);
+        } else {
+            self.stage_step += 1;
+        }
+
+        Some(transform)
+    }
+
+    fn reset(&mut self) {
+        self.current = 0;
+        self.stage_step = 0;
+        self.error = T::zero();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Icp;
+    use crate::convergence::never;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::test_utils::room_corner;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Calls = Rc<RefCell<Vec<(&'static str, usize)>>>;
+
+    /// An estimator that records its name and the step it got and doesn't move the alignee.
+    fn record(
+        calls: &Calls,
+        name: &'static str,
+    ) -> impl FnMut(
+        &mut MaskedPointCloud<f32, 3>,
+        &mut MaskedPointCloud<f32, 3>,
+        usize,
+    ) -> Option<Isometry3<f32>>
+    + 'static {
+        let calls = calls.clone();
+        move |_, _, step| {
+            calls.borrow_mut().push((name, step));
+            Some(Isometry3::identity())
+        }
+    }
+
+    #[test]
+    fn runs_stages_one_after_another() {
+        let calls = Calls::default();
+
+        let mut estimator = EstimatorSchedule::new()
+            .for_iterations(2, record(&calls, "first"))
+            .for_iterations(0, record(&calls, "skipped"))
+            .until_converged(
+                |_: &[f32], _: &[f32], _: &Isometry3<f32>, _: &mut f32, step: usize| step == 1,
+                record(&calls, "second"),
+            )
+            .finally(record(&calls, "last"));
+
+        let cloud = room_corner(1.0, 0.5);
+        for step in 0..6 {
+            estimator.estimate(
+                &mut MaskedPointCloud::new(&cloud),
+                &mut MaskedPointCloud::new(&cloud),
+                step,
+            );
+        }
+
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                ("first", 0),
+                ("first", 1),
+                ("second", 0),
+                ("second", 1),
+                ("last", 0),
+                ("last", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_from_first_stage_in_every_run() {
+        let calls = Calls::default();
+
+        let estimator = EstimatorSchedule::new()
+            .for_iterations(2, record(&calls, "first"))
+            .finally(record(&calls, "last"));
+
+        let target = room_corner(1.0, 0.5);
+
+        let mut icp = Icp::new()
+            .max_iterations(3)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .step_transform_estimator(estimator)
+            .is_converged(never);
+
+        icp.estimate_transform(target.clone(), &target);
+        icp.estimate_transform(target.clone(), &target);
+
+        let run = [("first", 0), ("first", 1), ("last", 0)];
+        assert_eq!(*calls.borrow(), [run, run].concat());
+    }
+}
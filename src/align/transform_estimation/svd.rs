@@ -1,16 +1,23 @@
+use std::ops::Mul;
+
+use crate::transform_estimation::pose_covariance;
 use crate::{MaskedPointCloud, compute_centroid, demean_into_matrix};
-use nalgebra::*;
+use nalgebra::{allocator::Allocator, *};
 
 /// Estimates the isometry between the alignee and the target using the SVD algorithm.
 ///
+/// Works for any dimension `D`, e.g. `2` for laser scans and `3` for point clouds.
+///
 /// See this [implementation of the algorithm from PointCloudLibrary](https://github.com/PointCloudLibrary/pcl/blob/d242fcbdbb53efc7de48c9159343432a2194a27c/registration/include/pcl/registration/impl/transformation_estimation_svd.hpp)
-pub fn estimate_isometry<T>(
-    alignee: &mut MaskedPointCloud<T, 3>,
-    target: &mut MaskedPointCloud<T, 3>,
+pub fn estimate_isometry<T, const D: usize>(
+    alignee: &mut MaskedPointCloud<T, D>,
+    target: &mut MaskedPointCloud<T, D>,
     _: usize,
-) -> Option<IsometryMatrix3<T>>
+) -> Option<Isometry<T, Rotation<T, D>, D>>
 where
     T: Scalar + RealField + Copy,
+    Const<D>: DimMin<Const<D>, Output = Const<D>> + DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<D>, U1>>,
 {
     let alignee_centroid = compute_centroid(alignee.points_iter());
     let target_centroid = compute_centroid(target.points_iter());
@@ -20,34 +27,22 @@ where
 
     let covariant_matrix = demeaned_alignee * demeaned_target.transpose();
 
-    let SVD {
-        u,
-        v_t,
-        singular_values: _,
-    } = covariant_matrix.svd(true, true);
-
-    let u = u?;
-    let mut v = v_t?.transpose();
-
-    if u.determinant() * v.determinant() < T::zero() {
-        let mut column = v.column_mut(2);
-        column *= -T::one();
-    }
-
-    let rotation_matrix = v * u.transpose();
-    let rotation = Rotation3::from_matrix_unchecked(rotation_matrix);
+    let rotation_matrix = rotation_from_covariance(covariant_matrix)?;
+    let rotation = Rotation::from_matrix_unchecked(rotation_matrix);
 
-    let translation = Translation3::from(target_centroid - rotation_matrix * alignee_centroid);
+    let translation = Translation::from(target_centroid - rotation_matrix * alignee_centroid);
 
-    Some(IsometryMatrix3::from_parts(translation, rotation))
+    Some(Isometry::from_parts(translation, rotation))
 }
 
-pub fn estimate_similarity<T>(
-    source: &mut MaskedPointCloud<T, 3>,
-    target: &mut MaskedPointCloud<T, 3>,
-) -> Option<SimilarityMatrix3<T>>
+pub fn estimate_similarity<T, const D: usize>(
+    source: &mut MaskedPointCloud<T, D>,
+    target: &mut MaskedPointCloud<T, D>,
+) -> Option<Similarity<T, Rotation<T, D>, D>>
 where
     T: Scalar + RealField + Copy,
+    Const<D>: DimMin<Const<D>, Output = Const<D>> + DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<D>, U1>>,
     // &'a T: Mul<&'a T, Output=&'a T>,
 {
     let source_centroid = compute_centroid(source.points_iter());
@@ -58,6 +53,209 @@ where
 
     let covariant_matrix = &demeaned_source * demeaned_target.transpose();
 
+    let rotation_matrix = rotation_from_covariance(covariant_matrix)?;
+
+    let rotated_source = rotation_matrix * &demeaned_source;
+
+    let mut sum_ss = T::zero();
+    let mut sum_tt = T::zero();
+
+    for ((dem_src, dem_tgt), rot_src) in demeaned_source
+        .iter()
+        .zip(demeaned_target.iter())
+        .zip(rotated_source.iter())
+    {
+        sum_ss += (*dem_src) * (*dem_src);
+        sum_tt += (*dem_tgt) * (*rot_src);
+    }
+
+    let scale = sum_tt / sum_ss;
+
+    let rotation = Rotation::from_matrix_unchecked(rotation_matrix);
+
+    let translation =
+        Translation::from(target_centroid - rotation_matrix * source_centroid * scale);
+
+    Some(Similarity::from_parts(translation, rotation, scale))
+}
+
+/// Estimates the 6x6 covariance of the step `transform` that was estimated with [`estimate_isometry`] or
+/// [`estimate_weighted_isometry`] for point clouds in 3D.
+///
+/// This is the inverse of the point-to-point Hessian scaled by the residual variance (the weighted sum of the
+/// squared distances after applying `transform` divided by three times the number of correspondences minus six).
+/// The covariance is expressed for a left increment `[ω, v]` (rotation vector followed by the translation) in the
+/// coordinate system of the target, the same parametrization as
+/// [`crate::transform_estimation::levenberg_marquardt::se3_exp`]. The weights of the points are respected.
+///
+/// Returns `None` if the correspondences don't constrain all six degrees of freedom, e.g. if they are collinear.
+#[allow(non_snake_case)]
+pub fn estimate_covariance<T, M>(
+    alignee: &mut MaskedPointCloud<T, 3>,
+    target: &mut MaskedPointCloud<T, 3>,
+    transform: &M,
+) -> Option<Matrix6<T>>
+where
+    T: Scalar + RealField + Copy,
+    for<'b> &'b M: Mul<Point3<T>, Output = Point3<T>>,
+{
+    let mut H = Matrix6::<T>::zeros();
+    let mut squared_residual_sum = T::zero();
+    let mut count = 0;
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let weight = a.weight * t.weight;
+
+        let pos = transform * a.pos;
+
+        // derivative of the transformed point w.r.t. a left increment
+        let mut J = Matrix3x6::<T>::zeros();
+        J.fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&-pos.coords.cross_matrix());
+        J.fixed_view_mut::<3, 3>(0, 3)
+            .copy_from(&Matrix3::identity());
+
+        H += J.transpose() * J * weight;
+        squared_residual_sum += (pos - t.pos).norm_squared() * weight;
+        count += 3;
+    }
+
+    pose_covariance(H, squared_residual_sum, count)
+}
+
+/// Estimates the isometry between the alignee and the target using the weighted SVD algorithm (weighted Kabsch).
+///
+/// Same as [`estimate_isometry`] but every correspondence is weighted with the product of the weights of the alignee
+/// and the target point (see [`crate::PointCloudPoint::weight`]). Works for any dimension `D`.
+///
+/// See this [paper from Umeyama](https://doi.org/10.1109/34.88573)
+pub fn estimate_weighted_isometry<T, const D: usize>(
+    alignee: &mut MaskedPointCloud<T, D>,
+    target: &mut MaskedPointCloud<T, D>,
+    _: usize,
+) -> Option<Isometry<T, Rotation<T, D>, D>>
+where
+    T: Scalar + RealField + Copy,
+    Const<D>: DimMin<Const<D>, Output = Const<D>> + DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<D>, U1>>,
+{
+    let moments = WeightedMoments::new(alignee, target)?;
+
+    let rotation_matrix = rotation_from_covariance(moments.covariant_matrix)?;
+    let rotation = Rotation::from_matrix_unchecked(rotation_matrix);
+
+    let translation =
+        Translation::from(moments.target_centroid - rotation_matrix * moments.source_centroid);
+
+    Some(Isometry::from_parts(translation, rotation))
+}
+
+/// Estimates the similarity between the source and the target using the weighted Umeyama algorithm.
+///
+/// Same as [`estimate_similarity`] but every correspondence is weighted with the product of the weights of the source
+/// and the target point (see [`crate::PointCloudPoint::weight`]). Works for any dimension `D`.
+///
+/// See this [paper from Umeyama](https://doi.org/10.1109/34.88573)
+pub fn estimate_weighted_similarity<T, const D: usize>(
+    source: &mut MaskedPointCloud<T, D>,
+    target: &mut MaskedPointCloud<T, D>,
+) -> Option<Similarity<T, Rotation<T, D>, D>>
+where
+    T: Scalar + RealField + Copy,
+    Const<D>: DimMin<Const<D>, Output = Const<D>> + DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<D>, U1>>,
+{
+    let WeightedMoments {
+        source_centroid,
+        target_centroid,
+        covariant_matrix,
+        source_variance,
+    } = WeightedMoments::new(source, target)?;
+
+    if source_variance <= T::zero() {
+        return None;
+    }
+
+    let rotation_matrix = rotation_from_covariance(covariant_matrix)?;
+
+    // sum of w * (t - t_c) . R (s - s_c) == trace(R^T * covariance^T)
+    let scale =
+        (rotation_matrix.transpose() * covariant_matrix.transpose()).trace() / source_variance;
+
+    let rotation = Rotation::from_matrix_unchecked(rotation_matrix);
+
+    let translation =
+        Translation::from(target_centroid - rotation_matrix * source_centroid * scale);
+
+    Some(Similarity::from_parts(translation, rotation, scale))
+}
+
+/// The weighted centroids of the source and the target, their weighted cross-covariance matrix
+/// and the weighted sum of the squared distances of the source points to their centroid.
+struct WeightedMoments<T, const D: usize>
+where
+    T: Scalar,
+{
+    source_centroid: SVector<T, D>,
+    target_centroid: SVector<T, D>,
+    covariant_matrix: SMatrix<T, D, D>,
+    source_variance: T,
+}
+
+impl<T, const D: usize> WeightedMoments<T, D>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn new(source: &MaskedPointCloud<T, D>, target: &MaskedPointCloud<T, D>) -> Option<Self> {
+        let mut source_centroid = SVector::<T, D>::zeros();
+        let mut target_centroid = SVector::<T, D>::zeros();
+        let mut weight_sum = T::zero();
+
+        for (s, t) in source.iter().zip(target.iter()) {
+            let weight = s.weight * t.weight;
+            source_centroid += s.pos.coords * weight;
+            target_centroid += t.pos.coords * weight;
+            weight_sum += weight;
+        }
+
+        if weight_sum <= T::zero() {
+            return None;
+        }
+
+        source_centroid /= weight_sum;
+        target_centroid /= weight_sum;
+
+        let mut covariant_matrix = SMatrix::<T, D, D>::zeros();
+        let mut source_variance = T::zero();
+
+        for (s, t) in source.iter().zip(target.iter()) {
+            let weight = s.weight * t.weight;
+            let demeaned_source = s.pos.coords - source_centroid;
+            let demeaned_target = t.pos.coords - target_centroid;
+
+            covariant_matrix += demeaned_source * demeaned_target.transpose() * weight;
+            source_variance += demeaned_source.norm_squared() * weight;
+        }
+
+        Some(WeightedMoments {
+            source_centroid,
+            target_centroid,
+            covariant_matrix,
+            source_variance,
+        })
+    }
+}
+
+/// Computes the rotation matrix that best rotates the source onto the target from their
+/// cross-covariance matrix. Reflections are corrected.
+pub(crate) fn rotation_from_covariance<T, const D: usize>(
+    covariant_matrix: SMatrix<T, D, D>,
+) -> Option<SMatrix<T, D, D>>
+where
+    T: Scalar + RealField + Copy,
+    Const<D>: DimMin<Const<D>, Output = Const<D>> + DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<D>, U1>>,
+{
     let SVD {
         u,
         v_t,
@@ -68,32 +266,125 @@ where
     let mut v = v_t?.transpose();
 
     if u.determinant() * v.determinant() < T::zero() {
-        let mut column = v.column_mut(2);
+        let mut column = v.column_mut(D - 1);
         column *= -T::one();
     }
 
-    let rotation_matrix = v * u.transpose();
+    Some(v * u.transpose())
+}
 
-    let rotated_source = rotation_matrix * &demeaned_source;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointCloud;
+    use crate::test_utils::{assert_isometry_eq, room_corner, transformed};
 
-    let mut sum_ss = T::zero();
-    let mut sum_tt = T::zero();
+    fn transform() -> Isometry3<f32> {
+        Isometry3::new(Vector3::new(0.3, -0.2, 0.5), Vector3::new(0.2, -0.1, 0.3))
+    }
 
-    for ((dem_src, dem_tgt), rot_src) in demeaned_source
-        .iter()
-        .zip(demeaned_target.iter())
-        .zip(rotated_source.iter())
-    {
-        sum_ss += (*dem_src) * (*dem_src);
-        sum_tt += (*dem_tgt) * (*rot_src);
+    /// Moves every tenth target point far away and sets its weight to zero.
+    fn with_zero_weighted_outliers(target: &PointCloud<f32, 3>) -> PointCloud<f32, 3> {
+        let mut target = target.clone();
+        for p in target.iter_mut().step_by(10) {
+            p.pos += Vector3::new(3.0, -2.0, 4.0);
+            p.weight = 0.0;
+        }
+        target
     }
 
-    let scale = sum_tt / sum_ss;
+    #[test]
+    fn weighted_isometry_ignores_zero_weights() {
+        let alignee = room_corner(2.0, 0.2);
+        let target = with_zero_weighted_outliers(&transformed(&alignee, &transform()));
 
-    let rotation = Rotation3::from_matrix_unchecked(rotation_matrix);
+        let result = estimate_weighted_isometry(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+            0,
+        )
+        .unwrap();
 
-    let translation =
-        Translation3::from(target_centroid - rotation_matrix * source_centroid * scale);
+        assert_isometry_eq(
+            &Isometry3::from_parts(result.translation, result.rotation.into()),
+            &transform(),
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn weights_act_like_repeated_points() {
+        let alignee = room_corner(1.0, 0.25);
+        let mut target = transformed(&alignee, &transform());
+        for (i, p) in target.iter_mut().enumerate() {
+            p.pos.x += (i % 3) as f32 * 0.01;
+        }
+
+        let mut weighted_target = target.clone();
+        weighted_target[0].weight = 3.0;
+
+        let mut repeated_alignee = alignee.clone();
+        let mut repeated_target = target.clone();
+        for _ in 0..2 {
+            repeated_alignee.push(alignee[0]);
+            repeated_target.push(target[0]);
+        }
+
+        let weighted = estimate_weighted_isometry(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&weighted_target),
+            0,
+        )
+        .unwrap();
+        let repeated = estimate_isometry(
+            &mut MaskedPointCloud::new(&repeated_alignee),
+            &mut MaskedPointCloud::new(&repeated_target),
+            0,
+        )
+        .unwrap();
+
+        assert!((weighted.translation.vector - repeated.translation.vector).norm() < 1e-5);
+        assert!((weighted.rotation.matrix() - repeated.rotation.matrix()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn weighted_similarity_recovers_scale() {
+        let alignee = room_corner(2.0, 0.2);
+
+        let similarity = Similarity3::from_isometry(transform(), 1.7);
+        let mut target = alignee.clone();
+        for p in target.iter_mut() {
+            p.pos = similarity * p.pos;
+        }
+        let target = with_zero_weighted_outliers(&target);
+
+        let result = estimate_weighted_similarity(
+            &mut MaskedPointCloud::new(&alignee),
+            &mut MaskedPointCloud::new(&target),
+        )
+        .unwrap();
 
-    Some(SimilarityMatrix3::from_parts(translation, rotation, scale))
+        assert!((result.scaling() - 1.7).abs() < 1e-4);
+        assert!(
+            (result.isometry.translation.vector - transform().translation.vector).norm() < 1e-4
+        );
+    }
+
+    #[test]
+    fn rejects_zero_weights() {
+        let alignee = room_corner(1.0, 0.5);
+        let target: PointCloud<f32, 3> = alignee
+            .iter()
+            .map(|p| crate::PointCloudPoint { weight: 0.0, ..*p })
+            .collect();
+
+        assert!(
+            estimate_weighted_isometry(
+                &mut MaskedPointCloud::new(&alignee),
+                &mut MaskedPointCloud::new(&target),
+                0
+            )
+            .is_none()
+        );
+    }
 }
@@ -0,0 +1,231 @@
+use nalgebra::*;
+use num_traits::Float;
+
+use crate::MaskedPointCloud;
+use crate::transform_estimation::TransformEstimator;
+use crate::transform_estimation::motion_prior::{MotionPrior, PriorEstimator};
+
+/// Estimates the isometry between the alignee and the target by minimizing the symmetric point-to-plane objective.
+///
+/// The residual of every correspondence is measured along the sum of the alignee and the target normal while
+/// the alignee and the target are both rotated half of the way towards each other. This makes the objective
+/// exact for points on a common circle and usually converges in far fewer iterations than
+/// [`crate::transform_estimation::point_to_plane_lls::estimate_isometry`].
+///
+/// The target needs normals. If an alignee point has no normal only the target normal is used for that correspondence.
+/// Alignee normals that point away from the target normal are flipped. The weights of the points are respected.
+///
+/// See this [paper from Rusinkiewicz](https://doi.org/10.1145/3306346.3323037)
+///
+/// ## Example
+///
+/// ```
+/// # use modern_icp::{Icp, PointCloud};
+/// # use modern_icp::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+/// # use modern_icp::transform_estimation::symmetric_point_to_plane;
+/// # use modern_icp::convergence::same_squared_distance_error;
+/// #
+/// # let alignee_cloud = PointCloud::<f32, 3>::new();
+/// # let target_cloud = PointCloud::<f32, 3>::new();
+/// #
+/// let (alignee_transform, error_sum) = Icp::new()
+///     .correspondence_estimator(NearestNeighbor::new(&target_cloud))
+///     .estimate_step_transform(symmetric_point_to_plane::estimate_isometry)
+///     .is_converged(same_squared_distance_error(0.1))
+///     .estimate_transform(alignee_cloud, &target_cloud);
+/// ```
+pub fn estimate_isometry<T>(
+    alignee: &mut MaskedPointCloud<T, 3>,
+    target: &mut MaskedPointCloud<T, 3>,
+    _: usize,
+) -> Option<Isometry3<T>>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    estimate(alignee, target, None)
+}
+
+/// Builds a transform estimator like [`estimate_isometry`] that is regularized toward the pose of the `prior`.
+///
+/// The prior is linearized in the parameters of the symmetric objective and added to its normal equations
+/// (see [`MotionPrior::normal_equations`]). Set the returned [`PriorEstimator`] with
+/// [`crate::Icp::step_transform_estimator`] which starts it over in every run. If the ICP starts from an initial
+/// transform (see [`crate::Icp::initial_transform`]) use [`MotionPrior::relative_to`].
+pub fn build_isometry_estimator_with_prior<T>(
+    prior: MotionPrior<T>,
+) -> impl TransformEstimator<T, Isometry3<T>, 3> + use<T>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    PriorEstimator::new(
+        move |alignee: &mut MaskedPointCloud<T, 3>,
+              target: &mut MaskedPointCloud<T, 3>,
+              current_transform: &Isometry3<T>| {
+            estimate(alignee, target, Some((&prior, current_transform)))
+        },
+    )
+}
+
+/// The prior is given together with the transform that has been applied to the alignee so far.
+#[allow(non_snake_case)]
+fn estimate<T>(
+    alignee: &MaskedPointCloud<T, 3>,
+    target: &MaskedPointCloud<T, 3>,
+    prior: Option<(&MotionPrior<T>, &Isometry3<T>)>,
+) -> Option<Isometry3<T>>
+where
+    T: Scalar + RealField + Float + Copy,
+{
+    // the problem is linearized around the centroids to keep it well conditioned
+    let mut alignee_centroid = Vector3::<T>::zeros();
+    let mut target_centroid = Vector3::<T>::zeros();
+    let mut weight_sum = T::zero();
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let weight = a.weight * t.weight;
+        alignee_centroid += a.pos.coords * weight;
+        target_centroid += t.pos.coords * weight;
+        weight_sum += weight;
+    }
+
+    if weight_sum <= T::zero() {
+        return None;
+    }
+
+    alignee_centroid /= weight_sum;
+    target_centroid /= weight_sum;
+
+    let mut ATA = Matrix6::<T>::zeros();
+    let mut ATb = Vector6::<T>::zeros();
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let target_norm = t.norm?;
+        let norm = match a.norm {
+            Some(alignee_norm) if alignee_norm.dot(&target_norm) < T::zero() => {
+                target_norm - alignee_norm
+            }
+            Some(alignee_norm) => target_norm + alignee_norm,
+            None => target_norm,
+        };
+
+        let weight = a.weight * t.weight;
+
+        let p = a.pos.coords - alignee_centroid;
+        let q = t.pos.coords - target_centroid;
+
+        let row = Vector6::from_iterator((p + q).cross(&norm).iter().chain(norm.iter()).copied());
+
+        ATA += row * row.transpose() * weight;
+        ATb -= row * (p - q).dot(&norm) * weight;
+    }
+
+    if let Some((prior, current_transform)) = prior {
+        // to first order the parameters x = [a, t] move the alignee by the left twist
+        // [2 * a, 2 * alignee_centroid × a + t + target_centroid - alignee_centroid]
+        let two = T::one() + T::one();
+        let mut A = Matrix6::<T>::identity();
+        A.fixed_view_mut::<3, 3>(0, 0)
+            .copy_from(&(Matrix3::identity() * two));
+        A.fixed_view_mut::<3, 3>(3, 0)
+            .copy_from(&(alignee_centroid.cross_matrix() * two));
+
+        let offset = target_centroid - alignee_centroid;
+        let offset = Vector6::new(
+            T::zero(),
+            T::zero(),
+            T::zero(),
+            offset.x,
+            offset.y,
+            offset.z,
+        );
+
+        let (prior_ATA, prior_ATb) = prior.normal_equations(current_transform);
+        ATA += A.transpose() * prior_ATA * A;
+        ATb += A.transpose() * (prior_ATb - prior_ATA * offset);
+    }
+
+    let x = ATA.cholesky()?.solve(&ATb);
+
+    // the rotation part of the solution is the axis scaled by the tangent of the rotation angle
+    let scaled_axis = Vector3::new(x[0], x[1], x[2]);
+    let angle = Float::atan(scaled_axis.norm());
+    let half_rotation = UnitQuaternion::from_scaled_axis(
+        scaled_axis
+            .try_normalize(T::default_epsilon())
+            .map(|axis| axis * angle)
+            .unwrap_or_else(Vector3::zeros),
+    );
+
+    let translation = Vector3::new(x[3], x[4], x[5]) * Float::cos(angle);
+
+    Some(
+        Translation3::from(target_centroid)
+            * half_rotation
+            * Translation3::from(translation)
+            * half_rotation
+            * Translation3::from(-alignee_centroid),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convergence::same_squared_distance_error;
+    use crate::correspondence::{CorrespondenceEstimator, NearestNeighbor};
+    use crate::test_utils::{assert_isometry_eq, transformed, wavy_surface};
+    use crate::transform_estimation::point_to_plane_lls;
+    use crate::{Icp, PointCloud};
+
+    fn transform() -> Isometry3<f32> {
+        Isometry3::new(
+            Vector3::new(0.08, -0.06, 0.05),
+            Vector3::new(0.04, -0.03, 0.05),
+        )
+    }
+
+    #[test]
+    fn recovers_isometry_in_fewer_iterations_than_point_to_plane() {
+        let target = wavy_surface(4.0, 0.2);
+        let alignee = transformed(&target, &transform());
+
+        let symmetric = Icp::new()
+            .max_iterations(100)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-10))
+            .register(alignee.clone(), &target);
+
+        let point_to_plane = Icp::new()
+            .max_iterations(100)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(point_to_plane_lls::estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-10))
+            .register(alignee, &target);
+
+        assert_isometry_eq(&symmetric.transform, &transform().inverse(), 1e-3);
+        assert!(
+            symmetric.iterations < point_to_plane.iterations,
+            "{} iterations with the symmetric objective, {} with point-to-plane",
+            symmetric.iterations,
+            point_to_plane.iterations
+        );
+    }
+
+    #[test]
+    fn uses_target_normals_for_alignee_without_normals() {
+        let target = wavy_surface(4.0, 0.2);
+        let alignee: PointCloud<f32, 3> = transformed(&target, &transform())
+            .into_iter()
+            .map(|p| crate::PointCloudPoint { norm: None, ..p })
+            .collect();
+
+        let (result, _) = Icp::new()
+            .max_iterations(100)
+            .correspondence_estimator(NearestNeighbor::new(&target))
+            .estimate_step_transform(estimate_isometry)
+            .is_converged(same_squared_distance_error(1e-10))
+            .estimate_transform(alignee, &target);
+
+        assert_isometry_eq(&result, &transform().inverse(), 1e-3);
+    }
+}
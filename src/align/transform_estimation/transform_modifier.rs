@@ -50,6 +50,8 @@ where
 ///
 /// This allos to use multiple transform estimators in a row.
 /// The first estimator is used for the first iteration, the second estimator for the second iteration and so on.
+///
+/// Use [`crate::transform_estimation::schedule::EstimatorSchedule`] to run owned estimators in stages instead.
 pub fn build_interlaced_transform_estimator<T, M, const S: usize>(
     transform_estimators: &'static mut [impl FnMut(
         &mut MaskedPointCloud<T, 3>,
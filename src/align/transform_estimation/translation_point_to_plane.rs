@@ -1,32 +1,45 @@
-use crate::{MaskedPointCloud, Plane};
+use crate::{IcpError, MaskedPointCloud, Plane};
 use nalgebra::*;
 
 /// Estimates exclusively the translation between the alignee and the target using the Point-to-Plane algorithm.
+///
+/// Returns `None` if there are no correspondences or a target point has no normal. Use [`try_estimate_translation`]
+/// to find out which.
 pub fn estimate_translation<T, const D: usize>(
     alignee: &mut MaskedPointCloud<T, D>,
     target: &mut MaskedPointCloud<T, D>,
-    _: usize,
+    step: usize,
 ) -> Option<Isometry<T, Rotation<T, D>, D>>
+where
+    T: Scalar + RealField + Copy + From<f32>,
+{
+    try_estimate_translation(alignee, target, step).ok()
+}
+
+/// Same as [`estimate_translation`] but returns [`IcpError::NoCorrespondences`] if there are no correspondences and
+/// [`IcpError::MissingNormals`] if a target point has no normal.
+pub fn try_estimate_translation<T, const D: usize>(
+    alignee: &mut MaskedPointCloud<T, D>,
+    target: &mut MaskedPointCloud<T, D>,
+    _: usize,
+) -> Result<Isometry<T, Rotation<T, D>, D>, IcpError>
 where
     T: Scalar + RealField + Copy + From<f32>,
 {
     if alignee.is_empty() || target.is_empty() {
-        return None;
+        return Err(IcpError::NoCorrespondences);
+    }
+
+    let mut translation_vec = SVector::<T, D>::zeros();
+
+    for (a, t) in alignee.iter().zip(target.iter()) {
+        let plane = Plane::from_normal_and_point(&t.norm.ok_or(IcpError::MissingNormals)?, &t.pos);
+        translation_vec += plane.normal * plane.distance_to_point(&a.pos);
     }
 
-    let translation_vec = alignee
-        .iter()
-        .zip(target.iter().map(|t| {
-            Plane::from_normal_and_point(
-                &t.norm.expect("Target point cloud must have normals"),
-                &t.pos,
-            )
-        }))
-        .map(|(a, t)| t.normal * t.distance_to_point(&a.pos))
-        .sum::<SVector<T, D>>()
-        / -T::from(alignee.len() as f32);
+    translation_vec /= -T::from(alignee.len() as f32);
 
-    Some(Isometry::from_parts(
+    Ok(Isometry::from_parts(
         Translation::from(translation_vec),
         Rotation::identity(),
     ))
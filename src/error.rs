@@ -0,0 +1,42 @@
+use std::fmt::{Display, Formatter};
+
+/// The reasons why a registration or one of its building blocks can fail.
+///
+/// Returned by the fallible variants like [`crate::Icp::try_estimate_transform`],
+/// [`crate::transform_estimation::point_to_plane_lls::try_estimate_isometry`] or [`crate::Plane::try_fit_to_points`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IcpError {
+    /// The alignee point cloud has no points.
+    EmptyPointCloud,
+    /// A point has NaN or infinite coordinates.
+    NonFiniteCoordinates,
+    /// A target point has no normal but the transform estimator needs normals.
+    MissingNormals,
+    /// No correspondences were left after filtering and outlier rejection.
+    NoCorrespondences,
+    /// The linear system of the estimator is singular, e.g. because the correspondences don't constrain all degrees
+    /// of freedom.
+    SingularSystem,
+    /// A matrix decomposition like the SVD didn't converge.
+    DecompositionFailed,
+    /// A transform estimator that doesn't report a reason returned `None`.
+    EstimationFailed,
+}
+
+impl Display for IcpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            IcpError::EmptyPointCloud => "the point cloud is empty",
+            IcpError::NonFiniteCoordinates => "a point has NaN or infinite coordinates",
+            IcpError::MissingNormals => "a target point has no normal",
+            IcpError::NoCorrespondences => "no correspondences were found",
+            IcpError::SingularSystem => "the linear system is singular",
+            IcpError::DecompositionFailed => "a matrix decomposition didn't converge",
+            IcpError::EstimationFailed => "the transform estimation failed",
+        };
+
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for IcpError {}
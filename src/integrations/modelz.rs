@@ -1,7 +1,7 @@
-use modelz::Model3D;
+use modelz::{Indices, Model3D};
 use nalgebra::{Point3, Vector3};
 
-use crate::{PointCloud, PointCloudPoint, ToPointCloud};
+use crate::{PointCloud, PointCloudPoint, ToPointCloud, ToTriangles, Triangle};
 
 impl ToPointCloud<f32, 3> for Model3D {
     fn to_point_cloud(&self) -> PointCloud<f32, 3> {
@@ -14,6 +14,8 @@ impl ToPointCloud<f32, 3> for Model3D {
                     pos: Point3::new(vertex.position[0], vertex.position[1], vertex.position[2]),
                     norm: vertex.normal.map(|n| Vector3::new(n[0], n[1], n[2])),
                     weight: 1.0,
+                    color: vertex.color.map(|c| Vector3::new(c[0], c[1], c[2])),
+                    intensity: None,
                 });
             }
         }
@@ -21,3 +23,35 @@ impl ToPointCloud<f32, 3> for Model3D {
         point_cloud
     }
 }
+
+impl ToTriangles<f32> for Model3D {
+    /// Meshes without indices are interpreted as a plain list of triangles, i.e. every three consecutive
+    /// vertices form a triangle. Faces that reference a vertex outside the mesh are skipped.
+    fn to_triangles(&self) -> Vec<Triangle<f32>> {
+        let mut triangles = vec![];
+
+        for mesh in &self.meshes {
+            let indices: Vec<usize> = match &mesh.indices {
+                Some(Indices::U8(indices)) => indices.iter().map(|i| *i as usize).collect(),
+                Some(Indices::U16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+                Some(Indices::U32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+                None => (0..mesh.vertices.len()).collect(),
+            };
+
+            let position = |i: usize| {
+                let p = mesh.vertices.get(i)?.position;
+                Some(Point3::new(p[0], p[1], p[2]))
+            };
+
+            for face in indices.chunks_exact(3) {
+                if let (Some(a), Some(b), Some(c)) =
+                    (position(face[0]), position(face[1]), position(face[2]))
+                {
+                    triangles.push(Triangle::new(a, b, c));
+                }
+            }
+        }
+
+        triangles
+    }
+}
@@ -107,14 +107,20 @@
 //! When you run the ICP algorithm you'll see the alignment process visualized in real-time.
 mod align;
 mod common;
+mod error;
 mod integrations;
+mod mesh;
 mod plane;
 mod point_cloud;
+#[cfg(test)]
+mod test_utils;
 
 pub use align::*;
 pub use common::*;
+pub use error::*;
 #[allow(unused_imports)]
 pub use integrations::*;
+pub use mesh::*;
 pub use plane::*;
 pub use point_cloud::*;
 
@@ -129,10 +135,11 @@ where
     T: Clone + PartialEq + nalgebra::Scalar,
     f32: From<T>,
 {
+    // 2D points are logged in the xy-plane
     [
         pt[0].clone().into(),
         pt[1].clone().into(),
-        pt[2].clone().into(),
+        pt.coords.get(2).map_or(0.0, |z| z.clone().into()),
     ]
 }
 
@@ -145,7 +152,7 @@ where
     [
         vec[0].clone().into(),
         vec[1].clone().into(),
-        vec[2].clone().into(),
+        vec.get(2).map_or(0.0, |z| z.clone().into()),
     ]
 }
 
@@ -165,7 +172,7 @@ where
     T: Copy + Clone + PartialEq + nalgebra::Scalar,
     f32: From<T>,
 {
-    if D == 3 {
+    if D == 2 || D == 3 {
         crate::RR
             .log(
                 format!("{}/points", name),
@@ -0,0 +1,354 @@
+use nalgebra::*;
+
+/// Triangle in 3D space described by its three corners.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Triangle<T: Scalar + RealField + Copy> {
+    pub a: Point3<T>,
+    pub b: Point3<T>,
+    pub c: Point3<T>,
+}
+
+impl<T: Scalar + RealField + Copy> Triangle<T> {
+    pub fn new(a: Point3<T>, b: Point3<T>, c: Point3<T>) -> Self {
+        Triangle { a, b, c }
+    }
+
+    /// The unit face normal following the right-hand rule for the corners `a`, `b`, `c`.
+    ///
+    /// Returns `None` for degenerate triangles.
+    pub fn normal(&self) -> Option<Vector3<T>> {
+        (self.b - self.a)
+            .cross(&(self.c - self.a))
+            .try_normalize(T::default_epsilon())
+    }
+
+    pub fn centroid(&self) -> Point3<T> {
+        Point3::from((self.a.coords + self.b.coords + self.c.coords) / T::from_u8(3).unwrap())
+    }
+
+    /// Computes the point on the triangle (including its interior) that is closest to `point`.
+    ///
+    /// See section 5.1.5 of Ericson, "Real-Time Collision Detection".
+    pub fn closest_point(&self, point: &Point3<T>) -> Point3<T> {
+        let ab = self.b - self.a;
+        let ac = self.c - self.a;
+
+        let ap = point - self.a;
+        let d1 = ab.dot(&ap);
+        let d2 = ac.dot(&ap);
+        if d1 <= T::zero() && d2 <= T::zero() {
+            return self.a;
+        }
+
+        let bp = point - self.b;
+        let d3 = ab.dot(&bp);
+        let d4 = ac.dot(&bp);
+        if d3 >= T::zero() && d4 <= d3 {
+            return self.b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= T::zero() && d1 >= T::zero() && d3 <= T::zero() {
+            let v = d1 / (d1 - d3);
+            return self.a + ab * v;
+        }
+
+        let cp = point - self.c;
+        let d5 = ab.dot(&cp);
+        let d6 = ac.dot(&cp);
+        if d6 >= T::zero() && d5 <= d6 {
+            return self.c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= T::zero() && d2 >= T::zero() && d6 <= T::zero() {
+            let w = d2 / (d2 - d6);
+            return self.a + ac * w;
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= T::zero() && (d4 - d3) >= T::zero() && (d5 - d6) >= T::zero() {
+            let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+            return self.b + (self.c - self.b) * w;
+        }
+
+        let denom = T::one() / (va + vb + vc);
+        let v = vb * denom;
+        let w = vc * denom;
+
+        self.a + ab * v + ac * w
+    }
+
+    fn bounds(&self) -> (Point3<T>, Point3<T>) {
+        (
+            self.a.inf(&self.b).inf(&self.c),
+            self.a.sup(&self.b).sup(&self.c),
+        )
+    }
+}
+
+/// Trait for types that can be converted into a list of triangles like a mesh.
+pub trait ToTriangles<T>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn to_triangles(&self) -> Vec<Triangle<T>>;
+}
+
+impl<T> ToTriangles<T> for Vec<Triangle<T>>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn to_triangles(&self) -> Vec<Triangle<T>> {
+        self.clone()
+    }
+}
+
+impl<T> ToTriangles<T> for Vec<[Point3<T>; 3]>
+where
+    T: Scalar + RealField + Copy,
+{
+    fn to_triangles(&self) -> Vec<Triangle<T>> {
+        self.iter()
+            .map(|[a, b, c]| Triangle::new(*a, *b, *c))
+            .collect()
+    }
+}
+
+const MAX_TRIANGLES_PER_LEAF: usize = 4;
+
+#[derive(Clone, Debug)]
+struct BvhNode<T: Scalar + RealField + Copy> {
+    min: Point3<T>,
+    max: Point3<T>,
+    content: BvhNodeContent,
+}
+
+#[derive(Clone, Debug)]
+enum BvhNodeContent {
+    /// Range into the triangle list
+    Leaf(usize, usize),
+    /// Indices of the child nodes
+    Inner(usize, usize),
+}
+
+/// Bounding volume hierarchy of axis aligned bounding boxes over a list of triangles.
+///
+/// Used to find the closest point on the surface of a mesh.
+#[derive(Clone, Debug)]
+pub struct TriangleBvh<T: Scalar + RealField + Copy> {
+    triangles: Vec<Triangle<T>>,
+    nodes: Vec<BvhNode<T>>,
+}
+
+impl<T: Scalar + RealField + Copy> TriangleBvh<T> {
+    /// Builds the hierarchy by recursively splitting the triangles at the median of their
+    /// centroids along the longest axis of the bounding box.
+    pub fn new(mut triangles: Vec<Triangle<T>>) -> Self {
+        let mut nodes = Vec::with_capacity(2 * triangles.len() / MAX_TRIANGLES_PER_LEAF + 1);
+
+        if !triangles.is_empty() {
+            let len = triangles.len();
+            Self::build(&mut triangles, &mut nodes, 0, len);
+        }
+
+        TriangleBvh { triangles, nodes }
+    }
+
+    fn build(
+        triangles: &mut [Triangle<T>],
+        nodes: &mut Vec<BvhNode<T>>,
+        start: usize,
+        end: usize,
+    ) -> usize {
+        let (mut min, mut max) = triangles[start].bounds();
+        for triangle in &triangles[start + 1..end] {
+            let (t_min, t_max) = triangle.bounds();
+            min = min.inf(&t_min);
+            max = max.sup(&t_max);
+        }
+
+        let node_index = nodes.len();
+        nodes.push(BvhNode {
+            min,
+            max,
+            content: BvhNodeContent::Leaf(start, end),
+        });
+
+        if end - start <= MAX_TRIANGLES_PER_LEAF {
+            return node_index;
+        }
+
+        let axis = (max - min).imax();
+        triangles[start..end].sort_by(|a, b| {
+            partial_cmp(&a.centroid()[axis], &b.centroid()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let mid = start + (end - start) / 2;
+        let left = Self::build(triangles, nodes, start, mid);
+        let right = Self::build(triangles, nodes, mid, end);
+
+        nodes[node_index].content = BvhNodeContent::Inner(left, right);
+
+        node_index
+    }
+
+    pub fn triangles(&self) -> &[Triangle<T>] {
+        &self.triangles
+    }
+
+    /// Finds the point on the surface of the triangles that is closest to `point`.
+    ///
+    /// Returns the closest point, the triangle it lies on and the squared distance to `point`.
+    /// Returns `None` if there are no triangles.
+    pub fn closest_point(&self, point: &Point3<T>) -> Option<(Point3<T>, &Triangle<T>, T)> {
+        let mut best: Option<(Point3<T>, &Triangle<T>, T)> = None;
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let Some(node) = self.nodes.get(node_index) else {
+                continue;
+            };
+
+            if let Some((_, _, best_dist)) = best
+                && Self::squared_distance_to_box(point, &node.min, &node.max) > best_dist
+            {
+                continue;
+            }
+
+            match node.content {
+                BvhNodeContent::Leaf(start, end) => {
+                    for triangle in &self.triangles[start..end] {
+                        let closest = triangle.closest_point(point);
+                        let dist = (closest - point).norm_squared();
+
+                        if best.is_none_or(|(_, _, best_dist)| dist < best_dist) {
+                            best = Some((closest, triangle, dist));
+                        }
+                    }
+                }
+                BvhNodeContent::Inner(left, right) => {
+                    let left_dist = Self::squared_distance_to_box(
+                        point,
+                        &self.nodes[left].min,
+                        &self.nodes[left].max,
+                    );
+                    let right_dist = Self::squared_distance_to_box(
+                        point,
+                        &self.nodes[right].min,
+                        &self.nodes[right].max,
+                    );
+
+                    // push the farther node first so the closer one is visited first
+                    if left_dist < right_dist {
+                        stack.push(right);
+                        stack.push(left);
+                    } else {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    fn squared_distance_to_box(point: &Point3<T>, min: &Point3<T>, max: &Point3<T>) -> T {
+        let clamped = point.sup(min).inf(max);
+        (point - clamped).norm_squared()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_mesh(count: usize) -> Vec<Triangle<f32>> {
+        let height = |i: usize, j: usize| ((i * 7 + j * 3) % 5) as f32 * 0.1;
+        let corner = |i: usize, j: usize| Point3::new(i as f32, j as f32, height(i, j));
+
+        let mut triangles = vec![];
+        for i in 0..count {
+            for j in 0..count {
+                triangles.push(Triangle::new(
+                    corner(i, j),
+                    corner(i + 1, j),
+                    corner(i, j + 1),
+                ));
+                triangles.push(Triangle::new(
+                    corner(i + 1, j),
+                    corner(i + 1, j + 1),
+                    corner(i, j + 1),
+                ));
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn closest_point_on_triangle() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 2.0, 0.0),
+        );
+
+        // interior, edge and corner regions
+        assert_eq!(
+            triangle.closest_point(&Point3::new(0.5, 0.5, 1.0)),
+            Point3::new(0.5, 0.5, 0.0)
+        );
+        assert_eq!(
+            triangle.closest_point(&Point3::new(1.0, -1.0, 1.0)),
+            Point3::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            triangle.closest_point(&Point3::new(2.0, 2.0, 0.0)),
+            Point3::new(1.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            triangle.closest_point(&Point3::new(-1.0, -1.0, 0.5)),
+            Point3::origin()
+        );
+        assert_eq!(
+            triangle.closest_point(&Point3::new(3.0, -0.5, 0.0)),
+            Point3::new(2.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn bvh_finds_same_distance_as_brute_force() {
+        let triangles = grid_mesh(8);
+        let bvh = TriangleBvh::new(triangles.clone());
+
+        for i in 0..20 {
+            let point = Point3::new(
+                (i as f32 * 0.77) % 9.0 - 0.5,
+                (i as f32 * 1.31) % 9.0 - 0.5,
+                (i as f32 * 0.43) % 2.0 - 0.5,
+            );
+
+            let brute_force = triangles
+                .iter()
+                .map(|triangle| (triangle.closest_point(&point) - point).norm_squared())
+                .fold(f32::INFINITY, f32::min);
+
+            let (closest, _, distance) = bvh.closest_point(&point).unwrap();
+
+            assert!((distance - brute_force).abs() < 1e-5, "{point:?}");
+            assert!(((closest - point).norm_squared() - distance).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn empty_bvh_has_no_closest_point() {
+        assert!(
+            TriangleBvh::<f32>::new(vec![])
+                .closest_point(&Point3::origin())
+                .is_none()
+        );
+    }
+}
@@ -2,7 +2,7 @@ use nalgebra::*;
 use num_traits::{Float, Zero};
 use statistical::standard_deviation;
 
-use crate::{MaskedPointCloud, compute_centroid, demean_into_matrix};
+use crate::{IcpError, MaskedPointCloud, compute_centroid, demean_into_matrix};
 
 /// Plane that is described by the equation `normal.dot(point_on_plane.coords) - constant == 0`
 #[derive(Clone, Copy)]
@@ -55,7 +55,22 @@ impl<T: Scalar + RealField + Copy, const D: usize> Plane<T, D> {
     }
 
     /// Fit the plane to the given points using SVD.
+    ///
+    /// Panics if there are no points or the SVD doesn't converge. Use [`Plane::try_fit_to_points`] to handle these
+    /// cases.
     pub fn fit_to_points(points: impl Iterator<Item = Point<T, D>> + Clone) -> Self {
+        Self::try_fit_to_points(points).expect("SVD unsuccessful")
+    }
+
+    /// Same as [`Plane::fit_to_points`] but returns [`IcpError::EmptyPointCloud`] if there are no points and
+    /// [`IcpError::DecompositionFailed`] if the SVD doesn't converge.
+    pub fn try_fit_to_points(
+        points: impl Iterator<Item = Point<T, D>> + Clone,
+    ) -> Result<Self, IcpError> {
+        if points.clone().next().is_none() {
+            return Err(IcpError::EmptyPointCloud);
+        }
+
         let alignee_centroid = compute_centroid(points.clone());
         let demeaned_alignee = demean_into_matrix(points, &alignee_centroid);
 
@@ -65,12 +80,15 @@ impl<T: Scalar + RealField + Copy, const D: usize> Plane<T, D> {
             singular_values: _,
         } = demeaned_alignee
             .try_svd(true, false, T::default_epsilon(), 10)
-            .expect("SVD unsuccessful");
+            .ok_or(IcpError::DecompositionFailed)?;
 
-        let uw = u.unwrap();
+        let uw = u.ok_or(IcpError::DecompositionFailed)?;
         let normal = uw.column(2);
 
-        Plane::from_normal_and_point(&normal.normalize(), &Point::from(alignee_centroid))
+        Ok(Plane::from_normal_and_point(
+            &normal.normalize(),
+            &Point::from(alignee_centroid),
+        ))
     }
 
     /// Fits a plane iteratively to the point cloud using SVD and then rejects points that are farther
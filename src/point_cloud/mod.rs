@@ -1,5 +1,7 @@
 mod iterator;
 mod masked;
+mod normals;
+mod organized;
 mod point;
 mod traits;
 
@@ -7,10 +9,14 @@ pub use iterator::*;
 use kdtree::KdTree;
 pub use masked::*;
 use nalgebra::{Point3, RealField, Scalar};
+pub use normals::*;
 use num_traits::{Float, One, Zero};
+pub use organized::*;
 pub use point::*;
 pub use traits::*;
 
+use crate::IcpError;
+
 pub type PointCloud<T, const D: usize> = Vec<PointCloudPoint<T, D>>;
 
 pub fn point_cloud_from_position_slice<T: Scalar + Copy + One>(slice: &[T]) -> PointCloud<T, 3> {
@@ -35,9 +41,38 @@ where
 {
     let mut kd_tree = KdTree::new(D);
 
+    // points with NaN or infinite coordinates can't be added and are left out,
+    // `CorrespondenceEstimator::validate_target` reports them
     for (i, p) in point_cloud.iter().enumerate() {
-        kd_tree.add(p.pos.coords.as_slice().to_owned(), i).unwrap();
+        let _ = kd_tree.add(p.pos.coords.as_slice().to_owned(), i);
     }
 
     kd_tree
 }
+
+/// Checks that the point cloud is not empty and that all positions and normals are finite.
+///
+/// [`crate::Icp::try_estimate_transform`] does this for the alignee and, through
+/// [`crate::correspondence::CorrespondenceEstimator::validate_target`], for the target.
+pub fn validate_point_cloud<T, const D: usize>(
+    point_cloud: &PointCloud<T, D>,
+) -> Result<(), IcpError>
+where
+    T: Scalar + RealField + Float,
+{
+    if point_cloud.is_empty() {
+        return Err(IcpError::EmptyPointCloud);
+    }
+
+    let is_finite = |p: &PointCloudPoint<T, D>| {
+        p.pos.iter().all(|c| Float::is_finite(*c))
+            && p.norm
+                .is_none_or(|n| n.iter().all(|c| Float::is_finite(*c)))
+    };
+
+    if !point_cloud.iter().all(is_finite) {
+        return Err(IcpError::NonFiniteCoordinates);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use kdtree::distance::squared_euclidean;
+use nalgebra::{
+    Const, DefaultAllocator, DimSub, RealField, Scalar, ToTypenum, allocator::Allocator,
+};
+use num_traits::{Float, One, Zero};
+
+use super::{PointCloud, kd_tree_of_point_cloud};
+use crate::pca::compute_principal_component_analysis;
+
+/// Estimates the normal of every point from its `k_neighbors` nearest neighbors (including itself)
+/// and stores it in the point's `norm` field.
+///
+/// The normal is the direction of least variance of the neighborhood (see [`crate::pca::compute_principal_component_analysis`]).
+/// Works for any dimension `D`, e.g. `2` for laser scans where the normal is perpendicular to the scan line.
+/// Because the sign of such a normal is ambiguous, all normals are flipped to face the origin, which is
+/// usually where the sensor is located.
+pub fn estimate_normals<T, const D: usize>(cloud: &mut PointCloud<T, D>, k_neighbors: usize)
+where
+    T: Scalar + RealField + Float + One + Zero,
+    Const<D>: ToTypenum + DimSub<Const<1>>,
+    DefaultAllocator: Allocator<<Const<D> as DimSub<Const<1>>>::Output>,
+{
+    let tree = kd_tree_of_point_cloud(cloud);
+
+    let normals: Vec<_> = cloud
+        .iter()
+        .map(|p| {
+            let neighbors = tree
+                .nearest(
+                    p.pos.coords.as_slice(),
+                    k_neighbors.max(D),
+                    &squared_euclidean,
+                )
+                .ok()?;
+
+            if neighbors.len() < D {
+                return None;
+            }
+
+            let (normal, _) = compute_principal_component_analysis(
+                neighbors.iter().map(|(_, idx)| cloud[**idx].pos),
+            )
+            .pop()?;
+
+            if normal.dot(&p.pos.coords) > T::zero() {
+                Some(-normal)
+            } else {
+                Some(normal)
+            }
+        })
+        .collect();
+
+    for (p, normal) in cloud.iter_mut().zip(normals) {
+        p.norm = normal;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointCloudPoint;
+    use nalgebra::{Point2, Vector2};
+
+    #[test]
+    fn normals_of_2d_line_face_origin() {
+        let mut scan: PointCloud<f32, 2> = (0..10)
+            .map(|i| PointCloudPoint::from_pos(Point2::new(i as f32 * 0.1 - 0.5, 1.0)))
+            .collect();
+
+        estimate_normals(&mut scan, 3);
+
+        for p in &scan {
+            assert!((p.norm.unwrap() - Vector2::new(0.0, -1.0)).norm() < 1e-5);
+        }
+    }
+}
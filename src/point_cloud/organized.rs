@@ -0,0 +1,282 @@
+use nalgebra::{Point2, Point3, RealField, Scalar, Vector3};
+
+use super::{PointCloud, PointCloudPoint, ToPointCloud};
+
+/// Pinhole camera model described by its intrinsics.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PinholeCamera<T: Scalar + RealField + Copy> {
+    /// Focal length in x direction in pixels
+    pub fx: T,
+    /// Focal length in y direction in pixels
+    pub fy: T,
+    /// Principal point x coordinate in pixels
+    pub cx: T,
+    /// Principal point y coordinate in pixels
+    pub cy: T,
+}
+
+impl<T: Scalar + RealField + Copy> PinholeCamera<T> {
+    pub fn new(fx: T, fy: T, cx: T, cy: T) -> Self {
+        PinholeCamera { fx, fy, cx, cy }
+    }
+
+    /// Projects a point given in camera coordinates onto the image plane.
+    ///
+    /// Returns `None` for points that lie behind or on the camera plane.
+    pub fn project(&self, point: &Point3<T>) -> Option<Point2<T>> {
+        if point.z <= T::zero() {
+            return None;
+        }
+
+        Some(Point2::new(
+            self.fx * point.x / point.z + self.cx,
+            self.fy * point.y / point.z + self.cy,
+        ))
+    }
+
+    /// Back-projects the pixel `(u, v)` with the given `depth` into camera coordinates.
+    pub fn back_project(&self, u: T, v: T, depth: T) -> Point3<T> {
+        Point3::new(
+            (u - self.cx) * depth / self.fx,
+            (v - self.cy) * depth / self.fy,
+            depth,
+        )
+    }
+}
+
+/// Point cloud that is organized in a `width` × `height` grid like the pixels of a depth image.
+///
+/// The points are stored row by row and are given in the coordinates of the `camera`.
+/// Pixels without a measurement are marked as invalid (see [`OrganizedPointCloud::is_valid`]).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrganizedPointCloud<T: Scalar + RealField + Copy> {
+    pub width: usize,
+    pub height: usize,
+    pub camera: PinholeCamera<T>,
+    pub points: PointCloud<T, 3>,
+    pub valid: Vec<bool>,
+}
+
+impl<T: Scalar + RealField + Copy> OrganizedPointCloud<T> {
+    /// Creates an organized point cloud from a row-major grid of points.
+    ///
+    /// Points with non-finite coordinates or non-positive depth are marked invalid.
+    ///
+    /// Panics if `points.len() != width * height`.
+    pub fn new(
+        width: usize,
+        height: usize,
+        camera: PinholeCamera<T>,
+        points: PointCloud<T, 3>,
+    ) -> Self {
+        assert_eq!(
+            points.len(),
+            width * height,
+            "Number of points must equal width * height"
+        );
+
+        let valid = points
+            .iter()
+            .map(|p| p.pos.iter().all(|c| c.is_finite()) && p.pos.z > T::zero())
+            .collect();
+
+        OrganizedPointCloud {
+            width,
+            height,
+            camera,
+            points,
+            valid,
+        }
+    }
+
+    /// Back-projects a row-major depth image into an organized point cloud.
+    ///
+    /// The normals are computed from the neighboring pixels to the right and below. Pixels with a depth
+    /// that is not positive or not finite are marked invalid.
+    ///
+    /// Panics if `depth.len() != width * height`.
+    pub fn from_depth_image(
+        depth: &[T],
+        width: usize,
+        height: usize,
+        camera: PinholeCamera<T>,
+    ) -> Self {
+        assert_eq!(
+            depth.len(),
+            width * height,
+            "Number of depth values must equal width * height"
+        );
+
+        let mut points: PointCloud<T, 3> = depth
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let u = T::from_usize(i % width).unwrap();
+                let v = T::from_usize(i / width).unwrap();
+                PointCloudPoint::from_pos(camera.back_project(u, v, *d))
+            })
+            .collect();
+
+        let valid: Vec<bool> = depth
+            .iter()
+            .map(|d| d.is_finite() && *d > T::zero())
+            .collect();
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * width + x;
+
+                // use the left/upper neighbour at the right/bottom image border
+                let (x0, x1) = if x + 1 < width {
+                    (x, x + 1)
+                } else {
+                    (x.saturating_sub(1), x)
+                };
+                let (y0, y1) = if y + 1 < height {
+                    (y, y + 1)
+                } else {
+                    (y.saturating_sub(1), y)
+                };
+
+                let h0 = y * width + x0;
+                let h1 = y * width + x1;
+                let v0 = y0 * width + x;
+                let v1 = y1 * width + x;
+
+                if !valid[i] || !valid[h0] || !valid[h1] || !valid[v0] || !valid[v1] {
+                    continue;
+                }
+
+                let dx = points[h1].pos - points[h0].pos;
+                let dy = points[v1].pos - points[v0].pos;
+
+                if let Some(normal) = dy.cross(&dx).try_normalize(T::default_epsilon()) {
+                    // make the normal face the camera
+                    let normal: Vector3<T> = if normal.dot(&points[i].pos.coords) > T::zero() {
+                        -normal
+                    } else {
+                        normal
+                    };
+                    points[i].norm = Some(normal);
+                }
+            }
+        }
+
+        OrganizedPointCloud {
+            width,
+            height,
+            camera,
+            points,
+            valid,
+        }
+    }
+
+    /// Returns the index into `points` of the pixel `(x, y)` if it lies inside the grid.
+    pub fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the point at `index` holds a valid measurement.
+    pub fn is_valid(&self, index: usize) -> bool {
+        self.valid.get(index).copied().unwrap_or(false)
+    }
+
+    /// Projects the point (given in camera coordinates) into the grid and returns the index of the
+    /// pixel it falls onto. Returns `None` if the point is outside the image or the pixel is invalid.
+    pub fn project(&self, point: &Point3<T>) -> Option<usize> {
+        let pixel = self.camera.project(point)?;
+
+        // round to the nearest pixel
+        let half = T::from_f32(0.5).unwrap();
+        let x = (pixel.x + half).floor();
+        let y = (pixel.y + half).floor();
+
+        if x < T::zero() || y < T::zero() {
+            return None;
+        }
+
+        let x: f64 = x.to_subset()?;
+        let y: f64 = y.to_subset()?;
+
+        let index = self.index(x as usize, y as usize)?;
+
+        self.is_valid(index).then_some(index)
+    }
+}
+
+impl<T: Scalar + RealField + Copy> ToPointCloud<T, 3> for OrganizedPointCloud<T> {
+    /// Returns only the valid points.
+    fn to_point_cloud(&self) -> PointCloud<T, 3> {
+        self.points
+            .iter()
+            .zip(self.valid.iter())
+            .filter_map(|(p, valid)| valid.then_some(*p))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> PinholeCamera<f32> {
+        PinholeCamera::new(100.0, 100.0, 2.0, 1.5)
+    }
+
+    #[test]
+    fn back_projected_pixels_project_onto_themselves() {
+        let camera = camera();
+
+        for (u, v, depth) in [(0.0, 0.0, 1.0), (3.0, 2.0, 2.5), (1.5, 0.5, 0.3)] {
+            let pixel = camera.project(&camera.back_project(u, v, depth)).unwrap();
+            assert!((pixel - Point2::new(u, v)).norm() < 1e-5);
+        }
+
+        assert!(camera.project(&Point3::new(0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn normals_of_depth_image_face_camera() {
+        let mut depth = vec![2.0; 4 * 3];
+        depth[5] = 0.0;
+
+        let cloud = OrganizedPointCloud::from_depth_image(&depth, 4, 3, camera());
+
+        assert!(!cloud.is_valid(5));
+        assert_eq!(cloud.to_point_cloud().len(), 11);
+
+        // the neighbours of the invalid pixel have no normal
+        assert!(cloud.points[4].norm.is_none());
+        assert!(cloud.points[1].norm.is_none());
+
+        let normal = cloud.points[10].norm.unwrap();
+        assert!((normal - Vector3::new(0.0, 0.0, -1.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn projects_onto_nearest_valid_pixel() {
+        let mut depth = vec![2.0; 4 * 3];
+        depth[5] = f32::NAN;
+
+        let cloud = OrganizedPointCloud::from_depth_image(&depth, 4, 3, camera());
+
+        let point = cloud.camera.back_project(2.4, 0.6, 1.0);
+        assert_eq!(cloud.project(&point), Some(cloud.index(2, 1).unwrap()));
+
+        // pixel (1, 1) is invalid and (5, 1) is outside the image
+        assert_eq!(
+            cloud.project(&cloud.camera.back_project(1.0, 1.0, 1.0)),
+            None
+        );
+        assert_eq!(
+            cloud.project(&cloud.camera.back_project(5.0, 1.0, 1.0)),
+            None
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use nalgebra::{Point, SVector, Scalar};
+use nalgebra::Point;
+use nalgebra::{RealField, SVector, Scalar, Vector3};
 use num_traits::One;
 
 #[derive(Copy, Clone, Debug)]
@@ -7,6 +8,10 @@ pub struct PointCloudPoint<T: Scalar + Copy, const D: usize> {
     pub pos: Point<T, D>,
     pub norm: Option<SVector<T, D>>,
     pub weight: T,
+    /// Optional RGB color with components in the range `[0, 1]`.
+    pub color: Option<Vector3<T>>,
+    /// Optional intensity, e.g. the reflectance of a lidar return.
+    pub intensity: Option<T>,
 }
 
 impl<T: Scalar + Copy + One, const D: usize> PointCloudPoint<T, D> {
@@ -15,6 +20,8 @@ impl<T: Scalar + Copy + One, const D: usize> PointCloudPoint<T, D> {
             pos,
             norm: None,
             weight: T::one(),
+            color: None,
+            intensity: None,
         }
     }
 
@@ -23,8 +30,38 @@ impl<T: Scalar + Copy + One, const D: usize> PointCloudPoint<T, D> {
             pos,
             norm: Some(norm),
             weight: T::one(),
+            color: None,
+            intensity: None,
         }
     }
+
+    pub fn with_color(self, color: Vector3<T>) -> Self {
+        Self {
+            color: Some(color),
+            ..self
+        }
+    }
+
+    pub fn with_intensity(self, intensity: T) -> Self {
+        Self {
+            intensity: Some(intensity),
+            ..self
+        }
+    }
+}
+
+impl<T: Scalar + RealField + Copy, const D: usize> PointCloudPoint<T, D> {
+    /// Returns the intensity of the point if set. Otherwise the luminance of the color is returned
+    /// (ITU-R BT.601 weights). Returns `None` if neither is set.
+    pub fn intensity_or_luminance(&self) -> Option<T> {
+        self.intensity.or_else(|| {
+            self.color.map(|c| {
+                c.x * nalgebra::convert(0.299)
+                    + c.y * nalgebra::convert(0.587)
+                    + c.z * nalgebra::convert(0.114)
+            })
+        })
+    }
 }
 
 impl<T: Scalar + Copy + One, const D: usize> From<Point<T, D>> for PointCloudPoint<T, D> {
@@ -33,6 +70,8 @@ impl<T: Scalar + Copy + One, const D: usize> From<Point<T, D>> for PointCloudPoi
             pos: p,
             norm: None,
             weight: T::one(),
+            color: None,
+            intensity: None,
         }
     }
 }
@@ -43,6 +82,8 @@ impl<T: Scalar + Copy + One, const D: usize> From<SVector<T, D>> for PointCloudP
             pos: Point::from(p),
             norm: None,
             weight: T::one(),
+            color: None,
+            intensity: None,
         }
     }
 }
@@ -0,0 +1,90 @@
+use crate::{PointCloud, PointCloudPoint};
+use nalgebra::{Isometry3, Point3, Vector3};
+
+/// Points on the three planes `x = 0`, `y = 0` and `z = 0` inside `[0, size]³` with their normals.
+/// Together they constrain all six degrees of freedom.
+pub fn room_corner(size: f32, spacing: f32) -> PointCloud<f32, 3> {
+    let mut cloud = plane_patch(size, spacing, |u, v| Point3::new(u, v, 0.0), Vector3::z());
+    cloud.extend(plane_patch(
+        size,
+        spacing,
+        |u, v| Point3::new(u, 0.0, v),
+        Vector3::y(),
+    ));
+    cloud.extend(plane_patch(
+        size,
+        spacing,
+        |u, v| Point3::new(0.0, u, v),
+        Vector3::x(),
+    ));
+    cloud
+}
+
+/// Points on the plane `z = 0` inside `[0, size]²` with their normals.
+pub fn floor(size: f32, spacing: f32) -> PointCloud<f32, 3> {
+    plane_patch(size, spacing, |u, v| Point3::new(u, v, 0.0), Vector3::z())
+}
+
+/// Points on the smooth surface `z = 0.5 sin(x) cos(y)` inside `[0, size]²` with their normals.
+pub fn wavy_surface(size: f32, spacing: f32) -> PointCloud<f32, 3> {
+    let count = (size / spacing) as usize + 1;
+
+    let mut cloud = PointCloud::with_capacity(count * count);
+    for i in 0..count {
+        for j in 0..count {
+            let (x, y) = (i as f32 * spacing, j as f32 * spacing);
+            let normal = Vector3::new(-0.5 * x.cos() * y.cos(), 0.5 * x.sin() * y.sin(), 1.0);
+
+            cloud.push(PointCloudPoint::from_pos_norm(
+                Point3::new(x, y, 0.5 * x.sin() * y.cos()),
+                normal.normalize(),
+            ));
+        }
+    }
+    cloud
+}
+
+/// Applies `transform` to the positions and normals of a copy of `cloud`.
+pub fn transformed(cloud: &PointCloud<f32, 3>, transform: &Isometry3<f32>) -> PointCloud<f32, 3> {
+    let mut cloud = cloud.clone();
+    crate::transform_point_cloud(&mut cloud, *transform);
+    cloud
+}
+
+/// Translation distance and rotation angle between two isometries.
+pub fn isometry_difference(a: &Isometry3<f32>, b: &Isometry3<f32>) -> (f32, f32) {
+    let difference = a.inverse() * b;
+    (
+        difference.translation.vector.norm(),
+        difference.rotation.angle(),
+    )
+}
+
+pub fn assert_isometry_eq(actual: &Isometry3<f32>, expected: &Isometry3<f32>, tolerance: f32) {
+    let (translation, angle) = isometry_difference(actual, expected);
+
+    assert!(
+        translation < tolerance && angle < tolerance,
+        "expected {expected:?} but got {actual:?} (translation off by {translation}, rotation off by {angle})"
+    );
+}
+
+fn plane_patch(
+    size: f32,
+    spacing: f32,
+    position: impl Fn(f32, f32) -> Point3<f32>,
+    normal: Vector3<f32>,
+) -> PointCloud<f32, 3> {
+    let count = (size / spacing) as usize + 1;
+
+    let mut cloud = PointCloud::with_capacity(count * count);
+    for i in 0..count {
+        for j in 0..count {
+            cloud.push(PointCloudPoint::from_pos_norm(
+                position(i as f32 * spacing, j as f32 * spacing),
+                normal,
+            ));
+        }
+    }
+    cloud
+}